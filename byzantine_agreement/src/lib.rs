@@ -0,0 +1,2335 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs::{self, File, OpenOptions},
+    io::{Read, Write},
+    net::IpAddr,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Condvar, Mutex,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use reqwest::blocking::Client;
+use serde::{Deserialize, Serialize};
+use hmac::{Hmac, Mac};
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use sha2::Sha256;
+use tiny_http::{Response, Server};
+use uuid::Uuid;
+use dc_common::{
+    args::{arg_value, cli_args},
+    http::json_header,
+    log::LogRotation,
+    topology::validate_topology,
+};
+
+type HttpResponse = Response<std::io::Cursor<Vec<u8>>>;
+
+const USAGE: &str = "\
+usage: byzantine_rust_sim [OPTIONS]
+       byzantine_rust_sim serve [OPTIONS] | coordinator [OPTIONS]
+       byzantine_rust_sim replay TRACE NODE-ID [OPTIONS]
+
+Runs every general in this process unless --id picks one. The protocol is
+OM(1): one forward phase, so /rounds reports rounds 0 and 1 only.
+
+Protocol:
+  --values A,B,...           orders a commander may give; it issues the first
+  --default-order VALUE      decided on a tie
+  --orders FILE|-            per-lieutenant orders as JSON
+  --forward-barrier MS|ready
+  --decide-timeout-ms MS     how long to wait for missing forwards
+  --traitor-sends ID=VALUE   what a traitor forwards to one peer
+  --omit-orders-to ID,...    lieutenants the commander sends nothing
+  --await-acks-ms MS         commander waits for every lieutenant's 200
+  --order-retries N          resend to lieutenants that didn't ack in time
+  --shuffle-send             send orders in a seeded random order
+  --seed N
+  --require-fault-bound      refuse to start when n < 3m + 1
+  --each-commander           run once per commander and print a table
+
+Cluster and network:
+  --topology FILE            generals and traitors from topology.toml
+  --id ID                    run only this node
+  --auth-secret SECRET       HMAC-sign orders and forwards
+  --max-body-bytes N
+  --rate-limit PER_SEC
+  --rate-burst N
+  --connect-timeout-ms MS
+  --request-timeout-ms MS
+
+Output:
+  --per-node-logs            node_<id>.log instead of byzantine.log
+  --max-log-bytes N
+  --log-backups N
+  --record                   append orders, forwards and decisions to byzantine_trace.jsonl
+  --junit PATH               write coordinator / --each-commander verdicts as JUnit XML
+  -h, --help
+";
+
+const AUTH_HEADER: &str = "X-Auth";
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Key shared by exactly the pair `a`, `b`, whichever direction they talk.
+fn pair_key(secret: &str, a: usize, b: usize) -> String {
+    format!("{}:{}:{}", secret, a.min(b), a.max(b))
+}
+
+fn sign_hmac(key: &str, body: &str) -> String {
+    let mut mac = HmacSha256::new_from_slice(key.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(body.as_bytes());
+    mac.finalize().into_bytes().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn verify_hmac(key: &str, body: &str, tag: &str) -> bool {
+    let Some(bytes) = (0..tag.len())
+        .step_by(2)
+        .map(|i| tag.get(i..i + 2).and_then(|h| u8::from_str_radix(h, 16).ok()))
+        .collect::<Option<Vec<u8>>>()
+    else {
+        return false;
+    };
+    let mut mac = HmacSha256::new_from_slice(key.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(body.as_bytes());
+    mac.verify_slice(&bytes).is_ok()
+}
+
+/// Per-source-IP token bucket: each IP may burst up to `burst` requests,
+/// refilled at `rate` per second.
+struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    buckets: HashMap<IpAddr, (f64, Instant)>,
+}
+
+impl RateLimiter {
+    fn new(rate: f64, burst: f64) -> Self {
+        RateLimiter { rate, burst, buckets: HashMap::new() }
+    }
+
+    fn allow(&mut self, ip: IpAddr, now: Instant) -> bool {
+        let (tokens, last) = self.buckets.entry(ip).or_insert((self.burst, now));
+        *tokens = (*tokens + now.duration_since(*last).as_secs_f64() * self.rate).min(self.burst);
+        *last = now;
+        if *tokens >= 1.0 {
+            *tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+const PEER_WAIT_TIMEOUT: Duration = Duration::from_secs(10);
+const NODES: [(usize, u16); 3] = [(0, 8000), (1, 8001), (2, 8002)];
+const BYZANTINE_NODES: [usize; 1] = [2];
+const COMMANDER: usize = 0;
+/// Extra attempts after a peer answers with a 5xx status.
+const SERVER_ERROR_RETRIES: u32 = 2;
+const SERVER_ERROR_BACKOFF: Duration = Duration::from_millis(100);
+const TRACE_FILE: &str = "byzantine_trace.jsonl";
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+struct OrderMsg {
+    from: usize,
+    order: String,
+    #[serde(default)]
+    trace_id: Uuid,
+}
+
+impl OrderMsg {
+    fn new(from: usize, order: String) -> Self {
+        OrderMsg { from, order, trace_id: Uuid::new_v4() }
+    }
+}
+
+fn now_ms() -> u128 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis()
+}
+
+/// Suffix for send/receive log lines, joinable on the trace id.
+fn trace_tag(trace_id: Uuid, at_ms: u128) -> String {
+    format!("trace={} at_ms={}", trace_id, at_ms)
+}
+
+/// One line of a `--record` trace. Replaying a node's messages up to its
+/// decision reproduces that decision.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum TraceEntry {
+    /// An `/order` or `/forward` body as `node` handled it, including the
+    /// node's own relay, which only ever lands in its local map.
+    Message { at_ms: u64, node: usize, path: String, body: String },
+    Decision { at_ms: u64, node: usize, decided: Option<String> },
+}
+
+fn load_trace(path: &str) -> Result<Vec<TraceEntry>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+    text.lines()
+        .filter(|l| !l.trim().is_empty())
+        .enumerate()
+        .map(|(i, l)| serde_json::from_str(l).map_err(|e| format!("{}:{}: {}", path, i + 1, e)))
+        .collect()
+}
+
+/// When a lieutenant starts forwarding the commander's order to its peers.
+#[derive(Clone, Copy, Debug)]
+enum ForwardBarrier {
+    /// Forward once this long has elapsed after receiving the order.
+    Delay(Duration),
+    /// Hold the order until the commander posts to `/ready`.
+    ReadySignal,
+}
+
+impl ForwardBarrier {
+    fn parse(s: &str) -> Option<Self> {
+        if s == "ready" {
+            return Some(ForwardBarrier::ReadySignal);
+        }
+        s.parse::<u64>().ok().map(|ms| ForwardBarrier::Delay(Duration::from_millis(ms)))
+    }
+}
+
+/// How a node relays the commander's order; switchable at runtime through
+/// `/behavior`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ByzantineBehavior {
+    Loyal,
+    /// Relay the next value after the real order to every peer.
+    FlipAll,
+    /// Relay the `--traitor-sends` value to listed peers, flip for the rest.
+    Targeted,
+}
+
+/// Body of a `/behavior` POST.
+#[derive(Deserialize)]
+struct BehaviorUpdate {
+    behavior: ByzantineBehavior,
+}
+
+struct Config {
+    forward_barrier: ForwardBarrier,
+    /// The orders a commander may give; the commander issues the first.
+    values: Vec<String>,
+    /// Decided on when no value wins an outright majority.
+    default_order: String,
+    /// Per-lieutenant orders for the commander, from `--orders FILE|-`.
+    orders: HashMap<usize, String>,
+    /// Upper bound on how long `decide` waits for missing forwards.
+    decide_timeout: Duration,
+    /// Shared secret for HMAC-signing orders and forwards.
+    auth_secret: Option<String>,
+    /// Larger request bodies are refused with 413.
+    max_body_bytes: usize,
+    /// Sustained requests per second allowed from one source IP; beyond
+    /// `rate_burst` of them, requests get 429.
+    rate_limit: f64,
+    rate_burst: f64,
+    /// Run only this node from `nodes` instead of the whole cluster.
+    id: Option<usize>,
+    /// Refuse to start, rather than warn, when n < 3m + 1.
+    require_fault_bound: bool,
+    /// What a traitor forwards to specific peers (`--traitor-sends ID=VALUE`);
+    /// any other peer gets the next value after the real order.
+    traitor_targets: HashMap<usize, String>,
+    /// Rotate `byzantine.log` once it reaches this size.
+    max_log_bytes: Option<u64>,
+    /// Rotated logs to keep, as `byzantine.log.1` (newest) onwards.
+    log_backups: usize,
+    /// Log each node to `node_<id>.log` instead of the shared `byzantine.log`.
+    per_node_logs: bool,
+    /// Bounds on each outbound HTTP call, so a peer that accepts but never
+    /// answers can't stall a sender past the decide timeout.
+    connect_timeout: Duration,
+    request_timeout: Duration,
+    /// Send the commander's orders to lieutenants in a seeded random order
+    /// instead of node list order.
+    shuffle_send: bool,
+    seed: Option<u64>,
+    /// Run the protocol once per node as commander, resetting in between,
+    /// and print whether the loyal lieutenants agreed each time.
+    each_commander: bool,
+    /// Have `commander_send` wait this long for every lieutenant's 200
+    /// before returning, instead of firing and forgetting.
+    ack_timeout: Option<Duration>,
+    /// With `ack_timeout`, how many times to resend the order to
+    /// lieutenants that haven't acked before giving up on them.
+    order_retries: u32,
+    /// Append every handled order and forward, and each decision, to
+    /// `byzantine_trace.jsonl`.
+    record: bool,
+    /// Lieutenants a commander sends no order to at all (`--omit-orders-to
+    /// 2,3`): an omission fault, as opposed to a flipped order.
+    omit_orders_to: HashSet<usize>,
+    /// `(id, port)` of every general; `NODES` unless `--topology` says
+    /// otherwise.
+    nodes: Vec<(usize, u16)>,
+    /// The general that gives the order; `COMMANDER` unless `--topology`
+    /// names another.
+    commander: usize,
+    /// The traitors and how each behaves; `BYZANTINE_NODES`, `Targeted`,
+    /// by default.
+    traitors: HashMap<usize, ByzantineBehavior>,
+    /// Also write the `coordinator` and `--each-commander` verdicts here as
+    /// JUnit XML, one testcase per invariant checked.
+    junit: Option<String>,
+}
+
+impl Config {
+    fn from_args() -> Self {
+        Self::from_args_after(1)
+    }
+
+    /// Parses flags starting at argument `skip`, past any subcommand.
+    fn from_args_after(skip: usize) -> Self {
+        let mut cfg = Config {
+            forward_barrier: ForwardBarrier::Delay(Duration::from_millis(200)),
+            values: vec!["ATTACK".to_string(), "RETREAT".to_string()],
+            default_order: "RETREAT".to_string(),
+            orders: HashMap::new(),
+            decide_timeout: Duration::from_secs(2),
+            auth_secret: None,
+            max_body_bytes: 64 * 1024,
+            rate_limit: 200.0,
+            rate_burst: 400.0,
+            id: None,
+            require_fault_bound: false,
+            traitor_targets: HashMap::new(),
+            max_log_bytes: None,
+            log_backups: 3,
+            per_node_logs: false,
+            connect_timeout: Duration::from_secs(2),
+            request_timeout: Duration::from_secs(2),
+            shuffle_send: false,
+            seed: None,
+            each_commander: false,
+            ack_timeout: None,
+            order_retries: 0,
+            record: false,
+            omit_orders_to: HashSet::new(),
+            nodes: NODES.to_vec(),
+            commander: COMMANDER,
+            traitors: BYZANTINE_NODES.iter().map(|id| (*id, ByzantineBehavior::Targeted)).collect(),
+            junit: None,
+        };
+        let mut args = cli_args().skip(skip);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-h" | "--help" => {
+                    print!("{}", USAGE);
+                    std::process::exit(0);
+                }
+                "--forward-barrier" => {
+                    let v = args.next().unwrap_or_default();
+                    cfg.forward_barrier = ForwardBarrier::parse(&v)
+                        .unwrap_or_else(|| panic!("--forward-barrier expects `ready` or milliseconds, got {:?}", v));
+                }
+                "--values" => {
+                    let v: String = arg_value(&mut args, &arg);
+                    cfg.values = v.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect();
+                    if cfg.values.is_empty() {
+                        panic!("--values needs at least one value");
+                    }
+                }
+                "--default-order" => cfg.default_order = arg_value(&mut args, &arg),
+                "--decide-timeout-ms" => cfg.decide_timeout = Duration::from_millis(arg_value(&mut args, &arg)),
+                "--max-log-bytes" => cfg.max_log_bytes = Some(arg_value(&mut args, &arg)),
+                "--log-backups" => cfg.log_backups = arg_value(&mut args, &arg),
+                "--per-node-logs" => cfg.per_node_logs = true,
+                "--connect-timeout-ms" => cfg.connect_timeout = Duration::from_millis(arg_value(&mut args, &arg)),
+                "--request-timeout-ms" => cfg.request_timeout = Duration::from_millis(arg_value(&mut args, &arg)),
+                "--traitor-sends" => {
+                    let v: String = arg_value(&mut args, &arg);
+                    let parsed = v.split_once('=').and_then(|(id, o)| Some((id.parse().ok()?, o.to_string())));
+                    let (id, o) = parsed.unwrap_or_else(|| panic!("--traitor-sends expects ID=VALUE, got {:?}", v));
+                    cfg.traitor_targets.insert(id, o);
+                }
+                "--require-fault-bound" => cfg.require_fault_bound = true,
+                "--shuffle-send" => cfg.shuffle_send = true,
+                "--each-commander" => cfg.each_commander = true,
+                "--record" => cfg.record = true,
+                "--omit-orders-to" => {
+                    let v: String = arg_value(&mut args, &arg);
+                    cfg.omit_orders_to = v
+                        .split(',')
+                        .map(|id| id.trim().parse().unwrap_or_else(|_| panic!("--omit-orders-to expects IDs, got {:?}", v)))
+                        .collect();
+                }
+                "--await-acks-ms" => cfg.ack_timeout = Some(Duration::from_millis(arg_value(&mut args, &arg))),
+                "--order-retries" => cfg.order_retries = arg_value(&mut args, &arg),
+                "--seed" => cfg.seed = Some(arg_value(&mut args, &arg)),
+                "--id" => cfg.id = Some(arg_value(&mut args, &arg)),
+                "--max-body-bytes" => cfg.max_body_bytes = arg_value(&mut args, &arg),
+                "--rate-limit" => cfg.rate_limit = arg_value(&mut args, &arg),
+                "--rate-burst" => cfg.rate_burst = arg_value(&mut args, &arg),
+                "--auth-secret" => cfg.auth_secret = Some(arg_value(&mut args, &arg)),
+                "--orders" => cfg.orders = load_orders(&arg_value::<String>(&mut args, &arg)),
+                "--junit" => cfg.junit = Some(arg_value(&mut args, &arg)),
+                "--topology" => cfg.apply_topology(&load_topology(&arg_value::<String>(&mut args, &arg))),
+                other => panic!("unknown argument: {}", other),
+            }
+        }
+        cfg
+    }
+
+    /// Applies a topology that `parse_topology` accepted.
+    fn apply_topology(&mut self, t: &Topology) {
+        self.nodes = t.nodes.iter().map(|n| (n.id, n.port)).collect();
+        self.commander = t.commander.or_else(|| t.nodes.iter().map(|n| n.id).min()).unwrap_or(COMMANDER);
+        self.traitors = t
+            .nodes
+            .iter()
+            .filter(|n| n.byzantine)
+            .map(|n| (n.id, n.behavior.unwrap_or(ByzantineBehavior::Targeted)))
+            .collect();
+    }
+
+    fn port_of(&self, id: usize) -> Option<u16> {
+        self.nodes.iter().find(|(nid, _)| *nid == id).map(|(_, p)| *p)
+    }
+
+    /// The traitors' ids, in order.
+    fn traitor_ids(&self) -> Vec<usize> {
+        let mut ids: Vec<usize> = self.traitors.keys().copied().collect();
+        ids.sort();
+        ids
+    }
+
+    fn behavior_of(&self, id: usize) -> ByzantineBehavior {
+        self.traitors.get(&id).copied().unwrap_or(ByzantineBehavior::Loyal)
+    }
+
+    fn http_client(&self) -> Client {
+        Client::builder()
+            .connect_timeout(self.connect_timeout)
+            .timeout(self.request_timeout)
+            .build()
+            .expect("failed to build HTTP client")
+    }
+}
+
+/// Parses an orders file: a JSON object mapping lieutenant id to order,
+/// e.g. `{"1": "ATTACK", "2": "RETREAT"}`.
+fn parse_orders(json: &str) -> Result<HashMap<usize, String>, String> {
+    serde_json::from_str(json).map_err(|e| format!("invalid orders: {}", e))
+}
+
+fn load_orders(source: &str) -> HashMap<usize, String> {
+    let mut json = String::new();
+    let read = if source == "-" {
+        std::io::stdin().read_to_string(&mut json).map(|_| ())
+    } else {
+        std::fs::read_to_string(source).map(|s| json = s)
+    };
+    read.unwrap_or_else(|e| panic!("cannot read orders from {}: {}", source, e));
+    parse_orders(&json).unwrap_or_else(|e| panic!("{}: {}", source, e))
+}
+
+/// `--topology FILE`: the generals from a `topology.toml` shared with the
+/// mutex binary, one `[[nodes]]` table each with `id`, `port` and, for a
+/// traitor, `byzantine = true` and an optional `behavior` (`targeted` if
+/// left out). A top-level `commander = ID` picks who gives the order; the
+/// lowest id does if it is left out. Keys meant for the other binary, such
+/// as `resources`, are ignored. Every general runs on this machine at
+/// 127.0.0.1.
+#[derive(Debug, Deserialize)]
+struct Topology {
+    commander: Option<usize>,
+    nodes: Vec<TopologyNode>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TopologyNode {
+    id: usize,
+    port: u16,
+    #[serde(default)]
+    byzantine: bool,
+    behavior: Option<ByzantineBehavior>,
+}
+
+/// Parses and checks a topology, listing every problem with it at once.
+fn parse_topology(text: &str) -> Result<Topology, String> {
+    let t: Topology = toml::from_str(text).map_err(|e| format!("invalid topology: {}", e))?;
+    let mut problems = vec![];
+    if t.nodes.is_empty() {
+        problems.push("node list is empty".to_string());
+    }
+    for n in &t.nodes {
+        match (n.byzantine, n.behavior) {
+            (true, Some(ByzantineBehavior::Loyal)) => problems.push(format!("node {} is byzantine but its behavior is loyal", n.id)),
+            (false, Some(b)) if b != ByzantineBehavior::Loyal => {
+                problems.push(format!("node {} has behavior {:?} but is not byzantine", n.id, b));
+            }
+            _ => {}
+        }
+    }
+    if let Some(c) = t.commander.filter(|c| !t.nodes.iter().any(|n| n.id == *c)) {
+        problems.push(format!("commander {} is not in the node list", c));
+    }
+    let nodes: Vec<(usize, u16)> = t.nodes.iter().map(|n| (n.id, n.port)).collect();
+    if let Err(e) = validate_topology(&nodes) {
+        problems.push(e);
+    }
+    if problems.is_empty() {
+        Ok(t)
+    } else {
+        Err(problems.join("; "))
+    }
+}
+
+fn load_topology(path: &str) -> Topology {
+    let text = std::fs::read_to_string(path).unwrap_or_else(|e| panic!("cannot read topology from {}: {}", path, e));
+    parse_topology(&text).unwrap_or_else(|e| panic!("{}: {}", path, e))
+}
+
+/// Every peer gets its entry from `overrides`, falling back to `fallback`.
+fn build_order_map(peers: &[(usize, u16)], overrides: &HashMap<usize, String>, fallback: &str) -> HashMap<usize, String> {
+    peers
+        .iter()
+        .map(|(nid, _)| (*nid, overrides.get(nid).cloned().unwrap_or_else(|| fallback.to_string())))
+        .collect()
+}
+
+/// What a traitor sends instead of `order`: the next value in `values`,
+/// wrapping around, so it always differs when there is more than one value.
+fn other_value(order: &str, values: &[String]) -> String {
+    match values.iter().position(|v| v == order) {
+        Some(i) => values[(i + 1) % values.len()].clone(),
+        None => values[0].clone(),
+    }
+}
+
+struct Node {
+    id: usize,
+    port: u16,
+    peers: Vec<(usize, u16)>,
+    /// Read by `forward_order` on every forward, so a POST to `/behavior`
+    /// turns the node traitor (or loyal) mid-run.
+    behavior: Arc<Mutex<ByzantineBehavior>>,
+    forward_barrier: ForwardBarrier,
+    values: Vec<String>,
+    default_order: String,
+    decide_timeout: Duration,
+    ack_timeout: Option<Duration>,
+    order_retries: u32,
+    auth_secret: Option<String>,
+    traitor_targets: HashMap<usize, String>,
+    max_body_bytes: usize,
+    rate_limiter: Arc<Mutex<RateLimiter>>,
+    commander_order: Arc<Mutex<Option<String>>>,
+    commander_id: Arc<Mutex<Option<usize>>>,
+    ready: Arc<Mutex<bool>>,
+    forwarded: Arc<Mutex<HashMap<usize, String>>>,
+    /// Signalled, under the `forwarded` lock, whenever an order or forward arrives.
+    forward_cv: Arc<Condvar>,
+    /// Peers that never answered `/ping` in `wait_for_peers`; `decide` does
+    /// not wait for their forwards.
+    unreachable: Arc<Mutex<HashSet<usize>>>,
+    client: Client,
+    log_file: Arc<Mutex<std::fs::File>>,
+    log_rotation: Option<LogRotation>,
+    decided: Arc<Mutex<Option<String>>>,
+    /// Interim majority after each round, as `/rounds` serves it. Only
+    /// OM(1) is implemented, so there are at most two: round 0 is the
+    /// commander's order alone, round 1 adds the forwarded values.
+    round_decisions: Arc<Mutex<Vec<String>>>,
+    /// Set while a `/decision` request's background decide is running.
+    deciding: Arc<AtomicBool>,
+    /// Set once `start_server` is listening, so `shutdown` can stop it.
+    server: Arc<Mutex<Option<Arc<Server>>>>,
+    /// With `--shuffle-send`, reorders the peers for every `commander_send`.
+    send_shuffle: Option<Arc<Mutex<StdRng>>>,
+    trace_file: Option<Arc<Mutex<File>>>,
+    /// Replay mode: handle messages but never forward to peers.
+    offline: bool,
+    omit_orders_to: HashSet<usize>,
+}
+
+impl Node {
+    fn new(
+        id: usize,
+        port: u16,
+        peers: Vec<(usize, u16)>,
+        behavior: ByzantineBehavior,
+        cfg: &Config,
+        log_file: Arc<Mutex<std::fs::File>>,
+    ) -> Self {
+        Node {
+            id,
+            port,
+            peers,
+            behavior: Arc::new(Mutex::new(behavior)),
+            forward_barrier: cfg.forward_barrier,
+            values: cfg.values.clone(),
+            default_order: cfg.default_order.clone(),
+            decide_timeout: cfg.decide_timeout,
+            ack_timeout: cfg.ack_timeout,
+            order_retries: cfg.order_retries,
+            auth_secret: cfg.auth_secret.clone(),
+            traitor_targets: cfg.traitor_targets.clone(),
+            max_body_bytes: cfg.max_body_bytes,
+            rate_limiter: Arc::new(Mutex::new(RateLimiter::new(cfg.rate_limit, cfg.rate_burst))),
+            commander_order: Arc::new(Mutex::new(None)),
+            commander_id: Arc::new(Mutex::new(None)),
+            ready: Arc::new(Mutex::new(false)),
+            forwarded: Arc::new(Mutex::new(HashMap::new())),
+            forward_cv: Arc::new(Condvar::new()),
+            unreachable: Arc::new(Mutex::new(HashSet::new())),
+            client: cfg.http_client(),
+            log_file,
+            log_rotation: None,
+            decided: Arc::new(Mutex::new(None)),
+            round_decisions: Arc::new(Mutex::new(Vec::new())),
+            deciding: Arc::new(AtomicBool::new(false)),
+            server: Arc::new(Mutex::new(None)),
+            send_shuffle: cfg
+                .shuffle_send
+                .then(|| Arc::new(Mutex::new(StdRng::seed_from_u64(cfg.seed.unwrap_or(0))))),
+            trace_file: None,
+            offline: false,
+            omit_orders_to: cfg.omit_orders_to.clone(),
+        }
+    }
+
+    fn record(&self, entry: TraceEntry) {
+        let Some(f) = &self.trace_file else { return };
+        let mut line = serde_json::to_string(&entry).unwrap();
+        line.push('\n');
+        if let Err(e) = f.lock().unwrap().write_all(line.as_bytes()) {
+            eprintln!("[Node {}] cannot write trace: {}", self.id, e);
+        }
+    }
+
+    fn record_message(&self, path: &str, body: &str) {
+        if self.trace_file.is_some() {
+            let (path, body) = (path.to_string(), body.to_string());
+            self.record(TraceEntry::Message { at_ms: now_ms() as u64, node: self.id, path, body });
+        }
+    }
+
+    fn log(&self, msg: &str) {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let line = dc_common::log::line(now, self.id, msg);
+        print!("{}", line);
+        dc_common::log::append(self.id, &self.log_file, self.log_rotation.as_ref(), &line);
+    }
+
+    fn start_server(&self) {
+        let port = self.port;
+        let node = self.clone();
+        thread::spawn(move || {
+            let addr = format!("0.0.0.0:{}", port);
+            let server = Arc::new(Server::http(&addr).expect("failed to start tiny-http server"));
+            *node.server.lock().unwrap() = Some(server.clone());
+            node.log(&format!("HTTP server listening on {}", addr));
+            for mut req in server.incoming_requests() {
+                let url = req.url().to_string();
+                let ip = req.remote_addr().map(|a| a.ip());
+                if let Some(resp) = node.admit(ip, req.body_length()) {
+                    let _ = req.respond(resp);
+                    continue;
+                }
+                // Bodies without a declared length are capped while reading.
+                let mut body = String::new();
+                let limit = node.max_body_bytes as u64 + 1;
+                let _ = req.as_reader().take(limit).read_to_string(&mut body);
+                if body.len() > node.max_body_bytes {
+                    let _ = req.respond(node.too_large(body.len()));
+                    continue;
+                }
+                let auth = req
+                    .headers()
+                    .iter()
+                    .find(|h| h.field.equiv(AUTH_HEADER))
+                    .map(|h| h.value.as_str().to_string());
+                if !node.authenticate(&url, &body, auth.as_deref()) {
+                    let _ = req.respond(Response::from_string("AUTH_FAIL").with_status_code(401));
+                    continue;
+                }
+                let _ = req.respond(node.handle(&url, &body));
+            }
+        });
+    }
+
+    /// Rate-limits by source IP and refuses declared-oversized bodies;
+    /// returns the rejection to send, if any.
+    fn admit(&self, ip: Option<IpAddr>, body_len: Option<usize>) -> Option<HttpResponse> {
+        if let Some(ip) = ip {
+            if !self.rate_limiter.lock().unwrap().allow(ip, Instant::now()) {
+                self.log(&format!("Rate limit exceeded for {}", ip));
+                return Some(Response::from_string("RATE_LIMITED").with_status_code(429));
+            }
+        }
+        match body_len {
+            Some(len) if len > self.max_body_bytes => Some(self.too_large(len)),
+            _ => None,
+        }
+    }
+
+    fn too_large(&self, len: usize) -> HttpResponse {
+        self.log(&format!("Rejected {}-byte body (max {})", len, self.max_body_bytes));
+        Response::from_string("PAYLOAD_TOO_LARGE").with_status_code(413)
+    }
+
+    /// With a shared secret configured, `/order` and `/forward` must carry an
+    /// HMAC of the body under the key shared by the claimed sender and us.
+    fn authenticate(&self, url: &str, body: &str, auth: Option<&str>) -> bool {
+        let Some(secret) = &self.auth_secret else { return true };
+        if url != "/order" && url != "/forward" {
+            return true;
+        }
+        let from = serde_json::from_str::<OrderMsg>(body).map(|m| m.from).ok();
+        let ok = match (from, auth) {
+            (Some(from), Some(tag)) => verify_hmac(&pair_key(secret, from, self.id), body, tag),
+            _ => false,
+        };
+        if !ok {
+            self.log(&format!("AUTH_FAIL on {} claiming sender {:?}", url, from));
+        }
+        ok
+    }
+
+    /// POSTs `payload` to peer `to`. A non-2xx answer is a failed delivery;
+    /// a 5xx is retried, with a linear backoff, since the peer may recover.
+    fn post_signed(&self, url: &str, to: usize, payload: String) -> Result<(), String> {
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let mut req = self.client.post(url);
+            if let Some(secret) = &self.auth_secret {
+                req = req.header(AUTH_HEADER, sign_hmac(&pair_key(secret, self.id, to), &payload));
+            }
+            let status = req.body(payload.clone()).send().map_err(|e| e.to_string())?.status();
+            if status.is_success() {
+                return Ok(());
+            }
+            if !status.is_server_error() || attempt > SERVER_ERROR_RETRIES {
+                return Err(format!("HTTP {} after {} attempt(s)", status, attempt));
+            }
+            self.log(&format!("HTTP {} from {} on attempt {}; retrying", status, to, attempt));
+            thread::sleep(SERVER_ERROR_BACKOFF * attempt);
+        }
+    }
+
+    fn handle(&self, url: &str, body: &str) -> HttpResponse {
+        match url {
+            "/ping" => return Response::from_string("pong"),
+            "/order" => {
+                if let Ok(msg) = serde_json::from_str::<OrderMsg>(body) {
+                    self.record_message(url, body);
+                    self.receive_order(msg);
+                } else {
+                    self.log(&format!("Bad /order payload: {}", body));
+                }
+            }
+            "/forward" => {
+                if let Ok(msg) = serde_json::from_str::<OrderMsg>(body) {
+                    self.record_message(url, body);
+                    self.receive_forward(msg);
+                } else {
+                    self.log(&format!("Bad /forward payload: {}", body));
+                }
+            }
+            "/ready" => self.receive_ready(),
+            "/reset" => self.reset(),
+            "/start" => {
+                let overrides = if body.trim().is_empty() {
+                    HashMap::new()
+                } else {
+                    match parse_orders(body) {
+                        Ok(o) => o,
+                        Err(e) => {
+                            self.log(&format!("Bad /start payload: {}", e));
+                            return Response::from_string(e).with_status_code(400);
+                        }
+                    }
+                };
+                let node = self.clone();
+                thread::spawn(move || node.start_as_commander(&overrides));
+            }
+            "/forwarded" => {
+                let forwarded = self.forwarded.lock().unwrap().clone();
+                return Response::from_string(serde_json::to_string(&forwarded).unwrap()).with_header(json_header());
+            }
+            "/rounds" => {
+                let rounds = self.round_decisions.lock().unwrap().clone();
+                return Response::from_string(serde_json::to_string(&rounds).unwrap()).with_header(json_header());
+            }
+            "/behavior" => {
+                if !body.trim().is_empty() {
+                    match serde_json::from_str::<BehaviorUpdate>(body) {
+                        Ok(u) => self.set_behavior(u.behavior),
+                        Err(e) => {
+                            self.log(&format!("Bad /behavior payload: {}", body));
+                            return Response::from_string(format!("malformed behavior update: {}", e)).with_status_code(400);
+                        }
+                    }
+                }
+                let body = serde_json::json!({ "id": self.id, "behavior": self.current_behavior() });
+                return Response::from_string(body.to_string()).with_header(json_header());
+            }
+            "/decision" => {
+                // Deciding may wait out `decide_timeout`, so it never runs on
+                // the server thread: until it's done, the answer is a 202.
+                let decided = self.decided.try_lock().ok().and_then(|d| d.clone());
+                if decided.is_none() {
+                    self.decide_in_background();
+                }
+                let body = serde_json::json!({ "id": self.id, "byzantine": self.is_byzantine(), "decided": decided });
+                let status = if decided.is_some() { 200 } else { 202 };
+                return Response::from_string(body.to_string()).with_header(json_header()).with_status_code(status);
+            }
+            _ => {}
+        }
+        Response::from_string("OK")
+    }
+
+    fn wait_for_peers(&self, timeout: Duration) -> bool {
+        let up = |port| {
+            let url = format!("http://127.0.0.1:{}/ping", port);
+            matches!(self.client.get(&url).send(), Ok(r) if r.status().is_success())
+        };
+        if let Err(ids) = dc_common::http::wait_for_peers(&self.peers, timeout, up) {
+            self.log(&format!("Timed out waiting for peers {:?}", ids));
+            self.unreachable.lock().unwrap().extend(ids);
+            return false;
+        }
+        self.log("All peers reachable");
+        true
+    }
+
+    /// The peers in the order `commander_send` contacts them.
+    fn send_order(&self) -> Vec<(usize, u16)> {
+        let mut peers = self.peers.clone();
+        if let Some(rng) = &self.send_shuffle {
+            peers.shuffle(&mut *rng.lock().unwrap());
+            let ids: Vec<usize> = peers.iter().map(|(nid, _)| *nid).collect();
+            self.log(&format!("Sending ORDER in shuffled order {:?}", ids));
+        }
+        peers
+    }
+
+    /// Sends every lieutenant its order in parallel. With an ack timeout,
+    /// waits for each one's 200, resends to the silent ones up to
+    /// `--order-retries` times, and returns the lieutenants that never
+    /// answered, sorted; otherwise returns at once with none. A lieutenant
+    /// whose ack was merely late may get the order twice.
+    fn commander_send(&self, order_map: &HashMap<usize, String>) -> Vec<usize> {
+        let (omitted, peers): (Vec<_>, Vec<_>) = self.send_order().into_iter().partition(|(nid, _)| self.omit_orders_to.contains(nid));
+        for (nid, _) in &omitted {
+            self.log(&format!("OMIT ORDER to {}: it must decide from forwards alone", nid));
+        }
+        let Some(timeout) = self.ack_timeout else {
+            self.send_orders(&peers, order_map);
+            return vec![];
+        };
+        let mut missing = peers.clone();
+        for attempt in 0..=self.order_retries {
+            if attempt > 0 {
+                let ids: Vec<usize> = missing.iter().map(|(nid, _)| *nid).collect();
+                self.log(&format!("RESEND ORDER to {:?} (retry {} of {})", ids, attempt, self.order_retries));
+            }
+            let acked = self.send_orders(&missing, order_map);
+            let deadline = Instant::now() + timeout;
+            let mut waiting: HashSet<usize> = missing.iter().map(|(nid, _)| *nid).collect();
+            while !waiting.is_empty() {
+                let now = Instant::now();
+                if now >= deadline {
+                    break;
+                }
+                match acked.recv_timeout(deadline - now) {
+                    Ok((nid, true)) => {
+                        waiting.remove(&nid);
+                    }
+                    Ok((_, false)) => {}
+                    Err(_) => break,
+                }
+            }
+            missing.retain(|(nid, _)| waiting.contains(nid));
+            if missing.is_empty() {
+                break;
+            }
+            // A refused connection fails at once; give a lieutenant that is
+            // still starting the whole window before trying it again.
+            if attempt < self.order_retries {
+                thread::sleep(deadline.saturating_duration_since(Instant::now()));
+            }
+        }
+        let mut missing: Vec<usize> = missing.into_iter().map(|(nid, _)| nid).collect();
+        missing.sort();
+        if missing.is_empty() {
+            self.log(&format!("ORDER acked by all {} lieutenant(s)", peers.len()));
+        } else {
+            self.log(&format!(
+                "No ORDER ack from {:?} within {:?} over {} attempt(s); unreachable",
+                missing,
+                timeout,
+                self.order_retries + 1
+            ));
+        }
+        missing
+    }
+
+    /// POSTs each of `peers` its order on its own thread. The receiver
+    /// yields `(lieutenant, acked)` as each send finishes.
+    fn send_orders(&self, peers: &[(usize, u16)], order_map: &HashMap<usize, String>) -> mpsc::Receiver<(usize, bool)> {
+        let (acks, acked) = mpsc::channel();
+        for (nid, port) in peers {
+            let order = order_map.get(nid).cloned().unwrap_or_else(|| self.default_order.clone());
+            let url = format!("http://127.0.0.1:{}/order", port);
+            let msg = OrderMsg::new(self.id, order);
+            let payload = serde_json::to_string(&msg).unwrap();
+            let nidv = *nid;
+            let node = self.clone();
+            let acks = acks.clone();
+            thread::spawn(move || {
+                let sent_at = now_ms();
+                let ok = match node.post_signed(&url, nidv, payload) {
+                    Err(e) => {
+                        node.log(&format!("Error sending ORDER to {}: {}", nidv, e));
+                        false
+                    }
+                    Ok(()) => {
+                        node.log(&format!("Sent ORDER to {} (via /order) {}", nidv, trace_tag(msg.trace_id, sent_at)));
+                        true
+                    }
+                };
+                let _ = acks.send((nidv, ok));
+            });
+        }
+        // Ours drops here, so `recv_timeout` stops early once every send is done.
+        acked
+    }
+
+    fn receive_order(&self, msg: OrderMsg) {
+        self.log(&format!(
+            "Received ORDER from commander {}: {} {}",
+            msg.from,
+            msg.order,
+            trace_tag(msg.trace_id, now_ms())
+        ));
+        {
+            let mut c = self.commander_order.lock().unwrap();
+            *c = Some(msg.order.clone());
+        }
+        *self.commander_id.lock().unwrap() = Some(msg.from);
+        self.record_round(msg.order.clone());
+        {
+            let _f = self.forwarded.lock().unwrap();
+            self.forward_cv.notify_all();
+        }
+        // A replayed trace carries this node's own relay as a message.
+        if self.offline {
+            return;
+        }
+        match self.forward_barrier {
+            ForwardBarrier::Delay(d) => {
+                let node = self.clone();
+                thread::spawn(move || {
+                    thread::sleep(d);
+                    node.forward_order(msg.order);
+                });
+            }
+            ForwardBarrier::ReadySignal => {
+                if *self.ready.lock().unwrap() {
+                    self.forward_order(msg.order);
+                }
+            }
+        }
+    }
+
+    /// If the commander's order hasn't arrived yet, `receive_order` forwards it on arrival.
+    fn receive_ready(&self) {
+        self.log("Received READY from commander");
+        *self.ready.lock().unwrap() = true;
+        let order = { self.commander_order.lock().unwrap().clone() };
+        if let Some(order) = order {
+            self.forward_order(order);
+        }
+    }
+
+    fn signal_ready(&self) {
+        for (nid, port) in &self.peers {
+            let url = format!("http://127.0.0.1:{}/ready", port);
+            if let Err(e) = self.client.post(&url).send() {
+                self.log(&format!("Error sending READY to {}: {}", nid, e));
+            }
+        }
+    }
+
+    /// Forgets everything about the last run, keeping peers, behavior and
+    /// which peers were unreachable, so the next `/start` begins fresh.
+    fn reset(&self) {
+        *self.commander_order.lock().unwrap() = None;
+        *self.commander_id.lock().unwrap() = None;
+        *self.ready.lock().unwrap() = false;
+        self.forwarded.lock().unwrap().clear();
+        *self.decided.lock().unwrap() = None;
+        self.round_decisions.lock().unwrap().clear();
+        self.log("RESET for a new run");
+    }
+
+    fn current_behavior(&self) -> ByzantineBehavior {
+        *self.behavior.lock().unwrap()
+    }
+
+    fn is_byzantine(&self) -> bool {
+        self.current_behavior() != ByzantineBehavior::Loyal
+    }
+
+    fn set_behavior(&self, behavior: ByzantineBehavior) {
+        let old = std::mem::replace(&mut *self.behavior.lock().unwrap(), behavior);
+        self.log(&format!("BEHAVIOR {:?} -> {:?}", old, behavior));
+    }
+
+    /// What this traitor tells `peer` the commander said.
+    fn traitor_value_for(&self, peer: usize, order: &str) -> String {
+        self.traitor_targets.get(&peer).cloned().unwrap_or_else(|| other_value(order, &self.values))
+    }
+
+    /// What a node behaving as `behavior` relays to `peer`.
+    fn relayed_value(&self, behavior: ByzantineBehavior, peer: usize, order: &str) -> String {
+        match behavior {
+            ByzantineBehavior::Loyal => order.to_string(),
+            ByzantineBehavior::FlipAll => other_value(order, &self.values),
+            ByzantineBehavior::Targeted => self.traitor_value_for(peer, order),
+        }
+    }
+
+    fn forward_order(&self, order: String) {
+        // One snapshot per forward, so every peer hears the same behavior.
+        let behavior = self.current_behavior();
+        let to_send = match behavior {
+            ByzantineBehavior::Loyal => order.clone(),
+            _ => other_value(&order, &self.values),
+        };
+        {
+            let mut f = self.forwarded.lock().unwrap();
+            if f.contains_key(&self.id) {
+                return;
+            }
+            f.insert(self.id, to_send.clone());
+        }
+        self.record_message("/forward", &serde_json::to_string(&OrderMsg::new(self.id, to_send.clone())).unwrap());
+        for (nid, port) in &self.peers {
+            if *nid == self.id { continue; }
+            let url = format!("http://127.0.0.1:{}/forward", port);
+            let value = self.relayed_value(behavior, *nid, &order);
+            let msg = OrderMsg::new(self.id, value);
+            let payload = serde_json::to_string(&msg).unwrap();
+            let node = self.clone();
+            let nidv = *nid;
+            thread::spawn(move || {
+                let sent_at = now_ms();
+                if let Err(e) = node.post_signed(&url, nidv, payload) {
+                    node.log(&format!("Error forwarding to {}: {}", nidv, e));
+                } else {
+                    node.log(&format!("Forwarded order to {} via /forward {}", nidv, trace_tag(msg.trace_id, sent_at)));
+                }
+            });
+        }
+    }
+
+    fn receive_forward(&self, msg: OrderMsg) {
+        self.log(&format!("Received FORWARD from {}: {} {}", msg.from, msg.order, trace_tag(msg.trace_id, now_ms())));
+        {
+            let mut f = self.forwarded.lock().unwrap();
+            f.insert(msg.from, msg.order.clone());
+            self.forward_cv.notify_all();
+        }
+    }
+
+    /// Every reachable peer except the commander is expected to forward to us.
+    fn expected_forwarders(&self) -> Vec<usize> {
+        let commander = *self.commander_id.lock().unwrap();
+        let unreachable = self.unreachable.lock().unwrap();
+        self.peers
+            .iter()
+            .map(|(nid, _)| *nid)
+            .filter(|nid| Some(*nid) != commander && !unreachable.contains(nid))
+            .collect()
+    }
+
+    /// Sends each lieutenant its order from `overrides`, or the first
+    /// configured value, and in ready-signal mode releases the forward phase
+    /// afterwards.
+    fn start_as_commander(&self, overrides: &HashMap<usize, String>) {
+        let order_map = build_order_map(&self.peers, overrides, &self.values[0]);
+        self.commander_send(&order_map);
+        if let ForwardBarrier::ReadySignal = self.forward_barrier {
+            // Acks already mean every order landed; otherwise give them time.
+            if self.ack_timeout.is_none() {
+                thread::sleep(Duration::from_millis(300));
+            }
+            self.signal_ready();
+        }
+    }
+
+    /// Decides at most once; concurrent callers share the first result.
+    fn decide_once(&self) -> Option<String> {
+        let mut decided = self.decided.lock().unwrap();
+        if let Some(d) = decided.clone() {
+            return Some(d);
+        }
+        let dec = self.decide();
+        self.record(TraceEntry::Decision { at_ms: now_ms() as u64, node: self.id, decided: dec.clone() });
+        match &dec {
+            Some(v) => {
+                self.log(&format!("FINAL DECISION = {}", v));
+                *decided = Some(v.clone());
+            }
+            None => self.log("FINAL DECISION = None"),
+        }
+        dec
+    }
+
+    /// Starts `decide_once` on its own thread unless one is already deciding.
+    fn decide_in_background(&self) {
+        if self.deciding.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let node = self.clone();
+        thread::spawn(move || {
+            node.decide_once();
+            node.deciding.store(false, Ordering::SeqCst);
+        });
+    }
+
+    /// Waits until the commander's order and a forward from every expected
+    /// peer have arrived, or `decide_timeout` elapses, then takes the OM(1)
+    /// majority over whatever reports did arrive. On timeout the peers
+    /// still missing are logged.
+    fn decide(&self) -> Option<String> {
+        let deadline = Instant::now() + self.decide_timeout;
+        let mut f = self.forwarded.lock().unwrap();
+        loop {
+            let have_order = self.commander_order.lock().unwrap().is_some();
+            let missing: Vec<usize> = self.expected_forwarders().into_iter().filter(|nid| !f.contains_key(nid)).collect();
+            // Without an order we can't tell who commands, but the commander
+            // is the only peer that never forwards, so one report short is
+            // every report there will be.
+            let complete = if have_order { missing.is_empty() } else { missing.len() <= 1 };
+            if complete {
+                break;
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                let received = f.len() + usize::from(have_order);
+                self.log(&format!("Deciding on the {} report(s) received; none from {:?}", received, missing));
+                break;
+            }
+            f = self.forward_cv.wait_timeout(f, deadline - now).unwrap().0;
+        }
+        let forwarded_map = f.clone();
+        drop(f);
+        let commander_opt = { self.commander_order.lock().unwrap().clone() };
+
+        let decision = match commander_opt {
+            Some(order) => majority(&order, &forwarded_map, &self.default_order),
+            None if forwarded_map.is_empty() => {
+                self.log("No commander order received yet; cannot decide");
+                return None;
+            }
+            None => {
+                self.log(&format!("No commander order; deciding from {} forward(s) alone", forwarded_map.len()));
+                tally(forwarded_map.iter().map(|(id, v)| (Voter::Node(*id), v.as_str())), &self.default_order)
+            }
+        };
+        self.log(&format!("TALLY {}", decision.explain()));
+        self.record_round(decision.winner.clone());
+        Some(decision.winner)
+    }
+
+    fn record_round(&self, value: String) {
+        let round = {
+            let mut rounds = self.round_decisions.lock().unwrap();
+            rounds.push(value.clone());
+            rounds.len() - 1
+        };
+        self.log(&format!("Round {} interim majority = {}", round, value));
+    }
+}
+
+/// Oral messages only guarantee agreement with `m` traitors among `n`
+/// generals when n >= 3m + 1.
+fn tolerates_faults(n: usize, m: usize) -> bool {
+    n > 3 * m
+}
+
+/// Warns, or exits with `--require-fault-bound`, when the cluster is too
+/// small for its traitors.
+fn check_fault_bound(cfg: &Config) {
+    let (n, m) = (cfg.nodes.len(), cfg.traitors.len());
+    if tolerates_faults(n, m) {
+        return;
+    }
+    println!("==================================================================");
+    println!("WARNING: {} nodes with {} traitor(s) violates n >= 3m+1 (needs {}).", n, m, 3 * m + 1);
+    println!("Loyal lieutenants are NOT guaranteed to agree.");
+    println!("==================================================================");
+    if cfg.require_fault_bound {
+        std::process::exit(2);
+    }
+}
+
+/// Who cast a vote in a tally: the commander's own order, or a
+/// lieutenant's forwarded copy of it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Voter {
+    Commander,
+    Node(usize),
+}
+
+impl std::fmt::Display for Voter {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Voter::Commander => write!(f, "commander"),
+            Voter::Node(id) => write!(f, "node {}", id),
+        }
+    }
+}
+
+/// A decided value and how it was reached.
+#[derive(Debug, PartialEq)]
+struct Tally {
+    winner: String,
+    /// Every value voted for, with its voters in order.
+    votes: BTreeMap<String, Vec<Voter>>,
+    /// No single value led, so `winner` is the default.
+    tied: bool,
+}
+
+impl Tally {
+    /// One line for the log, e.g. `ATTACK 3 (commander, node 1, node 2),
+    /// RETREAT 1 (node 3): ATTACK has the most votes`.
+    fn explain(&self) -> String {
+        let votes: Vec<String> = self
+            .votes
+            .iter()
+            .map(|(value, voters)| {
+                let names: Vec<String> = voters.iter().map(Voter::to_string).collect();
+                format!("{} {} ({})", value, voters.len(), names.join(", "))
+            })
+            .collect();
+        let why = if self.votes.is_empty() {
+            format!("no votes, default {}", self.winner)
+        } else if self.tied {
+            format!("tie for the most votes, default {}", self.winner)
+        } else {
+            format!("{} has the most votes", self.winner)
+        };
+        format!("{}: {}", votes.join(", "), why)
+    }
+}
+
+/// Majority over the commander's order and every forwarded copy. A tie for
+/// the top count falls back to `default`.
+fn majority(commander: &str, forwarded: &HashMap<usize, String>, default: &str) -> Tally {
+    let forwards = forwarded.iter().map(|(id, v)| (Voter::Node(*id), v.as_str()));
+    tally(std::iter::once((Voter::Commander, commander)).chain(forwards), default)
+}
+
+/// The value with the most votes, or `default` on a tie.
+fn tally<'a>(votes: impl Iterator<Item = (Voter, &'a str)>, default: &str) -> Tally {
+    let mut by_value: BTreeMap<String, Vec<Voter>> = BTreeMap::new();
+    for (voter, value) in votes {
+        by_value.entry(value.to_string()).or_default().push(voter);
+    }
+    by_value.values_mut().for_each(|voters| voters.sort());
+    let top = by_value.values().map(Vec::len).max().unwrap_or(0);
+    let leaders: Vec<&String> = by_value.iter().filter(|(_, voters)| voters.len() == top).map(|(v, _)| v).collect();
+    let tied = leaders.len() != 1;
+    let winner = if tied { default.to_string() } else { leaders[0].clone() };
+    Tally { winner, votes: by_value, tied }
+}
+
+impl Clone for Node {
+    fn clone(&self) -> Self {
+        Node {
+            id: self.id,
+            port: self.port,
+            peers: self.peers.clone(),
+            behavior: Arc::clone(&self.behavior),
+            forward_barrier: self.forward_barrier,
+            values: self.values.clone(),
+            default_order: self.default_order.clone(),
+            decide_timeout: self.decide_timeout,
+            ack_timeout: self.ack_timeout,
+            order_retries: self.order_retries,
+            auth_secret: self.auth_secret.clone(),
+            traitor_targets: self.traitor_targets.clone(),
+            max_body_bytes: self.max_body_bytes,
+            rate_limiter: Arc::clone(&self.rate_limiter),
+            commander_order: Arc::clone(&self.commander_order),
+            commander_id: Arc::clone(&self.commander_id),
+            ready: Arc::clone(&self.ready),
+            forwarded: Arc::clone(&self.forwarded),
+            forward_cv: Arc::clone(&self.forward_cv),
+            unreachable: Arc::clone(&self.unreachable),
+            client: self.client.clone(),
+            log_file: Arc::clone(&self.log_file),
+            log_rotation: self.log_rotation.clone(),
+            decided: Arc::clone(&self.decided),
+            round_decisions: Arc::clone(&self.round_decisions),
+            deciding: Arc::clone(&self.deciding),
+            server: Arc::clone(&self.server),
+            send_shuffle: self.send_shuffle.clone(),
+            trace_file: self.trace_file.clone(),
+            offline: self.offline,
+            omit_orders_to: self.omit_orders_to.clone(),
+        }
+    }
+}
+
+fn start_cluster(cfg: &Config) -> HashMap<usize, Node> {
+    if let Err(e) = validate_topology(&cfg.nodes) {
+        eprintln!("invalid topology: {}", e);
+        std::process::exit(2);
+    }
+    check_fault_bound(cfg);
+    let open_log = |path: &PathBuf| Arc::new(Mutex::new(OpenOptions::new().create(true).append(true).open(path).unwrap()));
+    let shared_log = (!cfg.per_node_logs).then(|| open_log(&PathBuf::from("byzantine.log")));
+    let trace_file = cfg.record.then(|| open_log(&PathBuf::from(TRACE_FILE)));
+
+    let mut node_objs: HashMap<usize, Node> = HashMap::new();
+    for (id, port) in cfg.nodes.iter().filter(|(id, _)| cfg.id.is_none_or(|want| *id == want)) {
+        let peers = cfg.nodes.iter().filter(|(nid, _)| nid != id).cloned().collect::<Vec<_>>();
+        let behavior = cfg.behavior_of(*id);
+        let path = PathBuf::from(if cfg.per_node_logs { format!("node_{}.log", id) } else { "byzantine.log".to_string() });
+        let log_file = shared_log.clone().unwrap_or_else(|| open_log(&path));
+        let mut n = Node::new(*id, *port, peers, behavior, cfg, log_file);
+        n.trace_file = trace_file.clone();
+        n.log_rotation = cfg.max_log_bytes.map(|max_bytes| LogRotation { path, max_bytes, backups: cfg.log_backups });
+        n.start_server();
+        node_objs.insert(*id, n);
+    }
+    for n in node_objs.values() {
+        n.wait_for_peers(PEER_WAIT_TIMEOUT);
+    }
+    node_objs
+}
+
+/// Whether the loyal lieutenants all decided the same value, which is what
+/// the protocol exists to achieve.
+#[derive(Debug, PartialEq)]
+enum Verdict {
+    Agreement(String),
+    /// Every loyal lieutenant's decision, `None` if it never decided.
+    Disagreement(BTreeMap<usize, Option<String>>),
+}
+
+impl Verdict {
+    fn failure(&self) -> Option<String> {
+        match self {
+            Verdict::Agreement(_) => None,
+            Verdict::Disagreement(loyal) => Some(format!("loyal lieutenants decided {:?}", loyal)),
+        }
+    }
+}
+
+/// Judges `decisions` by lieutenant, ignoring the nodes listed as traitors.
+fn check_agreement(decisions: &HashMap<usize, Option<String>>, traitors: &[usize]) -> Verdict {
+    let loyal: BTreeMap<usize, Option<String>> =
+        decisions.iter().filter(|(id, _)| !traitors.contains(id)).map(|(id, d)| (*id, d.clone())).collect();
+    let mut values = loyal.values();
+    match values.next() {
+        Some(Some(v)) if values.all(|d| d.as_deref() == Some(v.as_str())) => Verdict::Agreement(v.clone()),
+        _ => Verdict::Disagreement(loyal),
+    }
+}
+
+/// One `--junit` testcase: an invariant and, if it was broken, how.
+struct JunitCase {
+    name: String,
+    failure: Option<String>,
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;").replace('\'', "&apos;")
+}
+
+fn junit_xml(suite: &str, cases: &[JunitCase]) -> String {
+    let failures = cases.iter().filter(|c| c.failure.is_some()).count();
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml += &format!("<testsuite name=\"{}\" tests=\"{}\" failures=\"{}\">\n", xml_escape(suite), cases.len(), failures);
+    for c in cases {
+        let open = format!("  <testcase classname=\"{}\" name=\"{}\"", xml_escape(suite), xml_escape(&c.name));
+        match &c.failure {
+            None => xml += &format!("{}/>\n", open),
+            Some(msg) => xml += &format!("{}>\n    <failure message=\"{}\"/>\n  </testcase>\n", open, xml_escape(msg)),
+        }
+    }
+    xml + "</testsuite>\n"
+}
+
+fn write_junit(path: &str, suite: &str, cases: &[JunitCase]) {
+    if let Err(e) = fs::write(path, junit_xml(suite, cases)) {
+        eprintln!("cannot write {}: {}", path, e);
+    }
+}
+
+/// `coordinator`'s testcases: every lieutenant answered with a decision,
+/// and the loyal ones agreed.
+fn coordinator_cases(decisions: &HashMap<usize, Option<String>>, verdict: &Verdict) -> Vec<JunitCase> {
+    let mut undecided: Vec<usize> = decisions.iter().filter(|(_, d)| d.is_none()).map(|(id, _)| *id).collect();
+    undecided.sort();
+    vec![
+        JunitCase {
+            name: "all lieutenants decided".to_string(),
+            failure: (!undecided.is_empty()).then(|| format!("no decision from {:?}", undecided)),
+        },
+        JunitCase { name: "byzantine agreement reached".to_string(), failure: verdict.failure() },
+    ]
+}
+
+/// Polls a node's `/decision` until it has decided or `patience` runs out;
+/// the node answers 202 while it is still deciding.
+fn fetch_decision(client: &Client, url: &str, patience: Duration) -> Result<Option<String>, String> {
+    let deadline = Instant::now() + patience;
+    loop {
+        let resp = client.get(url).send().map_err(|e| e.to_string())?;
+        let pending = resp.status().as_u16() == 202;
+        let v: serde_json::Value = serde_json::from_str(&resp.text().map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+        if !pending || Instant::now() >= deadline {
+            return Ok(v["decided"].as_str().map(String::from));
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+/// Drives a cluster started with `serve` over HTTP and reports whether the
+/// loyal lieutenants agreed. Returns the process exit code.
+fn run_coordinator(cfg: &Config) -> i32 {
+    let client = cfg.http_client();
+    let Some(port) = cfg.port_of(cfg.commander) else {
+        println!("Coordinator: commander {} is not in the node list", cfg.commander);
+        return 2;
+    };
+    let start_url = format!("http://127.0.0.1:{}/start", port);
+    if let Err(e) = client.post(&start_url).send() {
+        println!("Coordinator: cannot reach commander {}: {}", cfg.commander, e);
+        return 2;
+    }
+    println!("Coordinator: started commander {}", cfg.commander);
+    thread::sleep(Duration::from_millis(1500));
+
+    let mut decisions: HashMap<usize, Option<String>> = HashMap::new();
+    for (id, port) in cfg.nodes.iter().filter(|(id, _)| *id != cfg.commander) {
+        let url = format!("http://127.0.0.1:{}/decision", port);
+        match fetch_decision(&client, &url, cfg.decide_timeout + Duration::from_secs(1)) {
+            Ok(decided) => {
+                println!("Coordinator: node {} decided {:?}", id, decided);
+                decisions.insert(*id, decided);
+            }
+            Err(e) => {
+                println!("Coordinator: no decision from node {}: {}", id, e);
+                decisions.insert(*id, None);
+            }
+        }
+    }
+    let verdict = check_agreement(&decisions, &cfg.traitor_ids());
+    if let Some(path) = &cfg.junit {
+        write_junit(path, "byzantine", &coordinator_cases(&decisions, &verdict));
+    }
+    match verdict {
+        Verdict::Agreement(v) => {
+            println!("AGREEMENT REACHED on {}", v);
+            0
+        }
+        Verdict::Disagreement(loyal) => {
+            println!("AGREEMENT FAILED: {:?}", loyal);
+            1
+        }
+    }
+}
+
+/// Feeds `node` every message recorded for it up to its first decision,
+/// then decides. Returns the recorded and the replayed decision.
+fn replay_into(node: &Node, entries: &[TraceEntry]) -> (Option<String>, Option<String>) {
+    let mut recorded = None;
+    for e in entries {
+        match e {
+            TraceEntry::Message { node: to, path, body, .. } if *to == node.id => {
+                node.handle(path, body);
+            }
+            TraceEntry::Decision { node: who, decided, .. } if *who == node.id => {
+                recorded = decided.clone();
+                break;
+            }
+            _ => {}
+        }
+    }
+    (recorded, node.decide_once())
+}
+
+/// `replay <trace.jsonl> <node-id>`: rebuilds one node from a recorded run
+/// and checks it decides as it did then. Returns the process exit code.
+fn run_replay(args: &[String]) -> i32 {
+    let (Some(path), Some(id)) = (args.get(2), args.get(3).and_then(|a| a.parse::<usize>().ok())) else {
+        eprintln!("usage: replay <byzantine_trace.jsonl> <node-id>");
+        return 2;
+    };
+    let entries = match load_trace(path) {
+        Ok(e) => e,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 2;
+        }
+    };
+    let cfg = Config::from_args_after(4);
+    let log_file = Arc::new(Mutex::new(OpenOptions::new().create(true).append(true).open("replay.log").unwrap()));
+    let port = cfg.port_of(id).unwrap_or(0);
+    let peers = cfg.nodes.iter().filter(|(nid, _)| *nid != id).cloned().collect();
+    let mut node = Node::new(id, port, peers, ByzantineBehavior::Loyal, &cfg, log_file);
+    node.offline = true;
+    let (recorded, replayed) = replay_into(&node, &entries);
+    println!("Node {}: recorded decision {:?}, replayed decision {:?}", id, recorded, replayed);
+    if recorded == replayed {
+        0
+    } else {
+        1
+    }
+}
+
+/// Runs the protocol once with each node of `cluster` as commander, in id
+/// order, and judges the decisions of the others not in `traitors` each
+/// time.
+fn run_each_commander(cluster: &HashMap<usize, Node>, orders: &HashMap<usize, String>, traitors: &[usize]) -> Vec<(usize, Verdict)> {
+    let mut ids: Vec<usize> = cluster.keys().copied().collect();
+    ids.sort();
+    ids.iter()
+        .map(|&commander| {
+            for n in cluster.values() {
+                n.reset();
+            }
+            cluster[&commander].start_as_commander(orders);
+            let decisions: HashMap<usize, Option<String>> =
+                ids.iter().filter(|id| **id != commander).map(|id| (*id, cluster[id].decide_once())).collect();
+            // Let stray forwards to the old commander land before the next reset.
+            thread::sleep(Duration::from_millis(200));
+            (commander, check_agreement(&decisions, traitors))
+        })
+        .collect()
+}
+
+fn print_commander_table(runs: &[(usize, Verdict)], traitors: &[usize]) {
+    println!("{:<10} {:<9} {:<10} value", "commander", "byzantine", "agreement");
+    for (commander, verdict) in runs {
+        let byzantine = traitors.contains(commander);
+        match verdict {
+            Verdict::Agreement(v) => println!("{:<10} {:<9} {:<10} {}", commander, byzantine, "yes", v),
+            Verdict::Disagreement(loyal) => println!("{:<10} {:<9} {:<10} {:?}", commander, byzantine, "no", loyal),
+        }
+    }
+}
+
+/// Graceful exit on SIGINT/SIGTERM: stops every server, flushes the log,
+/// prints each node's decision and exits 0.
+fn shutdown(nodes: &HashMap<usize, Node>) -> ! {
+    let mut ids: Vec<_> = nodes.keys().copied().collect();
+    ids.sort();
+    for id in &ids {
+        let n = &nodes[id];
+        n.log("Shutting down on signal");
+        if let Some(server) = n.server.lock().unwrap().take() {
+            server.unblock();
+        }
+    }
+    if let Some(n) = nodes.values().next() {
+        let _ = n.log_file.lock().unwrap().sync_all();
+    }
+    for id in &ids {
+        let n = &nodes[id];
+        let decided = n.decided.lock().unwrap().clone().unwrap_or_else(|| "-".to_string());
+        println!("Node {} (byzantine={}): decided {}", id, n.is_byzantine(), decided);
+    }
+    println!("Shutdown complete: {} node(s) stopped", nodes.len());
+    std::process::exit(0);
+}
+
+fn install_shutdown(nodes: &HashMap<usize, Node>) {
+    let nodes = nodes.clone();
+    ctrlc::set_handler(move || shutdown(&nodes)).expect("failed to install signal handler");
+}
+
+/// Runs the `byzantine_rust_sim` command line, for its own binary and for
+/// `dc byzantine`.
+pub fn run(args: Vec<String>) {
+    let args = dc_common::args::init(args);
+    match args.get(1).map(String::as_str) {
+        Some("coordinator") => std::process::exit(run_coordinator(&Config::from_args_after(2))),
+        Some("replay") => std::process::exit(run_replay(&args)),
+        Some("serve") => {
+            let cfg = Config::from_args_after(2);
+            let cluster = start_cluster(&cfg);
+            install_shutdown(&cluster);
+            loop {
+                thread::park();
+            }
+        }
+        _ => {}
+    }
+
+    let cfg = Config::from_args();
+    let node_objs = start_cluster(&cfg);
+    if node_objs.is_empty() {
+        panic!("--id {:?} is not in the node list", cfg.id);
+    }
+    install_shutdown(&node_objs);
+
+    if cfg.each_commander {
+        if cfg.id.is_some() {
+            panic!("--each-commander needs the whole cluster in this process, not --id");
+        }
+        let traitors = cfg.traitor_ids();
+        let runs = run_each_commander(&node_objs, &cfg.orders, &traitors);
+        print_commander_table(&runs, &traitors);
+        if let Some(path) = &cfg.junit {
+            let cases: Vec<JunitCase> = runs
+                .iter()
+                .map(|(commander, v)| JunitCase { name: format!("agreement with commander {}", commander), failure: v.failure() })
+                .collect();
+            write_junit(path, "byzantine", &cases);
+        }
+        return;
+    }
+
+    if let Some(commander) = node_objs.get(&cfg.commander) {
+        commander.start_as_commander(&cfg.orders);
+    }
+
+    for (id, _) in cfg.nodes.iter().filter(|(id, _)| *id != cfg.commander) {
+        if let Some(node) = node_objs.get(id) {
+            node.decide_once();
+        }
+    }
+
+    thread::sleep(Duration::from_millis(200));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_config() -> Config {
+        Config {
+            forward_barrier: ForwardBarrier::ReadySignal,
+            values: vec!["ATTACK".to_string(), "RETREAT".to_string()],
+            default_order: "RETREAT".to_string(),
+            orders: HashMap::new(),
+            decide_timeout: Duration::from_secs(5),
+            auth_secret: None,
+            max_body_bytes: 1024,
+            rate_limit: 1.0,
+            rate_burst: 2.0,
+            id: None,
+            require_fault_bound: false,
+            traitor_targets: HashMap::new(),
+            max_log_bytes: None,
+            log_backups: 3,
+            per_node_logs: false,
+            connect_timeout: Duration::from_secs(2),
+            request_timeout: Duration::from_secs(2),
+            shuffle_send: false,
+            seed: None,
+            each_commander: false,
+            ack_timeout: None,
+            order_retries: 0,
+            record: false,
+            omit_orders_to: HashSet::new(),
+            nodes: NODES.to_vec(),
+            commander: COMMANDER,
+            traitors: HashMap::new(),
+            junit: None,
+        }
+    }
+
+    fn test_node(id: usize, peers: Vec<(usize, u16)>, is_byzantine: bool) -> Node {
+        let path = std::env::temp_dir().join(format!("byzantine-test-{}-{}.log", std::process::id(), id));
+        let log_file = Arc::new(Mutex::new(OpenOptions::new().create(true).append(true).open(path).unwrap()));
+        let behavior = if is_byzantine { ByzantineBehavior::Targeted } else { ByzantineBehavior::Loyal };
+        Node::new(id, 0, peers, behavior, &test_config(), log_file)
+    }
+
+    #[test]
+    fn rate_limit_is_per_source_ip() {
+        let node = test_node(1, vec![], false);
+        let flooder: IpAddr = "10.0.0.2".parse().unwrap();
+        let peer: IpAddr = "10.0.0.3".parse().unwrap();
+        assert!(node.admit(Some(flooder), None).is_none());
+        assert!(node.admit(Some(flooder), None).is_none());
+        let resp = node.admit(Some(flooder), None).expect("burst of 2 exhausted");
+        assert_eq!(resp.status_code().0, 429);
+        assert!(node.admit(Some(peer), None).is_none());
+    }
+
+    #[test]
+    fn oversized_body_gets_413() {
+        let node = test_node(1, vec![], false);
+        assert_eq!(node.admit(None, Some(4096)).unwrap().status_code().0, 413);
+        assert!(node.admit(None, Some(512)).is_none());
+    }
+
+    fn body_of(resp: HttpResponse) -> String {
+        String::from_utf8(resp.into_reader().into_inner()).unwrap()
+    }
+
+    fn fwd(entries: &[(usize, &str)]) -> HashMap<usize, String> {
+        entries.iter().map(|(id, o)| (*id, o.to_string())).collect()
+    }
+
+    #[test]
+    fn fault_bound_needs_three_m_plus_one() {
+        for (n, m, ok) in [(1, 0, true), (3, 1, false), (4, 1, true), (6, 2, false), (7, 2, true), (9, 3, false), (10, 3, true)] {
+            assert_eq!(tolerates_faults(n, m), ok, "n={} m={}", n, m);
+        }
+    }
+
+    #[test]
+    fn configured_default_order_breaks_ties() {
+        let mut node = test_node(1, vec![(0, 0), (2, 0)], false);
+        node.default_order = "HOLD".to_string();
+        deliver(&node, "/order", 0, "ATTACK");
+        deliver(&node, "/forward", 2, "RETREAT");
+        assert_eq!(node.decide().as_deref(), Some("HOLD"));
+    }
+
+    #[test]
+    fn log_rotates_past_max_bytes() {
+        let path = std::env::temp_dir().join(format!("byzantine-test-{}-rotate.log", std::process::id()));
+        let mut node = test_node(9, vec![], false);
+        node.log_file = Arc::new(Mutex::new(OpenOptions::new().create(true).append(true).open(&path).unwrap()));
+        node.log_rotation = Some(LogRotation { path: path.clone(), max_bytes: 256, backups: 2 });
+        for i in 0..40 {
+            node.log(&format!("filler line {}", i));
+        }
+        let backup = |n| PathBuf::from(format!("{}.{}", path.display(), n));
+        assert!(backup(1).exists());
+        assert!(backup(2).exists());
+        assert!(!backup(3).exists());
+        assert!(fs::metadata(&path).unwrap().len() < 256);
+        for p in [path.clone(), backup(1), backup(2)] {
+            let _ = fs::remove_file(p);
+        }
+    }
+
+    #[test]
+    fn traitor_targets_choose_per_peer_values() {
+        let mut node = test_node(2, vec![(0, 0), (1, 0), (3, 0)], true);
+        node.traitor_targets = HashMap::from([(1, "ATTACK".to_string())]);
+        assert_eq!(node.traitor_value_for(1, "ATTACK"), "ATTACK");
+        assert_eq!(node.traitor_value_for(3, "ATTACK"), "RETREAT");
+    }
+
+    #[test]
+    fn majority_unanimous_attack() {
+        let f = fwd(&[(1, "ATTACK"), (2, "ATTACK"), (3, "ATTACK")]);
+        assert_eq!(majority("ATTACK", &f, "RETREAT").winner, "ATTACK");
+    }
+
+    #[test]
+    fn majority_outvotes_one_traitor() {
+        let f = fwd(&[(1, "ATTACK"), (2, "ATTACK"), (3, "RETREAT")]);
+        assert_eq!(majority("ATTACK", &f, "RETREAT").winner, "ATTACK");
+    }
+
+    #[test]
+    fn majority_tie_uses_default() {
+        let f = fwd(&[(1, "ATTACK"), (2, "RETREAT"), (3, "RETREAT")]);
+        assert_eq!(majority("ATTACK", &f, "HOLD").winner, "HOLD");
+    }
+
+    #[test]
+    fn majority_empty_forwarded_is_commander_order() {
+        assert_eq!(majority("ATTACK", &HashMap::new(), "RETREAT").winner, "ATTACK");
+    }
+
+    #[test]
+    fn orders_file_overrides_per_lieutenant() {
+        let overrides = parse_orders(r#"{"1": "ATTACK", "2": "RETREAT"}"#).unwrap();
+        let peers = [(1, 8001), (2, 8002), (3, 8003)];
+        let map = build_order_map(&peers, &overrides, "ATTACK");
+        assert_eq!(map[&1], "ATTACK");
+        assert_eq!(map[&2], "RETREAT");
+        assert_eq!(map[&3], "ATTACK");
+    }
+
+    #[test]
+    fn forwarded_endpoint_lists_every_peer() {
+        let node = test_node(1, vec![(0, 0), (2, 0), (3, 0)], false);
+        for (from, order) in [(2, "ATTACK"), (3, "RETREAT")] {
+            let msg = serde_json::to_string(&OrderMsg::new(from, order.to_string())).unwrap();
+            node.handle("/forward", &msg);
+        }
+        let map: HashMap<usize, String> = serde_json::from_str(&body_of(node.handle("/forwarded", ""))).unwrap();
+        assert_eq!(map, fwd(&[(2, "ATTACK"), (3, "RETREAT")]));
+    }
+
+    fn deliver(node: &Node, url: &str, from: usize, order: &str) {
+        let msg = serde_json::to_string(&OrderMsg::new(from, order.to_string())).unwrap();
+        node.handle(url, &msg);
+    }
+
+    /// One round of OM(1) by direct delivery: lieutenant `l` hears
+    /// `orders[l]` from commander 0, then every other lieutenant's relay of
+    /// what it heard. Returns each lieutenant's decision.
+    fn exchange(nodes: &[Node], orders: &HashMap<usize, &str>) -> HashMap<usize, Option<String>> {
+        for to in nodes {
+            deliver(to, "/order", 0, orders[&to.id]);
+        }
+        for to in nodes {
+            for from in nodes.iter().filter(|n| n.id != to.id) {
+                let heard = orders[&from.id];
+                let relayed = from.relayed_value(from.current_behavior(), to.id, heard);
+                deliver(to, "/forward", from.id, &relayed);
+            }
+        }
+        nodes.iter().map(|n| (n.id, n.decide_once())).collect()
+    }
+
+    fn lieutenants(n: usize, traitors: &[usize]) -> Vec<Node> {
+        (1..n).map(|id| test_node(id, (0..n).filter(|p| *p != id).map(|p| (p, 0)).collect(), traitors.contains(&id))).collect()
+    }
+
+    #[test]
+    fn loyal_lieutenants_agree_when_n_exceeds_3m() {
+        assert!(tolerates_faults(4, 1));
+        let nodes = lieutenants(4, &[3]);
+        let orders = HashMap::from([(1, "ATTACK"), (2, "ATTACK"), (3, "ATTACK")]);
+        let decisions = exchange(&nodes, &orders);
+        assert_eq!(check_agreement(&decisions, &[3]), Verdict::Agreement("ATTACK".to_string()));
+    }
+
+    #[test]
+    fn shuffled_send_order_still_reaches_agreement() {
+        let mut orders_seen = HashSet::new();
+        for seed in 0..50 {
+            let mut commander = test_node(0, vec![(1, 0), (2, 0), (3, 0)], false);
+            commander.send_shuffle = Some(Arc::new(Mutex::new(StdRng::seed_from_u64(seed))));
+            let order: Vec<usize> = commander.send_order().iter().map(|(nid, _)| *nid).collect();
+            orders_seen.insert(order.clone());
+
+            // Each lieutenant relays as soon as its order lands, so later
+            // ones hear forwards before the commander.
+            let nodes = lieutenants(4, &[3]);
+            let by_id = |id: usize| nodes.iter().find(|n| n.id == id).unwrap();
+            for l in &order {
+                deliver(by_id(*l), "/order", 0, "ATTACK");
+                for to in nodes.iter().filter(|n| n.id != *l) {
+                    let relayed = by_id(*l).relayed_value(by_id(*l).current_behavior(), to.id, "ATTACK");
+                    deliver(to, "/forward", *l, &relayed);
+                }
+            }
+            let decisions = nodes.iter().map(|n| (n.id, n.decide_once())).collect();
+            assert_eq!(check_agreement(&decisions, &[3]), Verdict::Agreement("ATTACK".to_string()), "seed {}", seed);
+        }
+        assert!(orders_seen.len() > 1, "shuffle never changed the order");
+    }
+
+    #[test]
+    fn any_loyal_commander_reaches_agreement_after_reset() {
+        let ids = [0, 1, 2, 3];
+        let nodes: Vec<Node> =
+            ids.iter().map(|&id| test_node(id, ids.iter().filter(|p| **p != id).map(|p| (*p, 0)).collect(), id == 3)).collect();
+        for commander in [0, 1, 2] {
+            for n in &nodes {
+                n.reset();
+            }
+            let lts: Vec<&Node> = nodes.iter().filter(|n| n.id != commander).collect();
+            for to in &lts {
+                deliver(to, "/order", commander, "ATTACK");
+            }
+            for to in &lts {
+                for from in lts.iter().filter(|n| n.id != to.id) {
+                    deliver(to, "/forward", from.id, &from.relayed_value(from.current_behavior(), to.id, "ATTACK"));
+                }
+            }
+            let decisions = lts.iter().map(|n| (n.id, n.decide_once())).collect();
+            assert_eq!(check_agreement(&decisions, &[3]), Verdict::Agreement("ATTACK".to_string()), "commander {}", commander);
+            assert_eq!(nodes[commander].round_decisions.lock().unwrap().len(), 0);
+        }
+    }
+
+    #[test]
+    fn two_traitors_among_four_can_split_the_loyal_lieutenants() {
+        assert!(!tolerates_faults(4, 2));
+        // Traitorous commander 0 and lieutenant 3 tell 1 ATTACK and 2 RETREAT.
+        let mut nodes = lieutenants(4, &[3]);
+        nodes[2].traitor_targets = HashMap::from([(1, "ATTACK".to_string()), (2, "RETREAT".to_string())]);
+        let orders = HashMap::from([(1, "ATTACK"), (2, "RETREAT"), (3, "ATTACK")]);
+        let decisions = exchange(&nodes, &orders);
+        let split = BTreeMap::from([(1, Some("ATTACK".to_string())), (2, Some("RETREAT".to_string()))]);
+        assert_eq!(check_agreement(&decisions, &[0, 3]), Verdict::Disagreement(split));
+    }
+
+    #[test]
+    fn decide_returns_once_last_forward_arrives() {
+        let node = test_node(1, vec![(0, 0), (2, 0), (3, 0)], false);
+        deliver(&node, "/order", 0, "ATTACK");
+        deliver(&node, "/forward", 2, "ATTACK");
+        let late = node.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            deliver(&late, "/forward", 3, "RETREAT");
+        });
+        let started = Instant::now();
+        assert_eq!(node.decide().as_deref(), Some("ATTACK"));
+        assert!(started.elapsed() < Duration::from_secs(2), "decide waited {:?}", started.elapsed());
+        assert!(node.forwarded.lock().unwrap().contains_key(&3));
+    }
+
+    #[test]
+    fn decide_waits_for_a_slow_forwarder() {
+        let node = test_node(1, vec![(0, 0), (2, 0), (3, 0), (4, 0)], false);
+        node.unreachable.lock().unwrap().insert(4);
+        deliver(&node, "/order", 0, "ATTACK");
+        deliver(&node, "/forward", 2, "RETREAT");
+        let slow = node.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(300));
+            deliver(&slow, "/forward", 3, "RETREAT");
+        });
+        // Only 3's RETREAT outvotes the commander's ATTACK; 4 is not waited for.
+        let started = Instant::now();
+        assert_eq!(node.decide().as_deref(), Some("RETREAT"));
+        assert!(started.elapsed() < node.decide_timeout, "decide waited {:?}", started.elapsed());
+    }
+
+    #[test]
+    fn concurrent_decide_once_computes_once() {
+        let node = test_node(1, vec![(0, 0), (2, 0), (3, 0)], false);
+        deliver(&node, "/order", 0, "ATTACK");
+        deliver(&node, "/forward", 2, "ATTACK");
+        deliver(&node, "/forward", 3, "RETREAT");
+        let callers: Vec<_> = (0..2)
+            .map(|_| {
+                let n = node.clone();
+                thread::spawn(move || n.decide_once())
+            })
+            .collect();
+        let results: Vec<_> = callers.into_iter().map(|h| h.join().unwrap()).collect();
+        assert_eq!(results, vec![Some("ATTACK".to_string()); 2]);
+        // Round 0 is the order itself; a single decide adds round 1.
+        assert_eq!(node.round_decisions.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn om1_with_four_nodes_records_two_rounds() {
+        let node = test_node(1, vec![(0, 0), (2, 0), (3, 0)], false);
+        deliver(&node, "/order", 0, "ATTACK");
+        deliver(&node, "/forward", 2, "ATTACK");
+        deliver(&node, "/forward", 3, "RETREAT");
+        node.decide();
+        let rounds: Vec<String> = serde_json::from_str(&body_of(node.handle("/rounds", ""))).unwrap();
+        assert_eq!(rounds, vec!["ATTACK", "ATTACK"]);
+    }
+
+    #[test]
+    fn behavior_switch_flips_the_next_forward() {
+        let node = test_node(1, vec![], false);
+        node.forward_order("ATTACK".to_string());
+        assert_eq!(node.forwarded.lock().unwrap()[&1], "ATTACK");
+
+        let resp = node.handle("/behavior", r#"{"behavior": "flip_all"}"#);
+        assert_eq!(resp.status_code().0, 200);
+        assert!(body_of(resp).contains("flip_all"));
+        assert!(node.is_byzantine());
+        // A fresh round: forward_order only relays once per order.
+        node.forwarded.lock().unwrap().clear();
+        node.forward_order("ATTACK".to_string());
+        assert_eq!(node.forwarded.lock().unwrap()[&1], "RETREAT");
+        assert_eq!(node.handle("/behavior", r#"{"behavior": "sneaky"}"#).status_code().0, 400);
+    }
+
+    #[test]
+    fn server_error_is_logged_and_retried() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (tx, hits) = mpsc::channel();
+        thread::spawn(move || answer(listener, "500 Internal Server Error", Duration::ZERO, Some(tx)));
+
+        let node = test_node(7, vec![(8, port)], false);
+        let url = format!("http://127.0.0.1:{}/forward", port);
+        let err = node.post_signed(&url, 8, "{}".to_string()).unwrap_err();
+        assert!(err.contains("500"), "{}", err);
+        assert_eq!(hits.try_iter().count(), 3);
+        let log = fs::read_to_string(std::env::temp_dir().join(format!("byzantine-test-{}-7.log", std::process::id()))).unwrap();
+        assert!(log.contains("HTTP 500 Internal Server Error from 8 on attempt 1; retrying"), "{}", log);
+    }
+
+    /// A peer on a raw socket that answers every request with 200 after
+    /// `delay`. Returns its port.
+    fn slow_peer(delay: Duration) -> u16 {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || answer(listener, "200 OK", delay, None));
+        port
+    }
+
+    /// Answers every request on `listener` with `status` after `delay`,
+    /// passing each body to `bodies` if given.
+    fn answer(listener: std::net::TcpListener, status: &str, delay: Duration, bodies: Option<mpsc::Sender<String>>) {
+        use std::io::{BufRead, BufReader};
+
+        for mut stream in listener.incoming().flatten() {
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut len = 0;
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap_or(0) > 2 {
+                if let Some(v) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                    len = v.trim().parse().unwrap_or(0);
+                }
+                line.clear();
+            }
+            let mut body = vec![];
+            let _ = reader.take(len).read_to_end(&mut body);
+            if let Some(tx) = &bodies {
+                let _ = tx.send(String::from_utf8_lossy(&body).into_owned());
+            }
+            thread::sleep(delay);
+            let _ = write!(stream, "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status);
+        }
+    }
+
+    fn free_port() -> u16 {
+        std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+    }
+
+    /// Starts `node` listening on `port`, with the rate limit lifted since
+    /// every peer shares 127.0.0.1, and waits until it is up.
+    fn serve(mut node: Node, port: u16) -> Node {
+        node.port = port;
+        node.rate_limiter = Arc::new(Mutex::new(RateLimiter::new(1000.0, 1000.0)));
+        node.start_server();
+        while node.server.lock().unwrap().is_none() {
+            thread::sleep(Duration::from_millis(10));
+        }
+        node
+    }
+
+    #[test]
+    fn slow_order_link_to_node_2_still_gets_its_forward_to_node_1() {
+        let ports: HashMap<usize, u16> = (1..4).map(|id| (id, free_port())).collect();
+        let peers_of = |id: usize| (0..4).filter(|p| *p != id).map(|p| (p, ports.get(&p).copied().unwrap_or(0))).collect();
+        let nodes: Vec<Node> = (1..4).map(|id| serve(test_node(id, peers_of(id), false), ports[&id])).collect();
+
+        // The commander reaches node 2 over a link that holds the ORDER back
+        // until well after READY, which goes straight through.
+        let link = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let link_port = link.local_addr().unwrap().port();
+        let (tx, bodies) = mpsc::channel::<String>();
+        thread::spawn(move || answer(link, "200 OK", Duration::ZERO, Some(tx)));
+        let node2 = nodes[1].clone();
+        thread::spawn(move || {
+            for body in bodies {
+                if body.is_empty() {
+                    node2.handle("/ready", "");
+                } else {
+                    let node2 = node2.clone();
+                    thread::spawn(move || {
+                        thread::sleep(Duration::from_millis(600));
+                        node2.handle("/order", &body);
+                    });
+                }
+            }
+        });
+
+        let commander = test_node(0, vec![(1, ports[&1]), (2, link_port), (3, ports[&3])], false);
+        commander.start_as_commander(&HashMap::new());
+        for node in &nodes {
+            assert_eq!(node.decide_once().as_deref(), Some("ATTACK"), "node {}", node.id);
+        }
+        assert_eq!(nodes[0].forwarded.lock().unwrap().get(&2).map(String::as_str), Some("ATTACK"));
+    }
+
+    #[test]
+    fn commander_send_waits_for_a_slow_ack() {
+        let port = slow_peer(Duration::from_millis(300));
+        let mut node = test_node(0, vec![(1, port)], false);
+        let orders = HashMap::from([(1, "ATTACK".to_string())]);
+
+        node.ack_timeout = Some(Duration::from_secs(2));
+        let started = Instant::now();
+        assert!(node.commander_send(&orders).is_empty());
+        assert!(started.elapsed() >= Duration::from_millis(300), "returned after {:?}", started.elapsed());
+
+        node.ack_timeout = Some(Duration::from_millis(100));
+        let started = Instant::now();
+        assert_eq!(node.commander_send(&orders), vec![1]);
+        let took = started.elapsed();
+        assert!(took >= Duration::from_millis(100) && took < Duration::from_millis(300), "returned after {:?}", took);
+    }
+
+    #[test]
+    fn lieutenant_without_an_order_decides_from_forwards() {
+        // The commander skips node 2 and doesn't wait on its ack.
+        let dead = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let mut commander = test_node(0, vec![(1, slow_peer(Duration::ZERO)), (2, dead), (3, slow_peer(Duration::ZERO))], false);
+        commander.omit_orders_to = HashSet::from([2]);
+        commander.ack_timeout = Some(Duration::from_secs(2));
+        let orders = HashMap::from([(1, "ATTACK".to_string()), (2, "ATTACK".to_string()), (3, "ATTACK".to_string())]);
+        assert!(commander.commander_send(&orders).is_empty());
+
+        let node = test_node(2, vec![(0, 0), (1, 0), (3, 0)], false);
+        deliver(&node, "/forward", 1, "ATTACK");
+        deliver(&node, "/forward", 3, "ATTACK");
+        let started = Instant::now();
+        assert_eq!(node.decide_once().as_deref(), Some("ATTACK"));
+        assert!(started.elapsed() < node.decide_timeout, "waited out the decide timeout");
+        assert!(node.commander_order.lock().unwrap().is_none());
+    }
+
+    #[test]
+    fn replayed_trace_reproduces_the_decision() {
+        let path = std::env::temp_dir().join(format!("byzantine-test-{}-trace.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let mut node = test_node(1, vec![(0, 0), (2, 0), (3, 0)], false);
+        node.trace_file = Some(Arc::new(Mutex::new(OpenOptions::new().create(true).append(true).open(&path).unwrap())));
+        deliver(&node, "/order", 0, "ATTACK");
+        deliver(&node, "/forward", 2, "RETREAT");
+        deliver(&node, "/forward", 3, "RETREAT");
+        let original = node.decide_once();
+        assert_eq!(original.as_deref(), Some("RETREAT"));
+
+        let entries = load_trace(path.to_str().unwrap()).unwrap();
+        assert_eq!(entries.iter().filter(|e| matches!(e, TraceEntry::Message { .. })).count(), 3);
+        let mut fresh = test_node(1, vec![(0, 0), (2, 0), (3, 0)], false);
+        fresh.offline = true;
+        let (recorded, replayed) = replay_into(&fresh, &entries);
+        assert_eq!(recorded, original);
+        assert_eq!(replayed, original);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn bad_hmac_is_rejected() {
+        let mut node = test_node(1, vec![(0, 0), (2, 0)], false);
+        node.auth_secret = Some("s3cret".to_string());
+        let body = serde_json::to_string(&OrderMsg::new(0, "ATTACK".to_string())).unwrap();
+        let good = sign_hmac(&pair_key("s3cret", 0, 1), &body);
+        let forged = sign_hmac(&pair_key("guess", 0, 1), &body);
+        assert!(node.authenticate("/order", &body, Some(&good)));
+        assert!(!node.authenticate("/order", &body, Some(&forged)));
+        assert!(!node.authenticate("/order", &body, None));
+    }
+
+    #[test]
+    fn other_value_never_repeats_the_order() {
+        let values: Vec<String> = ["A", "B", "C"].iter().map(|s| s.to_string()).collect();
+        for v in &values {
+            assert_ne!(&other_value(v, &values), v);
+        }
+    }
+
+    #[test]
+    fn loyal_nodes_converge_with_three_values() {
+        let values: Vec<String> = ["A", "B", "C"].iter().map(|s| s.to_string()).collect();
+        // Commander 0 orders "A"; lieutenant 3 is the traitor.
+        let traitor = other_value("A", &values);
+        let f = fwd(&[(1, "A"), (2, "A"), (3, &traitor)]);
+        assert_eq!(majority("A", &f, "NONE").winner, "A");
+    }
+
+    #[test]
+    fn decision_explanation_lists_every_vote() {
+        let f = fwd(&[(1, "ATTACK"), (2, "RETREAT"), (3, "ATTACK")]);
+        let t = majority("ATTACK", &f, "RETREAT");
+        assert_eq!(t.winner, "ATTACK");
+        assert!(!t.tied);
+        assert_eq!(t.votes["ATTACK"], [Voter::Commander, Voter::Node(1), Voter::Node(3)]);
+        assert_eq!(t.votes["RETREAT"], [Voter::Node(2)]);
+        assert_eq!(
+            t.explain(),
+            "ATTACK 3 (commander, node 1, node 3), RETREAT 1 (node 2): ATTACK has the most votes"
+        );
+
+        let f = fwd(&[(1, "ATTACK"), (2, "RETREAT"), (3, "RETREAT")]);
+        assert_eq!(
+            majority("ATTACK", &f, "HOLD").explain(),
+            "ATTACK 2 (commander, node 1), RETREAT 2 (node 2, node 3): tie for the most votes, default HOLD"
+        );
+    }
+
+    #[test]
+    fn topology_file_marks_traitors_and_their_behavior() {
+        let sample = r#"
+            [[nodes]]
+            id = 0
+            port = 8000
+            resources = ["A", "B"]
+
+            [[nodes]]
+            id = 1
+            port = 8001
+
+            [[nodes]]
+            id = 2
+            port = 8002
+            byzantine = true
+            behavior = "flip_all"
+
+            [[nodes]]
+            id = 3
+            port = 8003
+            byzantine = true
+        "#;
+        let mut cfg = Config::from_args_after(usize::MAX);
+        cfg.apply_topology(&parse_topology(sample).unwrap());
+        assert_eq!(cfg.nodes, [(0, 8000), (1, 8001), (2, 8002), (3, 8003)]);
+        assert_eq!(cfg.traitor_ids(), [2, 3]);
+        assert_eq!(cfg.behavior_of(1), ByzantineBehavior::Loyal);
+        assert_eq!(cfg.behavior_of(2), ByzantineBehavior::FlipAll);
+        assert_eq!(cfg.behavior_of(3), ByzantineBehavior::Targeted);
+
+        let bad = "[[nodes]]\nid = 0\nport = 8000\nbehavior = \"flip_all\"\n[[nodes]]\nid = 1\nport = 8000";
+        assert_eq!(
+            parse_topology(bad).unwrap_err(),
+            "node 0 has behavior FlipAll but is not byzantine; node 1 reuses port 8000"
+        );
+    }
+
+    #[test]
+    fn topology_without_node_0_names_its_own_commander() {
+        let nodes = "[[nodes]]\nid = 1\nport = 8001\n[[nodes]]\nid = 2\nport = 8002\n[[nodes]]\nid = 3\nport = 8003\n";
+        let mut cfg = Config::from_args_after(usize::MAX);
+        cfg.apply_topology(&parse_topology(nodes).unwrap());
+        assert_eq!(cfg.commander, 1);
+        cfg.apply_topology(&parse_topology(&format!("commander = 3\n{}", nodes)).unwrap());
+        assert_eq!(cfg.commander, 3);
+        assert_eq!(parse_topology(&format!("commander = 0\n{}", nodes)).unwrap_err(), "commander 0 is not in the node list");
+
+        // A commander missing from the node list is an error, not a panic.
+        cfg.commander = 0;
+        assert_eq!(run_coordinator(&cfg), 2);
+    }
+
+    #[test]
+    fn junit_report_has_a_well_formed_case_per_invariant() {
+        let decisions = HashMap::from([(1, Some("ATTACK".to_string())), (2, Some("RETREAT".to_string())), (3, None)]);
+        let verdict = check_agreement(&decisions, &[3]);
+        let xml = junit_xml("byzantine", &coordinator_cases(&decisions, &verdict));
+
+        let body = xml.strip_prefix("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n").expect("no XML declaration");
+        let (mut open, mut elements, mut rest) = (vec![], vec![], body);
+        while let Some(start) = rest.find('<') {
+            assert!(rest[..start].trim().is_empty(), "stray text {:?}", &rest[..start]);
+            let end = rest[start..].find('>').expect("unclosed tag") + start;
+            let tag = &rest[start + 1..end];
+            assert!(!tag.contains('<'), "bare < inside {:?}", tag);
+            if let Some(name) = tag.strip_prefix('/') {
+                assert_eq!(open.pop(), Some(name.to_string()), "mismatched </{}>", name);
+            } else {
+                let name = tag.split_whitespace().next().unwrap().trim_end_matches('/').to_string();
+                if !tag.ends_with('/') {
+                    open.push(name.clone());
+                }
+                elements.push(name);
+            }
+            rest = &rest[end + 1..];
+        }
+        assert!(open.is_empty(), "unclosed {:?}", open);
+
+        assert_eq!(elements, ["testsuite", "testcase", "failure", "testcase", "failure"]);
+        assert!(xml.contains("tests=\"2\" failures=\"2\""), "{}", xml);
+        assert!(xml.contains("name=\"all lieutenants decided\">"));
+        assert!(xml.contains("message=\"no decision from [3]\""));
+        assert!(xml.contains("name=\"byzantine agreement reached\">"));
+        assert!(xml.contains("message=\"loyal lieutenants decided {1: Some(&quot;ATTACK&quot;), 2: Some(&quot;RETREAT&quot;)}\""), "{}", xml);
+
+        let agreed = HashMap::from([(1, Some("ATTACK".to_string())), (2, Some("ATTACK".to_string()))]);
+        let xml = junit_xml("byzantine", &coordinator_cases(&agreed, &check_agreement(&agreed, &[])));
+        assert!(xml.contains("failures=\"0\"") && !xml.contains("<failure"), "{}", xml);
+    }
+
+    #[test]
+    fn commander_resends_until_a_late_lieutenant_acks() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let (tx, bodies) = mpsc::channel();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(350));
+            answer(std::net::TcpListener::bind(("127.0.0.1", port)).unwrap(), "200 OK", Duration::ZERO, Some(tx));
+        });
+        let mut commander = test_node(0, vec![(1, port)], false);
+        commander.ack_timeout = Some(Duration::from_millis(150));
+        let orders = HashMap::from([(1, "ATTACK".to_string())]);
+
+        commander.order_retries = 0;
+        assert_eq!(commander.commander_send(&orders), vec![1], "no retries: the late lieutenant is unreachable");
+        commander.order_retries = 5;
+        assert!(commander.commander_send(&orders).is_empty());
+        let order: OrderMsg = serde_json::from_str(&bodies.recv_timeout(Duration::from_secs(1)).unwrap()).unwrap();
+        assert_eq!((order.from, order.order.as_str()), (0, "ATTACK"));
+    }
+
+    #[test]
+    fn decision_endpoint_answers_at_once_while_deciding() {
+        let node = test_node(1, vec![(0, 0), (2, 0), (3, 0)], false);
+        let decision = |node: &Node| {
+            let resp = node.handle("/decision", "");
+            let status = resp.status_code().0;
+            let v: serde_json::Value = serde_json::from_str(&body_of(resp)).unwrap();
+            (status, v["decided"].as_str().map(String::from))
+        };
+        let started = Instant::now();
+        assert_eq!(decision(&node), (202, None));
+        assert!(started.elapsed() < Duration::from_millis(500), "blocked for {:?}", started.elapsed());
+
+        // Forwards still land while the background decide waits for them.
+        deliver(&node, "/order", 0, "ATTACK");
+        deliver(&node, "/forward", 2, "ATTACK");
+        deliver(&node, "/forward", 3, "RETREAT");
+        let deadline = Instant::now() + Duration::from_secs(2);
+        loop {
+            match decision(&node) {
+                (200, decided) => {
+                    assert_eq!(decided.as_deref(), Some("ATTACK"));
+                    break;
+                }
+                (status, _) => assert_eq!(status, 202),
+            }
+            assert!(Instant::now() < deadline, "never decided");
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    #[test]
+    fn two_silent_peers_still_get_a_majority_of_the_reports_received() {
+        let peers = vec![(0, 0), (2, 0), (3, 0), (4, 0)];
+
+        // Order and one forward in; lieutenants 3 and 4 never report.
+        let mut node = test_node(1, peers.clone(), false);
+        node.decide_timeout = Duration::from_millis(200);
+        deliver(&node, "/order", 0, "ATTACK");
+        deliver(&node, "/forward", 2, "ATTACK");
+        assert_eq!(node.decide_once().as_deref(), Some("ATTACK"));
+
+        // No order and two forwards; the commander and lieutenant 4 stay silent.
+        let mut node = test_node(1, peers, false);
+        node.decide_timeout = Duration::from_millis(200);
+        deliver(&node, "/forward", 2, "ATTACK");
+        deliver(&node, "/forward", 3, "ATTACK");
+        let started = Instant::now();
+        assert_eq!(node.decide_once().as_deref(), Some("ATTACK"), "fell back to the default");
+        assert!(started.elapsed() >= node.decide_timeout, "stopped waiting with two reports missing");
+    }
+
+    #[test]
+    fn send_to_a_silent_peer_fails_within_the_request_timeout() {
+        // Accepted by the kernel's backlog, never read or answered.
+        let black_hole = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = black_hole.local_addr().unwrap().port();
+        let mut node = test_node(24, vec![(1, port)], false);
+        node.client = Config { request_timeout: Duration::from_millis(300), ..test_config() }.http_client();
+
+        let started = Instant::now();
+        let err = node.post_signed(&format!("http://127.0.0.1:{}/receive_order", port), 1, "{}".to_string()).unwrap_err();
+        let took = started.elapsed();
+        assert!(took >= Duration::from_millis(300) && took < Duration::from_secs(2), "returned after {:?}: {}", took, err);
+        drop(black_hole);
+    }
+}
@@ -0,0 +1,47 @@
+use std::{
+    fs,
+    net::{TcpListener, TcpStream},
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+#[test]
+fn coordinator_reports_agreement_from_a_served_cluster() {
+    let dir = std::env::temp_dir().join(format!("byzantine-coordinator-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    // Held together so the four ports differ.
+    let listeners: Vec<TcpListener> = (0..4).map(|_| TcpListener::bind("127.0.0.1:0").unwrap()).collect();
+    let ports: Vec<u16> = listeners.iter().map(|l| l.local_addr().unwrap().port()).collect();
+    drop(listeners);
+    let mut topology = String::new();
+    for (id, port) in ports.iter().enumerate() {
+        topology += &format!("[[nodes]]\nid = {}\nport = {}\n", id, port);
+        if id == 3 {
+            topology += "byzantine = true\n";
+        }
+    }
+    let path = dir.join("topology.toml");
+    fs::write(&path, topology).unwrap();
+
+    let bin = env!("CARGO_BIN_EXE_byzantine_rust_sim");
+    let mut serve = Command::new(bin)
+        .args(["serve", "--topology", path.to_str().unwrap()])
+        .current_dir(&dir)
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap();
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !ports.iter().all(|p| TcpStream::connect(("127.0.0.1", *p)).is_ok()) {
+        assert!(Instant::now() < deadline, "cluster never came up");
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    let out = Command::new(bin).args(["coordinator", "--topology", path.to_str().unwrap()]).output().unwrap();
+    let _ = serve.kill();
+    let _ = serve.wait();
+    let _ = fs::remove_dir_all(&dir);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert_eq!(out.status.code(), Some(0), "{}{}", stdout, String::from_utf8_lossy(&out.stderr));
+    assert!(stdout.contains("AGREEMENT REACHED on ATTACK"), "{}", stdout);
+}
@@ -0,0 +1,51 @@
+#![cfg(unix)]
+
+use std::{
+    fs,
+    net::{TcpListener, TcpStream},
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+#[test]
+fn sigterm_on_serve_syncs_the_log_and_exits_0() {
+    let dir = std::env::temp_dir().join(format!("byzantine-shutdown-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    // Held together so the four ports differ.
+    let listeners: Vec<TcpListener> = (0..4).map(|_| TcpListener::bind("127.0.0.1:0").unwrap()).collect();
+    let ports: Vec<u16> = listeners.iter().map(|l| l.local_addr().unwrap().port()).collect();
+    drop(listeners);
+    let topology: String = ports.iter().enumerate().map(|(id, port)| format!("[[nodes]]\nid = {}\nport = {}\n", id, port)).collect();
+    fs::write(dir.join("topology.toml"), topology).unwrap();
+
+    let serve = Command::new(env!("CARGO_BIN_EXE_byzantine_rust_sim"))
+        .args(["serve", "--topology", "topology.toml"])
+        .current_dir(&dir)
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !ports.iter().all(|p| TcpStream::connect(("127.0.0.1", *p)).is_ok()) {
+        assert!(Instant::now() < deadline, "cluster never came up");
+        thread::sleep(Duration::from_millis(50));
+    }
+    // The handler is installed just after the servers start.
+    thread::sleep(Duration::from_millis(500));
+    let killed = Command::new("kill").args(["-TERM", &serve.id().to_string()]).status().unwrap();
+    assert!(killed.success());
+
+    let out = serve.wait_with_output().unwrap();
+    let log = fs::read_to_string(dir.join("byzantine.log")).unwrap_or_default();
+    let _ = fs::remove_dir_all(&dir);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert_eq!(out.status.code(), Some(0), "{}", stdout);
+    assert!(stdout.contains("Node 0 (byzantine=false): decided -"), "{}", stdout);
+    assert!(stdout.contains("Shutdown complete: 4 node(s) stopped"), "{}", stdout);
+    for id in 0..4 {
+        assert!(log.contains(&format!("[Node {}] Shutting down on signal", id)), "{}", log);
+    }
+    for port in ports {
+        assert!(TcpListener::bind(("0.0.0.0", port)).is_ok(), "port {} still held", port);
+    }
+}
@@ -0,0 +1,40 @@
+//! `dc <subcommand>`: both assignments behind one binary. Each subcommand
+//! hands the rest of the command line to its crate's own parser, so
+//! `dc mutex --rounds 3` behaves exactly like `lamport_mutex --rounds 3`.
+
+use clap::{Parser, Subcommand};
+
+#[derive(Parser)]
+#[command(name = "dc", about = "Distributed computing assignments")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Lamport mutual exclusion (and its token-ring and coordinator variants)
+    #[command(disable_help_flag = true)]
+    Mutex {
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// Byzantine agreement with oral messages
+    #[command(disable_help_flag = true)]
+    Byzantine {
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+}
+
+/// `args` behind a program name, as the crates' parsers expect.
+fn argv(name: &str, args: Vec<String>) -> Vec<String> {
+    std::iter::once(name.to_string()).chain(args).collect()
+}
+
+fn main() {
+    match Cli::parse().command {
+        Command::Mutex { args } => lamport_mutex::run(argv("dc mutex", args)),
+        Command::Byzantine { args } => byzantine_rust_sim::run(argv("dc byzantine", args)),
+    }
+}
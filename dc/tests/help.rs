@@ -0,0 +1,32 @@
+use std::process::Command;
+
+fn help(subcommand: &str) -> String {
+    let out = Command::new(env!("CARGO_BIN_EXE_dc")).args([subcommand, "--help"]).output().unwrap();
+    assert_eq!(out.status.code(), Some(0), "{}", String::from_utf8_lossy(&out.stderr));
+    String::from_utf8(out.stdout).unwrap()
+}
+
+#[test]
+fn mutex_help_lists_the_lamport_options() {
+    let text = help("mutex");
+    for flag in ["--algorithm", "--quorum", "--attempt-timeout-ms", "--wire-format"] {
+        assert!(text.contains(flag), "{} missing from:\n{}", flag, text);
+    }
+    assert!(!text.contains("--forward-barrier"));
+}
+
+#[test]
+fn byzantine_help_lists_the_agreement_options() {
+    let text = help("byzantine");
+    for flag in ["--forward-barrier", "--decide-timeout-ms", "--traitor-sends", "--orders"] {
+        assert!(text.contains(flag), "{} missing from:\n{}", flag, text);
+    }
+    assert!(!text.contains("--quorum"));
+}
+
+#[test]
+fn top_level_help_names_both_subcommands() {
+    let out = Command::new(env!("CARGO_BIN_EXE_dc")).arg("--help").output().unwrap();
+    let text = String::from_utf8(out.stdout).unwrap();
+    assert!(text.contains("mutex") && text.contains("byzantine"), "{}", text);
+}
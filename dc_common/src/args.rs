@@ -0,0 +1,29 @@
+use std::sync::OnceLock;
+
+/// The command line `run` was given, program name first.
+static ARGS: OnceLock<Vec<String>> = OnceLock::new();
+
+/// Records `args` as the process's command line, unless one already was,
+/// and returns whichever is kept.
+pub fn init(args: Vec<String>) -> Vec<String> {
+    ARGS.get_or_init(|| args).clone()
+}
+
+pub fn cli_args() -> std::vec::IntoIter<String> {
+    ARGS.get().cloned().unwrap_or_default().into_iter()
+}
+
+pub fn arg_value<T: std::str::FromStr>(args: &mut impl Iterator<Item = String>, flag: &str) -> T {
+    let v = args.next().unwrap_or_default();
+    v.parse().unwrap_or_else(|_| panic!("invalid value for {}: {:?}", flag, v))
+}
+
+/// Parses an inclusive `MIN..MAX` millisecond range.
+pub fn arg_range(args: &mut impl Iterator<Item = String>, flag: &str) -> (u64, u64) {
+    let v = args.next().unwrap_or_default();
+    let parsed = v.split_once("..").and_then(|(lo, hi)| Some((lo.parse().ok()?, hi.parse().ok()?)));
+    match parsed {
+        Some((lo, hi)) if lo <= hi => (lo, hi),
+        _ => panic!("{} expects MIN..MAX, got {:?}", flag, v),
+    }
+}
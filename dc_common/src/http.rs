@@ -0,0 +1,45 @@
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use tiny_http::Header;
+
+pub fn json_header() -> Header {
+    Header::from_bytes("Content-Type", "application/json").unwrap()
+}
+
+/// Polls `up` for every peer's port until all answer or `timeout` passes.
+/// On timeout, returns the ids still silent.
+pub fn wait_for_peers(peers: &[(usize, u16)], timeout: Duration, up: impl Fn(u16) -> bool) -> Result<(), Vec<usize>> {
+    let deadline = Instant::now() + timeout;
+    let mut pending = peers.to_vec();
+    loop {
+        pending.retain(|(_, port)| !up(*port));
+        if pending.is_empty() {
+            return Ok(());
+        }
+        if Instant::now() >= deadline {
+            return Err(pending.iter().map(|(nid, _)| *nid).collect());
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn waits_until_a_late_peer_answers() {
+        let polls = Cell::new(0);
+        let up = |port| {
+            polls.set(polls.get() + 1);
+            port != 2 || polls.get() > 4
+        };
+        assert_eq!(wait_for_peers(&[(1, 1), (2, 2)], Duration::from_secs(5), up), Ok(()));
+        assert!(polls.get() > 4);
+        assert_eq!(wait_for_peers(&[(1, 1), (2, 2)], Duration::ZERO, |port| port == 1), Err(vec![2]));
+    }
+}
@@ -0,0 +1,8 @@
+//! Scaffolding shared by the mutex and agreement simulators: command-line
+//! parsing, HTTP helpers, log files and topology checks. Each simulator
+//! keeps its own protocol; only the plumbing around it lives here.
+
+pub mod args;
+pub mod http;
+pub mod log;
+pub mod topology;
@@ -0,0 +1,62 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::PathBuf,
+    sync::Mutex,
+};
+
+/// Size-based rotation for the shared log: past `max_bytes` the log moves to
+/// `<path>.1`, older backups shift up to `<path>.<backups>`, and logging
+/// carries on in a fresh file.
+#[derive(Clone, Debug)]
+pub struct LogRotation {
+    pub path: PathBuf,
+    pub max_bytes: u64,
+    pub backups: usize,
+}
+
+impl LogRotation {
+    fn backup(&self, n: usize) -> PathBuf {
+        PathBuf::from(format!("{}.{}", self.path.display(), n))
+    }
+
+    /// Rotates if `file`, open on `path`, has reached the limit. Call with
+    /// the log mutex held so only one writer rotates.
+    pub fn rotate_if_full(&self, file: &mut File) -> io::Result<bool> {
+        if file.metadata()?.len() < self.max_bytes {
+            return Ok(false);
+        }
+        if self.backups == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            for n in (1..self.backups).rev() {
+                if self.backup(n).exists() {
+                    fs::rename(self.backup(n), self.backup(n + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.backup(1))?;
+        }
+        *file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        Ok(true)
+    }
+}
+
+/// One log line as both simulators write it, newline included.
+pub fn line(now_secs: u64, id: usize, msg: &str) -> String {
+    format!("[{}] [Node {}] {}\n", now_secs, id, msg)
+}
+
+/// Writes `line` to `file` straight away, then rotates if it filled up.
+/// A failed write is dropped, since the node carries on without its log;
+/// a failed rotation is reported as `[Node id] cannot rotate ...`.
+pub fn append(id: usize, file: &Mutex<File>, rotation: Option<&LogRotation>, line: &str) {
+    if let Ok(mut f) = file.lock() {
+        let _ = f.write_all(line.as_bytes());
+        let _ = f.flush();
+        if let Some(r) = rotation {
+            if let Err(e) = r.rotate_if_full(&mut f) {
+                eprintln!("[Node {}] cannot rotate {}: {}", id, r.path.display(), e);
+            }
+        }
+    }
+}
@@ -0,0 +1,37 @@
+//! Node-list checks run before a cluster starts from `(id, port)` pairs.
+
+use std::collections::HashSet;
+
+/// Rejects a node list with a repeated id or port: replies are routed by
+/// id, and a shared port would fail to bind.
+pub fn validate_topology(nodes: &[(usize, u16)]) -> Result<(), String> {
+    let mut ids = HashSet::new();
+    let mut ports = HashSet::new();
+    for (id, port) in nodes {
+        if !ids.insert(*id) {
+            return Err(format!("duplicate node id {}", id));
+        }
+        if !ports.insert(*port) {
+            return Err(format!("node {} reuses port {}", id, port));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn topology_rejects_duplicate_id() {
+        let err = validate_topology(&[(0, 8000), (1, 8001), (0, 8002)]).unwrap_err();
+        assert!(err.contains("duplicate node id 0"), "{}", err);
+    }
+
+    #[test]
+    fn topology_rejects_duplicate_port() {
+        let err = validate_topology(&[(0, 8000), (1, 8000)]).unwrap_err();
+        assert!(err.contains("port 8000"), "{}", err);
+        assert!(validate_topology(&[(0, 8000), (1, 8001), (2, 8002), (3, 8003)]).is_ok());
+    }
+}
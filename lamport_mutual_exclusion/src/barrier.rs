@@ -0,0 +1,87 @@
+//! `--start-barrier ID`: instead of sleeping out the staggered start
+//! delays, every node registers with node ID on `/register` once its server
+//! is up. When the last node has registered, ID sends `/start` to all of
+//! them, so the protocol begins everywhere together however slowly the
+//! processes came up. A node registering after START, say one that
+//! restarted, is told to start straight away.
+
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{LogLevel, Node};
+
+const REGISTER_RETRY: Duration = Duration::from_millis(50);
+
+/// Body of a `/register` or `/start` POST.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct BarrierMsg {
+    pub from: usize,
+}
+
+impl Node {
+    /// Coordinator side: counts `msg.from` in, and releases the barrier once
+    /// every node has registered.
+    pub fn receive_register(&self, msg: BarrierMsg) {
+        if msg.from != self.id && !self.peers.iter().any(|(nid, _)| *nid == msg.from) {
+            self.log_at(LogLevel::Error, &format!("REGISTER from unknown node {}; ignored", msg.from));
+            return;
+        }
+        let (count, release, late) = {
+            let mut st = self.state.lock().unwrap();
+            st.registered.insert(msg.from);
+            let late = st.started;
+            let release = !late && st.registered.len() == self.peers.len() + 1;
+            st.started |= release;
+            (st.registered.len(), release, late)
+        };
+        self.log(&format!("REGISTER from {} ({}/{})", msg.from, count, self.peers.len() + 1));
+        let start = serde_json::to_string(&BarrierMsg { from: self.id }).unwrap();
+        if release {
+            self.log("START: every node registered");
+            for (nid, port) in self.peers.clone() {
+                if let Err(e) = self.send_to(port, "/start", &start) {
+                    self.log_at(LogLevel::Error, &format!("Error sending START to {}: {}", nid, e));
+                }
+            }
+        } else if late && msg.from != self.id {
+            if let Err(e) = self.send_to_node(msg.from, "/start", &start) {
+                self.log_at(LogLevel::Error, &format!("Error sending START to {}: {}", msg.from, e));
+            }
+        }
+    }
+
+    pub fn receive_start(&self, msg: BarrierMsg) {
+        self.state.lock().unwrap().started = true;
+        self.log(&format!("START from {}", msg.from));
+    }
+
+    fn started(&self) -> bool {
+        self.state.lock().unwrap().started
+    }
+
+    /// Registers with `coordinator`, retrying until it is reachable, then
+    /// blocks until START arrives. Returns false if `timeout` passes first.
+    pub fn wait_for_start(&self, coordinator: usize, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let register = serde_json::to_string(&BarrierMsg { from: self.id }).unwrap();
+        while let Err(e) = self.send_to_node(coordinator, "/register", &register) {
+            if Instant::now() >= deadline {
+                self.log_at(LogLevel::Error, &format!("Timed out registering with {}: {}", coordinator, e));
+                return false;
+            }
+            thread::sleep(REGISTER_RETRY);
+        }
+        while !self.started() {
+            if Instant::now() >= deadline {
+                self.log_at(LogLevel::Error, &format!("Timed out waiting for START from {}", coordinator));
+                return false;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        true
+    }
+}
@@ -0,0 +1,86 @@
+//! `bench`: CS throughput versus cluster size over the in-process transport,
+//! so the numbers reflect the protocol's message cost rather than HTTP.
+
+use std::{
+    fs::OpenOptions,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use dc_common::args::arg_value;
+
+use crate::{in_process_cluster, Config, LogLevel};
+
+const DEFAULT_SIZES: [usize; 5] = [3, 5, 10, 20, 50];
+const BENCH_LOG: &str = "bench.log";
+
+struct Row {
+    nodes: usize,
+    acquisitions: usize,
+    elapsed: Duration,
+    messages: u64,
+}
+
+/// Every node takes resource A `per_node` times with an empty CS body.
+fn run_size(nodes: usize, per_node: usize, log_file: &Arc<Mutex<std::fs::File>>) -> Row {
+    let cfg = Config {
+        log_level: LogLevel::Error,
+        max_queue_len: nodes.max(64),
+        // Contention, not loss, is what makes waits long here.
+        attempt_timeout: Duration::from_secs(120),
+        ..Config::default()
+    };
+    let ids: Vec<usize> = (0..nodes).collect();
+    let (cluster, transport) = in_process_cluster(&ids, &cfg, log_file.clone());
+
+    let started = Instant::now();
+    let workers: Vec<_> = cluster
+        .iter()
+        .cloned()
+        .map(|n| thread::spawn(move || (0..per_node).filter(|_| n.enter_cs_with("A", || {})).count()))
+        .collect();
+    let acquisitions = workers.into_iter().map(|h| h.join().unwrap()).sum();
+    Row { nodes, acquisitions, elapsed: started.elapsed(), messages: transport.sent() }
+}
+
+/// Entry point for `bench [--sizes 3,5,10] [--per-node K]`; returns the
+/// process exit code.
+pub fn run(args: &[String]) -> i32 {
+    let mut sizes = DEFAULT_SIZES.to_vec();
+    let mut per_node = 2;
+    let mut it = args.iter().skip(2).cloned();
+    while let Some(arg) = it.next() {
+        match arg.as_str() {
+            "--sizes" => {
+                let v: String = arg_value(&mut it, &arg);
+                sizes = v
+                    .split(',')
+                    .map(|s| s.trim().parse().unwrap_or_else(|_| panic!("--sizes expects N,N,..., got {:?}", v)))
+                    .collect();
+            }
+            "--per-node" => per_node = arg_value(&mut it, &arg),
+            other => {
+                eprintln!("unknown argument: {}", other);
+                return 2;
+            }
+        }
+    }
+
+    let log_file = Arc::new(Mutex::new(OpenOptions::new().create(true).append(true).open(BENCH_LOG).unwrap()));
+    println!("{:>6} {:>6} {:>10} {:>12} {:>10}", "nodes", "cs", "secs", "cs/sec", "msgs/cs");
+    for n in sizes {
+        let r = run_size(n, per_node, &log_file);
+        let secs = r.elapsed.as_secs_f64();
+        let per_cs = if r.acquisitions > 0 { r.messages as f64 / r.acquisitions as f64 } else { 0.0 };
+        println!(
+            "{:>6} {:>6} {:>10.2} {:>12.1} {:>10.1}",
+            r.nodes,
+            r.acquisitions,
+            secs,
+            r.acquisitions as f64 / secs,
+            per_cs
+        );
+    }
+    0
+}
@@ -0,0 +1,51 @@
+//! `/cancel?resource=R`: gives up on a request while `enter_cs` is still
+//! waiting for it. The waiting thread does the withdrawing itself, at its
+//! next check, so a cancel can never race it into the CS: it drops our
+//! entry, broadcasts a CANCEL (a RELEASE for a request that was never
+//! held) and returns `NodeError::Cancelled` without running the body.
+
+use std::cmp::Reverse;
+
+use crate::{LogLevel, Node, ReleaseMsg};
+
+impl Node {
+    /// Asks the `enter_cs` waiting on `resource` to give up. False if
+    /// nothing is waiting: no request is out, or we already hold it.
+    pub fn cancel_request(&self, resource: &str) -> bool {
+        if self.in_cs.lock().unwrap().get(resource).copied().unwrap_or(false) {
+            return false;
+        }
+        let mut st = self.state.lock().unwrap();
+        if !st.in_flight.get(resource).copied().unwrap_or(false) {
+            return false;
+        }
+        st.cancelled.insert(resource.to_string())
+    }
+
+    /// Whether a cancel is pending for `resource`, clearing it.
+    pub fn take_cancel(&self, resource: &str) -> bool {
+        self.state.lock().unwrap().cancelled.remove(resource)
+    }
+
+    /// Drops our request `ts` and tells every peer to drop it too.
+    pub fn withdraw_request(&self, resource: &str, ts: u64) {
+        self.drop_own_request(resource, ts);
+        self.log(&format!("Cancelled REQUEST ts={} for resource={}; broadcasting CANCEL", ts, resource));
+        self.broadcast_withdrawal("/receive_cancel", "CANCEL", resource, ts);
+    }
+
+    /// Like a RELEASE, and remembered the same way so a REQUEST that
+    /// arrives late isn't queued, but it doesn't count as the sender having
+    /// had its turn.
+    pub fn receive_cancel(&self, msg: ReleaseMsg) {
+        {
+            let mut st = self.state.lock().unwrap();
+            if let Some(q) = st.request_queues.get_mut(&msg.resource) {
+                q.retain(|Reverse(e)| *e != (msg.ts, msg.from));
+            }
+            st.readers.remove(&(msg.resource.clone(), msg.ts, msg.from));
+            st.released.insert((msg.resource.clone(), msg.ts, msg.from));
+        }
+        self.log_at(LogLevel::Debug, &format!("Received CANCEL from {} ts={} for resource={}", msg.from, msg.ts, msg.resource));
+    }
+}
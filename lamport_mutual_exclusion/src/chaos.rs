@@ -0,0 +1,75 @@
+//! Runtime fault injection on outbound sends, driven through `/chaos`, so a
+//! partition can be opened and healed mid-run.
+//!
+//! Rules are one-way: they apply to what this node sends to a peer. A full
+//! partition between two nodes needs the rule set on both of them.
+
+use std::{collections::BTreeMap, thread, time::Duration};
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{error::NodeError, LogLevel, Node};
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PeerChaos {
+    #[serde(default)]
+    pub delay_ms: u64,
+    /// Fraction of sends, 0.0..=1.0, that fail as if lost.
+    #[serde(default)]
+    pub drop_rate: f64,
+    #[serde(default)]
+    pub partitioned: bool,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ChaosConfig {
+    pub peers: BTreeMap<usize, PeerChaos>,
+}
+
+/// Body of a `/chaos` POST. Omitted fields are cleared, so `{"peer": 2}`
+/// heals everything toward peer 2.
+#[derive(Deserialize)]
+struct ChaosUpdate {
+    peer: usize,
+    #[serde(flatten)]
+    rules: PeerChaos,
+}
+
+impl Node {
+    /// Sends through the transport unless a chaos rule for the peer on
+    /// `port` delays, drops or blocks it.
+    pub fn send_to(&self, port: u16, path: &str, payload: &str) -> Result<(), NodeError> {
+        let peer = self.peers.iter().find(|(_, p)| *p == port).map(|(nid, _)| *nid);
+        let rules = peer.and_then(|nid| self.chaos.lock().unwrap().peers.get(&nid).cloned());
+        if let Some(r) = rules {
+            if r.partitioned {
+                return Err(NodeError::Delivery(format!("chaos: partitioned from port {}", port)));
+            }
+            if r.drop_rate > 0.0 && rand::thread_rng().gen_bool(r.drop_rate.min(1.0)) {
+                return Err(NodeError::Delivery(format!("chaos: dropped send to port {}", port)));
+            }
+            thread::sleep(Duration::from_millis(r.delay_ms));
+        }
+        self.transport.send(port, path, payload)
+    }
+
+    /// Applies a `/chaos` update, if `body` has one, and returns the rules now
+    /// in force as JSON.
+    pub fn update_chaos(&self, body: &str) -> Result<String, String> {
+        let mut chaos = self.chaos.lock().unwrap();
+        if !body.trim().is_empty() {
+            let update: ChaosUpdate = serde_json::from_str(body).map_err(|e| format!("malformed chaos update: {}", e))?;
+            if !self.peers.iter().any(|(nid, _)| *nid == update.peer) {
+                return Err(format!("node {} has no peer {}", self.id, update.peer));
+            }
+            self.log_at(LogLevel::Info, &format!("CHAOS toward {}: {:?}", update.peer, update.rules));
+            if update.rules == PeerChaos::default() {
+                chaos.peers.remove(&update.peer);
+            } else {
+                chaos.peers.insert(update.peer, update.rules);
+            }
+        }
+        Ok(serde_json::to_string(&*chaos).unwrap())
+    }
+}
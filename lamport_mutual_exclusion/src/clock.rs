@@ -0,0 +1,71 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[cfg(test)]
+use std::sync::Mutex;
+
+/// Source of "now" for log timestamps, CS wait times and timeouts.
+pub trait Clock: Send + Sync {
+    /// Time since the Unix epoch.
+    fn now(&self) -> Duration;
+
+    fn elapsed_since(&self, start: Duration) -> Duration {
+        self.now().saturating_sub(start)
+    }
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap()
+    }
+}
+
+/// A node's Lamport timestamp. It must never run backwards, so every write
+/// goes through `set`, which checks that in debug builds.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MonotonicClock(u64);
+
+impl MonotonicClock {
+    pub fn get(self) -> u64 {
+        self.0
+    }
+
+    pub fn set(&mut self, ts: u64) {
+        debug_assert!(ts >= self.0, "Lamport timestamp went back from {} to {}", self.0, ts);
+        self.0 = ts;
+    }
+
+    /// A local event: one step forward. Returns the new time.
+    pub fn tick(&mut self) -> u64 {
+        self.set(self.0 + 1);
+        self.0
+    }
+
+    /// Receiving a message stamped `seen`: past both it and our own time.
+    pub fn witness(&mut self, seen: u64) -> u64 {
+        self.set(self.0.max(seen) + 1);
+        self.0
+    }
+}
+
+/// A clock that only moves when told to.
+#[cfg(test)]
+#[derive(Default)]
+pub struct MockClock {
+    now: Mutex<Duration>,
+}
+
+#[cfg(test)]
+impl MockClock {
+    pub fn advance(&self, by: Duration) {
+        *self.now.lock().unwrap() += by;
+    }
+}
+
+#[cfg(test)]
+impl Clock for MockClock {
+    fn now(&self) -> Duration {
+        *self.now.lock().unwrap()
+    }
+}
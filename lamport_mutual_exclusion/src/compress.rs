@@ -0,0 +1,35 @@
+//! `--gzip`: the HTTP transport gzips request bodies of at least
+//! `GZIP_MIN_BYTES` and marks them `Content-Encoding: gzip`. Small messages
+//! go out as they are, since gzip's header alone would outweigh the saving;
+//! REPLY batches and snapshots are where it pays off. A server inflates any
+//! gzip body whatever its own setting, so nodes may mix the flag freely.
+
+use std::io::{Read, Write};
+
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+
+use crate::error::NodeError;
+
+pub const GZIP: &str = "gzip";
+pub const GZIP_MIN_BYTES: usize = 1024;
+
+pub fn gzip(bytes: &[u8]) -> Result<Vec<u8>, NodeError> {
+    let mut enc = GzEncoder::new(Vec::new(), Compression::fast());
+    enc.write_all(bytes).and_then(|_| enc.finish()).map_err(|e| NodeError::Wire(format!("gzip: {}", e)))
+}
+
+pub fn gunzip(bytes: &[u8]) -> Result<Vec<u8>, NodeError> {
+    let mut out = vec![];
+    GzDecoder::new(bytes).read_to_end(&mut out).map_err(|e| NodeError::Wire(format!("gunzip: {}", e)))?;
+    Ok(out)
+}
+
+/// A request body as its sender encoded it, given its Content-Encoding.
+pub fn decode_body(content_encoding: Option<&str>, body: Vec<u8>) -> Result<Vec<u8>, NodeError> {
+    match content_encoding.map(str::trim) {
+        None | Some("") => Ok(body),
+        Some(enc) if enc.eq_ignore_ascii_case("identity") => Ok(body),
+        Some(enc) if enc.eq_ignore_ascii_case(GZIP) => gunzip(&body),
+        Some(enc) => Err(NodeError::Wire(format!("unsupported Content-Encoding {:?}", enc))),
+    }
+}
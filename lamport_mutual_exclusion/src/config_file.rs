@@ -0,0 +1,158 @@
+//! `--config FILE`: topology, quorum and timeouts from a JSON file, e.g.
+//!
+//! ```json
+//! { "nodes": [{ "id": 0, "port": 8000 }, { "id": 1, "port": 8001 }],
+//!   "resources": ["A", "B"], "quorum": "majority", "attempt_timeout_ms": 2000 }
+//! ```
+//!
+//! The file is checked as a whole before any of it is applied, so a typo
+//! gets every problem listed in plain words rather than the first serde
+//! error from deep inside a struct. `resources`, if given, may only name
+//! the compiled-in resources.
+
+use std::time::Duration;
+
+use dc_common::topology::validate_topology;
+use serde_json::Value;
+
+use crate::{quorum::QuorumPolicy, Config, RESOURCES};
+
+const TIMEOUT_KEYS: [&str; 3] = ["attempt_timeout_ms", "request_timeout_ms", "connect_timeout_ms"];
+const KNOWN_KEYS: [&str; 6] = ["nodes", "resources", "quorum", "attempt_timeout_ms", "request_timeout_ms", "connect_timeout_ms"];
+
+/// `(id, port)` of every well-formed entry in `nodes`, and a problem for
+/// each one that isn't.
+fn parse_nodes(v: &Value, problems: &mut Vec<String>) -> Vec<(usize, u16)> {
+    let Some(entries) = v.as_array() else {
+        problems.push("'nodes' must be a list of {\"id\", \"port\"} objects".to_string());
+        return vec![];
+    };
+    if entries.is_empty() {
+        problems.push("node list is empty".to_string());
+    }
+    let mut nodes = vec![];
+    for (i, entry) in entries.iter().enumerate() {
+        let id = entry.get("id").and_then(Value::as_u64);
+        // Name the node by id when it has one, else by its place in the list.
+        let name = id.map_or_else(|| format!("node #{}", i + 1), |id| format!("node {}", id));
+        if !entry.is_object() {
+            problems.push(format!("{} is not an object", name));
+            continue;
+        }
+        match entry.get("id") {
+            None => problems.push(format!("{} missing 'id'", name)),
+            Some(_) if id.is_none() => problems.push(format!("{} has a non-numeric 'id'", name)),
+            _ => {}
+        }
+        let port = match entry.get("port") {
+            None => {
+                problems.push(format!("{} missing 'port'", name));
+                None
+            }
+            Some(p) => {
+                let port = p.as_u64().filter(|p| (1..=u16::MAX as u64).contains(p));
+                if port.is_none() {
+                    problems.push(format!("{} has 'port' {} outside 1..=65535", name, p));
+                }
+                port
+            }
+        };
+        if let (Some(id), Some(port)) = (id, port) {
+            nodes.push((id as usize, port as u16));
+        }
+    }
+    if let Err(e) = validate_topology(&nodes) {
+        problems.push(e);
+    }
+    nodes
+}
+
+/// Every problem with `v` as a config file; empty if it can be applied.
+pub fn validate_config(v: &Value) -> Vec<String> {
+    let mut problems = vec![];
+    let Some(obj) = v.as_object() else {
+        return vec!["config must be a JSON object".to_string()];
+    };
+    for key in obj.keys().filter(|k| !KNOWN_KEYS.contains(&k.as_str())) {
+        problems.push(format!("unknown key '{}'", key));
+    }
+    let nodes = match obj.get("nodes") {
+        Some(n) => parse_nodes(n, &mut problems),
+        None => {
+            problems.push("missing 'nodes'".to_string());
+            vec![]
+        }
+    };
+    if let Some(r) = obj.get("resources") {
+        match r.as_array() {
+            None => problems.push("'resources' must be a list of names".to_string()),
+            Some(list) if list.is_empty() => problems.push("resource list is empty".to_string()),
+            Some(list) => {
+                for name in list {
+                    if !name.as_str().is_some_and(|n| RESOURCES.contains(&n)) {
+                        problems.push(format!("unknown resource {} (known: {})", name, RESOURCES.join(", ")));
+                    }
+                }
+            }
+        }
+    }
+    if let Some(q) = obj.get("quorum") {
+        let peers = nodes.len().saturating_sub(1);
+        match (q.as_u64(), q.as_str()) {
+            (Some(n), _) if n as usize > peers => {
+                problems.push(format!("quorum {} larger than peer count {}", n, peers));
+            }
+            (Some(_), _) => {}
+            (None, Some(s)) => match s.parse::<QuorumPolicy>() {
+                Ok(QuorumPolicy::Fixed(n)) if n > peers => {
+                    problems.push(format!("quorum {} larger than peer count {}", n, peers));
+                }
+                Ok(_) => {}
+                Err(()) => problems.push(format!("quorum {:?} is not all, majority, weighted or fixed:N", s)),
+            },
+            _ => problems.push("'quorum' must be a number or a policy name".to_string()),
+        }
+    }
+    for key in TIMEOUT_KEYS {
+        if let Some(t) = obj.get(key) {
+            if t.as_u64().is_none_or(|ms| ms == 0) {
+                problems.push(format!("'{}' must be a positive number of milliseconds, got {}", key, t));
+            }
+        }
+    }
+    problems
+}
+
+/// Reads and checks `path`, returning the problems if there are any.
+pub fn load(path: &str) -> Result<Value, Vec<String>> {
+    let text = std::fs::read_to_string(path).map_err(|e| vec![format!("cannot read: {}", e)])?;
+    let v: Value = serde_json::from_str(&text).map_err(|e| vec![format!("not valid JSON: {}", e)])?;
+    let problems = validate_config(&v);
+    if problems.is_empty() {
+        Ok(v)
+    } else {
+        Err(problems)
+    }
+}
+
+impl Config {
+    /// Applies a config that passed `validate_config`.
+    pub fn apply_file(&mut self, v: &Value) {
+        self.nodes = parse_nodes(&v["nodes"], &mut vec![]);
+        match v.get("quorum") {
+            Some(Value::Number(n)) => self.quorum = QuorumPolicy::Fixed(n.as_u64().unwrap_or(0) as usize),
+            Some(Value::String(s)) => self.quorum = s.parse().unwrap_or(QuorumPolicy::All),
+            _ => {}
+        }
+        let ms = |key: &str| v.get(key).and_then(Value::as_u64);
+        if let Some(t) = ms("attempt_timeout_ms") {
+            self.attempt_timeout = Duration::from_millis(t);
+        }
+        if let Some(t) = ms("request_timeout_ms") {
+            self.request_timeout_ms = t;
+        }
+        if let Some(t) = ms("connect_timeout_ms") {
+            self.connect_timeout_ms = t;
+        }
+    }
+}
@@ -0,0 +1,117 @@
+//! `--workload contention`: every node asks for resource A back to back,
+//! with an empty CS, for `--duration-secs`. That is the worst case for the
+//! protocol, so entries/sec here is the number to benchmark against. Like
+//! `--self-test` it runs in-process; an occupancy counter inside the CS is
+//! the oracle, and any overlap makes the run fail.
+
+use std::{
+    path::Path,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{in_process_cluster, open_log, Config, LogLevel};
+
+const RESOURCE: &str = "A";
+const CONTENTION_LOG: &str = "contention.log";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Workload {
+    /// `--rounds` of A then B, with think times in between.
+    #[default]
+    Rounds,
+    Contention,
+}
+
+impl FromStr for Workload {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s.to_ascii_lowercase().as_str() {
+            "rounds" => Ok(Workload::Rounds),
+            "contention" => Ok(Workload::Contention),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Workload {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Workload::Rounds => "rounds",
+            Workload::Contention => "contention",
+        }
+    }
+}
+
+pub struct Report {
+    pub nodes: usize,
+    pub entries: usize,
+    pub elapsed: Duration,
+    pub violations: usize,
+}
+
+impl Report {
+    pub fn entries_per_sec(&self) -> f64 {
+        self.entries as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+/// Runs `ids` in-process under `cfg` until `duration` has passed, counting
+/// entries and every time a node found someone already inside the CS.
+pub fn measure(ids: &[usize], cfg: &Config, duration: Duration, log_file: Arc<Mutex<std::fs::File>>) -> Report {
+    let (cluster, _) = in_process_cluster(ids, cfg, log_file);
+    let inside = Arc::new(AtomicUsize::new(0));
+    let violations = Arc::new(AtomicUsize::new(0));
+    let started = Instant::now();
+    let workers: Vec<_> = cluster
+        .into_iter()
+        .map(|n| {
+            let (inside, violations) = (inside.clone(), violations.clone());
+            thread::spawn(move || {
+                let mut entries = 0;
+                while started.elapsed() < duration {
+                    let entered = n.enter_cs_with(RESOURCE, || {
+                        let others = inside.fetch_add(1, Ordering::SeqCst);
+                        if others > 0 {
+                            violations.fetch_add(1, Ordering::SeqCst);
+                            n.log_at(LogLevel::Error, &format!("CONTENTION_VIOLATION resource={}: {} other node(s) inside", RESOURCE, others));
+                        }
+                        // Give an overlapping entry the chance to show up.
+                        thread::yield_now();
+                        inside.fetch_sub(1, Ordering::SeqCst);
+                    });
+                    entries += entered as usize;
+                }
+                entries
+            })
+        })
+        .collect();
+    let entries = workers.into_iter().map(|h| h.join().unwrap()).sum();
+    Report { nodes: ids.len(), entries, elapsed: started.elapsed(), violations: violations.load(Ordering::SeqCst) }
+}
+
+/// Entry point for `--workload contention`; returns the process exit code.
+pub fn run(cfg: &Config) -> i32 {
+    let ids: Vec<usize> = cfg.nodes.iter().map(|(id, _)| *id).collect();
+    let log_file = open_log(Path::new(CONTENTION_LOG));
+    let r = measure(&ids, cfg, cfg.duration, log_file);
+    println!(
+        "CONTENTION {} node(s), {} entries in {:.2}s: {:.1} entries/sec, {} violation(s)",
+        r.nodes,
+        r.entries,
+        r.elapsed.as_secs_f64(),
+        r.entries_per_sec(),
+        r.violations
+    );
+    if r.violations == 0 {
+        0
+    } else {
+        1
+    }
+}
@@ -0,0 +1,152 @@
+//! `--algorithm coordinator`: each resource is owned by one node, picked by
+//! consistent hashing of its name, which grants it to requesters one at a
+//! time. Three messages per entry instead of a broadcast, at the price of a
+//! single point of failure per resource.
+
+use std::{
+    collections::VecDeque,
+    thread,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{error::NodeError, LogLevel, Node};
+
+/// Points each node places on the hash ring, so resources spread evenly
+/// even over a handful of nodes.
+const VNODES_PER_NODE: u32 = 16;
+const GRANT_WAIT_TIMEOUT: Duration = Duration::from_secs(6);
+
+/// A REQUEST, GRANT or RELEASE for `resource`, depending on the endpoint.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct CoordMsg {
+    pub from: usize,
+    pub resource: String,
+}
+
+/// The coordinator's book for one resource.
+#[derive(Debug, Default)]
+pub struct Grants {
+    pub holder: Option<usize>,
+    pub waiting: VecDeque<usize>,
+}
+
+/// FNV-1a, rather than `DefaultHasher`, so every build agrees on the ring.
+fn fnv1a(s: &str) -> u64 {
+    s.bytes().fold(0xcbf2_9ce4_8422_2325, |h, b| (h ^ b as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+/// The node in `ids` owning `resource`: the first ring point at or after the
+/// resource's hash, wrapping around. Adding or removing a node only moves
+/// the resources next to its points.
+pub fn ring_owner(resource: &str, ids: &[usize]) -> Option<usize> {
+    let mut ring: Vec<(u64, usize)> =
+        ids.iter().flat_map(|&id| (0..VNODES_PER_NODE).map(move |v| (fnv1a(&format!("node-{}#{}", id, v)), id))).collect();
+    ring.sort();
+    let h = fnv1a(resource);
+    ring.iter().find(|(p, _)| *p >= h).or_else(|| ring.first()).map(|(_, id)| *id)
+}
+
+impl Node {
+    /// The node that grants `resource`, over this node and its peers.
+    pub fn coordinator_for(&self, resource: &str) -> usize {
+        let mut ids: Vec<usize> = self.peers.iter().map(|(nid, _)| *nid).collect();
+        ids.push(self.id);
+        ring_owner(resource, &ids).expect("ring always holds this node")
+    }
+
+    fn send_coord(&self, to: usize, path: &str, msg: &CoordMsg) -> Result<(), NodeError> {
+        self.send_to_node(to, path, &serde_json::to_string(msg)?)
+    }
+
+    pub fn receive_coord_request(&self, msg: CoordMsg) {
+        self.log_at(LogLevel::Debug, &format!("Received COORD_REQUEST from {} for resource={}", msg.from, msg.resource));
+        let grant = {
+            let mut st = self.state.lock().unwrap();
+            let g = st.grants.entry(msg.resource.clone()).or_default();
+            match g.holder {
+                None => {
+                    g.holder = Some(msg.from);
+                    true
+                }
+                Some(h) => {
+                    if h != msg.from && !g.waiting.contains(&msg.from) {
+                        g.waiting.push_back(msg.from);
+                    }
+                    false
+                }
+            }
+        };
+        if grant {
+            self.grant(msg.from, &msg.resource);
+        }
+    }
+
+    /// Frees `resource` if `from` held it, or withdraws `from`'s queued
+    /// request if it gave up waiting, and grants to the next in line.
+    pub fn receive_coord_release(&self, msg: CoordMsg) {
+        self.log_at(LogLevel::Debug, &format!("Received COORD_RELEASE from {} for resource={}", msg.from, msg.resource));
+        let next = {
+            let mut st = self.state.lock().unwrap();
+            let g = st.grants.entry(msg.resource.clone()).or_default();
+            if g.holder == Some(msg.from) {
+                g.holder = g.waiting.pop_front();
+                g.holder
+            } else {
+                g.waiting.retain(|nid| *nid != msg.from);
+                None
+            }
+        };
+        if let Some(nid) = next {
+            self.grant(nid, &msg.resource);
+        }
+    }
+
+    pub fn receive_coord_grant(&self, msg: CoordMsg) {
+        self.log_at(LogLevel::Debug, &format!("Received COORD_GRANT from {} for resource={}", msg.from, msg.resource));
+        self.state.lock().unwrap().granted.insert(msg.resource);
+    }
+
+    /// A grant that can't be delivered is released on the grantee's behalf,
+    /// so the resource moves on instead of staying held by nobody.
+    fn grant(&self, to: usize, resource: &str) {
+        let msg = CoordMsg { from: self.id, resource: resource.to_string() };
+        if let Err(e) = self.send_coord(to, "/coord_grant", &msg) {
+            self.log_at(LogLevel::Error, &format!("Error sending COORD_GRANT to {}: {}", to, e));
+            self.receive_coord_release(CoordMsg { from: to, resource: resource.to_string() });
+        }
+    }
+
+    fn release_to_coordinator(&self, coordinator: usize, resource: &str) {
+        let msg = CoordMsg { from: self.id, resource: resource.to_string() };
+        if let Err(e) = self.send_coord(coordinator, "/coord_release", &msg) {
+            self.log_at(LogLevel::Error, &format!("Error sending COORD_RELEASE to {}: {}", coordinator, e));
+        }
+    }
+
+    pub fn enter_cs_coordinator<F: FnOnce()>(&self, resource: &str, body: F) -> Result<(), NodeError> {
+        let coordinator = self.coordinator_for(resource);
+        let requested_at = self.clock.now();
+        self.state.lock().unwrap().granted.remove(resource);
+        self.log_at(LogLevel::Debug, &format!("Requesting resource={} from coordinator {}", resource, coordinator));
+        let msg = CoordMsg { from: self.id, resource: resource.to_string() };
+        self.send_coord(coordinator, "/coord_request", &msg)?;
+        loop {
+            if self.state.lock().unwrap().granted.remove(resource) {
+                let waited = self.clock.elapsed_since(requested_at);
+                self.record_cs_entry(resource, waited);
+                self.run_cs_body(resource, body);
+                self.release_to_coordinator(coordinator, resource);
+                return Ok(());
+            }
+            if self.clock.elapsed_since(requested_at) > GRANT_WAIT_TIMEOUT {
+                self.log("Timeout waiting for grant");
+                self.metrics.lock().unwrap().timeouts += 1;
+                self.release_to_coordinator(coordinator, resource);
+                return Err(NodeError::Timeout);
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+}
@@ -0,0 +1,149 @@
+//! `--print-config`: the configuration a node actually runs with, after
+//! defaults and flags are resolved.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::{metrics::MetricsFormat, quorum::QuorumPolicy, transport::TransportKind, Algorithm, Config, RESOURCES};
+
+/// Enum values are printed the way their flags spell them.
+#[derive(Serialize)]
+pub struct EffectiveConfig {
+    /// `(id, port)` of every node this process runs.
+    pub nodes: Vec<(usize, u16)>,
+    pub resources: Vec<String>,
+    pub priorities: BTreeMap<String, u8>,
+    pub algorithm: String,
+    pub transport: String,
+    pub multicast_group: Option<String>,
+    pub wire_format: String,
+    pub gzip: bool,
+    pub quorum: String,
+    pub weights: BTreeMap<usize, u32>,
+    pub node_priorities: BTreeMap<usize, u8>,
+    pub node_resources: BTreeMap<usize, Vec<String>>,
+    pub priority_inheritance: bool,
+    pub max_consecutive_entries: Option<u32>,
+    pub lease_grace_ms: Option<u64>,
+    pub stuck_head_ms: Option<u64>,
+    pub check_invariants_ms: Option<u64>,
+    pub invariant_panic: bool,
+    pub heartbeat_ms: Option<u64>,
+    pub rtt_probe_ms: Option<u64>,
+    pub suspect_after_ms: u64,
+    pub dead_after_ms: u64,
+    pub fifo: bool,
+    pub vector_clocks: bool,
+    pub fast_path: bool,
+    pub generation: u64,
+    pub max_queue_len: usize,
+    pub log_level: String,
+    pub log_sink: String,
+    pub log_flush: String,
+    pub max_log_bytes: Option<u64>,
+    pub log_backups: usize,
+    pub per_node_logs: bool,
+    pub attempt_timeout_ms: u64,
+    pub max_rebroadcasts: u32,
+    pub rebroadcast_jitter: String,
+    pub request_timeout_ms: u64,
+    pub connect_timeout_ms: u64,
+    pub send_workers: usize,
+    pub server_threads: usize,
+    pub pool_max_idle_per_host: usize,
+    pub rounds: usize,
+    pub workload: String,
+    pub duration_secs: u64,
+    pub think_ms: u64,
+    pub think_dist: Option<String>,
+    pub start_delay_ms: (u64, u64),
+    pub start_barrier: Option<usize>,
+    pub gap_ms: (u64, u64),
+    pub seed: Option<u64>,
+    pub acquire_both: bool,
+    pub counter_file: Option<String>,
+    pub record: bool,
+    pub deadline_secs: Option<u64>,
+    pub metrics_format: String,
+}
+
+impl Config {
+    pub fn effective(&self, nodes: &[(usize, u16)]) -> EffectiveConfig {
+        EffectiveConfig {
+            nodes: nodes.to_vec(),
+            resources: RESOURCES.iter().map(|r| r.to_string()).collect(),
+            priorities: self.priorities.iter().map(|(r, p)| (r.clone(), *p)).collect(),
+            algorithm: match self.algorithm {
+                Algorithm::Lamport => "lamport",
+                Algorithm::TokenRing => "token_ring",
+                Algorithm::Coordinator => "coordinator",
+            }
+            .to_string(),
+            transport: match self.transport {
+                TransportKind::Http => "http",
+                TransportKind::Multicast => "multicast",
+            }
+            .to_string(),
+            multicast_group: (self.transport == TransportKind::Multicast).then(|| self.multicast_group.to_string()),
+            wire_format: self.wire_format.as_str().to_string(),
+            gzip: self.gzip,
+            quorum: match &self.quorum {
+                QuorumPolicy::All => "all".to_string(),
+                QuorumPolicy::Majority => "majority".to_string(),
+                QuorumPolicy::Fixed(n) => format!("fixed:{}", n),
+                QuorumPolicy::WeightedMajority => "weighted".to_string(),
+            },
+            weights: self.weights.iter().map(|(id, w)| (*id, *w)).collect(),
+            node_priorities: self.node_priorities.iter().map(|(id, p)| (*id, *p)).collect(),
+            node_resources: self.node_resources.iter().map(|(id, r)| (*id, r.clone())).collect(),
+            priority_inheritance: self.priority_inheritance,
+            max_consecutive_entries: self.max_consecutive_entries,
+            lease_grace_ms: self.lease_grace.map(|g| g.as_millis() as u64),
+            stuck_head_ms: self.stuck_head_after.map(|t| t.as_millis() as u64),
+            check_invariants_ms: self.check_invariants_every.map(|t| t.as_millis() as u64),
+            invariant_panic: self.invariant_panic,
+            heartbeat_ms: self.heartbeat_interval.map(|t| t.as_millis() as u64),
+            rtt_probe_ms: self.rtt_probe_every.map(|t| t.as_millis() as u64),
+            suspect_after_ms: self.suspect_after.as_millis() as u64,
+            dead_after_ms: self.dead_after.as_millis() as u64,
+            fifo: self.fifo,
+            vector_clocks: self.vector_clocks,
+            fast_path: self.fast_path,
+            generation: self.generation,
+            max_queue_len: self.max_queue_len,
+            log_level: self.log_level.as_str().to_string(),
+            log_sink: self.log_sink.describe(),
+            log_flush: self.log_buffer.policy.describe(),
+            max_log_bytes: self.max_log_bytes,
+            log_backups: self.log_backups,
+            per_node_logs: self.per_node_logs,
+            attempt_timeout_ms: self.attempt_timeout.as_millis() as u64,
+            max_rebroadcasts: self.max_rebroadcasts,
+            rebroadcast_jitter: self.rebroadcast_jitter.describe(),
+            request_timeout_ms: self.request_timeout_ms,
+            connect_timeout_ms: self.connect_timeout_ms,
+            send_workers: self.send_workers,
+            server_threads: self.server_threads,
+            pool_max_idle_per_host: self.pool_max_idle_per_host,
+            rounds: self.rounds,
+            workload: self.workload.as_str().to_string(),
+            duration_secs: self.duration.as_secs(),
+            think_ms: self.think_time.as_millis() as u64,
+            think_dist: self.think_dist.map(|d| d.describe()),
+            start_delay_ms: self.start_delay_ms,
+            start_barrier: self.start_barrier,
+            gap_ms: self.gap_ms,
+            seed: self.seed,
+            acquire_both: self.acquire_both,
+            counter_file: self.counter_file.clone(),
+            record: self.record,
+            deadline_secs: self.deadline.map(|d| d.as_secs()),
+            metrics_format: match self.metrics_format {
+                MetricsFormat::Json => "json",
+                MetricsFormat::Prometheus => "prometheus",
+            }
+            .to_string(),
+        }
+    }
+}
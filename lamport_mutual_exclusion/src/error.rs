@@ -0,0 +1,50 @@
+use std::fmt;
+
+/// Why a send or a CS entry failed, so a caller can react to the kind of
+/// failure; only the top-level tasks turn it into a log line.
+#[derive(Debug)]
+pub enum NodeError {
+    /// The HTTP client couldn't reach the peer or lost the connection.
+    Transport(reqwest::Error),
+    /// Sent but not delivered: a non-2xx answer, a chaos rule, a multicast
+    /// socket error or an in-process inbox that's gone.
+    Delivery(String),
+    Serialize(serde_json::Error),
+    /// A body that isn't valid in its `--wire-format`.
+    Wire(String),
+    UnknownResource(String),
+    /// `enter_cs` from a thread already inside the CS for that resource.
+    Reentrant(String),
+    Timeout,
+    /// `cancel_request` withdrew the request before it was granted.
+    Cancelled,
+}
+
+impl fmt::Display for NodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NodeError::Transport(e) => write!(f, "{}", e),
+            NodeError::Delivery(msg) => write!(f, "{}", msg),
+            NodeError::Serialize(e) => write!(f, "{}", e),
+            NodeError::Wire(msg) => write!(f, "{}", msg),
+            NodeError::UnknownResource(r) => write!(f, "unknown resource={}", r),
+            NodeError::Reentrant(r) => write!(f, "already inside the CS for resource={}", r),
+            NodeError::Timeout => write!(f, "timed out"),
+            NodeError::Cancelled => write!(f, "cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for NodeError {}
+
+impl From<reqwest::Error> for NodeError {
+    fn from(e: reqwest::Error) -> Self {
+        NodeError::Transport(e)
+    }
+}
+
+impl From<serde_json::Error> for NodeError {
+    fn from(e: serde_json::Error) -> Self {
+        NodeError::Serialize(e)
+    }
+}
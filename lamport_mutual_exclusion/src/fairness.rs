@@ -0,0 +1,43 @@
+//! `--max-consecutive-entries`: once a node has entered a resource that
+//! many times in a row, it holds off its next REQUEST while anyone else has
+//! one queued, until another node's RELEASE shows it had its turn.
+//!
+//! The yield happens before requesting rather than in `can_enter_cs`: a node
+//! that sat at the head of the queue refusing to enter would block everyone
+//! queued behind its request too.
+
+use std::{cmp::Reverse, thread, time::Duration};
+
+use crate::{LogLevel, Node};
+
+impl Node {
+    /// Counts one of our own entries into `resource`.
+    pub fn note_own_entry(&self, resource: &str) {
+        *self.state.lock().unwrap().consecutive.entry(resource.to_string()).or_default() += 1;
+    }
+
+    /// Whether we've hit the cap on `resource` and someone else is waiting.
+    pub fn must_yield(&self, resource: &str) -> bool {
+        let Some(cap) = self.max_consecutive_entries else { return false };
+        let st = self.state.lock().unwrap();
+        st.consecutive.get(resource).copied().unwrap_or(0) >= cap
+            && st.request_queues.get(resource).is_some_and(|q| q.iter().any(|Reverse((_, from))| *from != self.id))
+    }
+
+    /// Waits out `must_yield`, for at most one attempt timeout so a waiter
+    /// that died with its request queued can't park us forever.
+    pub fn yield_turn(&self, resource: &str) {
+        if !self.must_yield(resource) {
+            return;
+        }
+        self.log_at(LogLevel::Debug, &format!("YIELD resource={}: consecutive entry cap reached", resource));
+        let started = self.clock.now();
+        while self.must_yield(resource) {
+            if self.clock.elapsed_since(started) > self.attempt_timeout {
+                self.log_at(LogLevel::Info, &format!("YIELD resource={}: nobody took the turn; requesting anyway", resource));
+                return;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+}
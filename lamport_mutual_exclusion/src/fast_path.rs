@@ -0,0 +1,141 @@
+//! `--fast-path`: an uncontended writer asks one leader for the resource
+//! instead of broadcasting, three messages instead of 3(N-1). The leader,
+//! the lowest node id, grants only while it has no Lamport REQUEST queued
+//! for the resource and holds back its REPLY to any REQUEST arriving while
+//! a grant is out, so a Lamport entrant can't overlap a fast one. A refused
+//! or unanswered fast request falls back to the full protocol.
+
+use std::{cmp::Reverse, thread, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{error::NodeError, LogLevel, Node, RequestMsg};
+
+/// `/fast_request` and `/fast_release` carry only the sender; the leader's
+/// `/fast_answer` also says whether it granted.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct FastMsg {
+    pub from: usize,
+    pub resource: String,
+    #[serde(default)]
+    pub granted: bool,
+}
+
+impl Node {
+    pub fn fast_path_leader(&self) -> usize {
+        self.peers.iter().map(|(nid, _)| *nid).chain([self.id]).min().unwrap()
+    }
+
+    /// Whether any other node's request for `resource` is in our queue.
+    fn contended(&self, resource: &str) -> bool {
+        let st = self.state.lock().unwrap();
+        st.request_queues.get(resource).is_some_and(|q| q.iter().any(|Reverse((_, from))| *from != self.id))
+    }
+
+    fn send_fast(&self, to: usize, path: &str, resource: &str, granted: bool) -> Result<(), NodeError> {
+        let msg = FastMsg { from: self.id, resource: resource.to_string(), granted };
+        self.send_to_node(to, path, &serde_json::to_string(&msg)?)
+    }
+
+    /// Runs `body` on a fast grant and returns true, or returns false, without
+    /// running it, if the caller should take the Lamport path instead.
+    pub fn try_fast_path<F: FnOnce()>(&self, resource: &str, body: F) -> bool {
+        if self.contended(resource) {
+            self.log_at(LogLevel::Debug, &format!("FAST_PATH skipped for resource={}: queue not empty", resource));
+            return false;
+        }
+        let leader = self.fast_path_leader();
+        let requested_at = self.clock.now();
+        self.state.lock().unwrap().fast_answers.remove(resource);
+        if let Err(e) = self.send_fast(leader, "/fast_request", resource, false) {
+            self.log_at(LogLevel::Error, &format!("Error sending FAST_REQUEST to {}: {}", leader, e));
+            return false;
+        }
+        let granted = loop {
+            if let Some(g) = self.state.lock().unwrap().fast_answers.remove(resource) {
+                break g;
+            }
+            if self.clock.elapsed_since(requested_at) > self.attempt_timeout {
+                // A grant may still be on its way; release it unused.
+                let _ = self.send_fast(leader, "/fast_release", resource, false);
+                break false;
+            }
+            thread::sleep(Duration::from_millis(10));
+        };
+        if !granted {
+            self.log(&format!("FAST_PATH refused for resource={}; falling back to Lamport", resource));
+            return false;
+        }
+        let waited = self.clock.elapsed_since(requested_at);
+        self.record_cs_entry(resource, waited);
+        self.metrics.lock().unwrap().fast_path_entries += 1;
+        self.run_cs_body(resource, body);
+        if let Err(e) = self.send_fast(leader, "/fast_release", resource, false) {
+            self.log_at(LogLevel::Error, &format!("Error sending FAST_RELEASE to {}: {}", leader, e));
+        }
+        true
+    }
+
+    /// Leader side: grant while nobody holds a fast grant or has a Lamport
+    /// REQUEST queued, this node included.
+    pub fn receive_fast_request(&self, msg: FastMsg) {
+        let inside = self.in_cs.lock().unwrap().get(&msg.resource).copied().unwrap_or(false);
+        let granted = {
+            let mut st = self.state.lock().unwrap();
+            let queued = st.request_queues.get(&msg.resource).is_some_and(|q| !q.is_empty());
+            let free = !queued && !inside && !st.fast_grants.contains_key(&msg.resource);
+            if free {
+                st.fast_grants.insert(msg.resource.clone(), msg.from);
+            }
+            free
+        };
+        self.log_at(
+            LogLevel::Debug,
+            &format!("FAST_REQUEST from {} for resource={}: granted={}", msg.from, msg.resource, granted),
+        );
+        if let Err(e) = self.send_fast(msg.from, "/fast_answer", &msg.resource, granted) {
+            self.log_at(LogLevel::Error, &format!("Error answering FAST_REQUEST from {}: {}", msg.from, e));
+            if granted {
+                self.receive_fast_release(msg);
+            }
+        }
+    }
+
+    pub fn receive_fast_answer(&self, msg: FastMsg) {
+        self.state.lock().unwrap().fast_answers.insert(msg.resource, msg.granted);
+    }
+
+    /// Leader side: ends `msg.from`'s grant and sends every REPLY held back
+    /// while it was out.
+    pub fn receive_fast_release(&self, msg: FastMsg) {
+        let deferred = {
+            let mut st = self.state.lock().unwrap();
+            if st.fast_grants.get(&msg.resource) != Some(&msg.from) {
+                return;
+            }
+            st.fast_grants.remove(&msg.resource);
+            st.deferred_replies.remove(&msg.resource).unwrap_or_default()
+        };
+        self.log_at(
+            LogLevel::Debug,
+            &format!("FAST_RELEASE from {} for resource={}; sending {} held REPLY(s)", msg.from, msg.resource, deferred.len()),
+        );
+        for req in deferred {
+            self.send_reply(&req);
+        }
+    }
+
+    /// Leader side: holds back the REPLY to `msg` while another node has a
+    /// fast grant on its resource. Returns true if it was held.
+    pub fn defer_reply(&self, msg: &RequestMsg) -> bool {
+        let mut st = self.state.lock().unwrap();
+        if st.fast_grants.get(&msg.resource).is_none_or(|h| *h == msg.from) {
+            return false;
+        }
+        let held = st.deferred_replies.entry(msg.resource.clone()).or_default();
+        if !held.iter().any(|r| r.from == msg.from && r.ts == msg.ts) {
+            held.push(msg.clone());
+        }
+        true
+    }
+}
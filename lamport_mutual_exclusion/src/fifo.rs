@@ -0,0 +1,88 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{snapshot::MarkerMsg, vclock::VectorClock, ReplyMsg, RequestMsg};
+
+/// A protocol message that travels on a FIFO-ordered channel.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "msg", rename_all = "snake_case")]
+pub enum Inbound {
+    Request(RequestMsg),
+    Reply(ReplyMsg),
+    Marker(MarkerMsg),
+}
+
+impl Inbound {
+    pub fn from(&self) -> usize {
+        match self {
+            Inbound::Request(m) => m.from,
+            Inbound::Reply(m) => m.from,
+            Inbound::Marker(m) => m.from,
+        }
+    }
+
+    pub fn seq(&self) -> u64 {
+        match self {
+            Inbound::Request(m) => m.seq,
+            Inbound::Reply(m) => m.seq,
+            Inbound::Marker(m) => m.seq,
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Inbound::Request(_) => "REQUEST",
+            Inbound::Reply(_) => "REPLY",
+            Inbound::Marker(_) => "MARKER",
+        }
+    }
+
+    /// The sender's vector clock at the send, with `--vector-clocks`.
+    pub fn vclock(&self) -> Option<&VectorClock> {
+        match self {
+            Inbound::Request(m) => m.vclock.as_ref(),
+            Inbound::Reply(m) => m.vclock.as_ref(),
+            Inbound::Marker(_) => None,
+        }
+    }
+}
+
+/// Per-peer sequence numbers for outgoing messages and reorder buffers for
+/// incoming ones. Sequences start at 1; 0 marks an untagged message.
+#[derive(Debug, Default)]
+pub struct FifoChannels {
+    next_send: HashMap<usize, u64>,
+    next_recv: HashMap<usize, u64>,
+    buffered: HashMap<usize, BTreeMap<u64, Inbound>>,
+}
+
+impl FifoChannels {
+    pub fn next_seq(&mut self, dest: usize) -> u64 {
+        let seq = self.next_send.entry(dest).or_insert(1);
+        *seq += 1;
+        *seq - 1
+    }
+
+    /// Buffers `msg` from `from` and returns every message that is now
+    /// deliverable, in send order.
+    pub fn accept(&mut self, from: usize, msg: Inbound) -> Vec<Inbound> {
+        let seq = msg.seq();
+        let expected = self.next_recv.entry(from).or_insert(1);
+        if seq < *expected {
+            return vec![];
+        }
+        let buf = self.buffered.entry(from).or_default();
+        buf.insert(seq, msg);
+        let mut ready = vec![];
+        while let Some(m) = buf.remove(expected) {
+            ready.push(m);
+            *expected += 1;
+        }
+        ready
+    }
+
+    pub fn buffered_from(&self, from: usize) -> usize {
+        self.buffered.get(&from).map(|b| b.len()).unwrap_or(0)
+    }
+}
@@ -0,0 +1,116 @@
+//! `--heartbeat-ms`: each node tells its peers it is alive every interval
+//! and tracks when it last heard from each of them. A peer silent for
+//! `--suspect-after-ms` is suspected, and for `--dead-after-ms` dead; a dead
+//! peer no longer counts towards the reply quorum and isn't re-sent
+//! REQUESTs. Any heartbeat brings it straight back to alive.
+
+use std::{collections::BTreeMap, thread, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{LogLevel, Node, State};
+
+pub const DEFAULT_SUSPECT_AFTER: Duration = Duration::from_millis(1500);
+pub const DEFAULT_DEAD_AFTER: Duration = Duration::from_millis(5000);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Liveness {
+    Alive,
+    Suspected,
+    Dead,
+}
+
+/// Body of a `/heartbeat` POST.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct HeartbeatMsg {
+    pub from: usize,
+}
+
+/// One peer as `/peers` reports it.
+#[derive(Serialize, Debug)]
+pub struct PeerStatus {
+    pub state: Liveness,
+    pub silent_ms: u64,
+}
+
+impl State {
+    /// Whether `peer` should still be counted on for replies.
+    pub fn counts_on(&self, peer: usize) -> bool {
+        self.liveness.get(&peer) != Some(&Liveness::Dead)
+    }
+}
+
+impl Node {
+    pub fn receive_heartbeat(&self, msg: HeartbeatMsg) {
+        let now = self.clock.now();
+        let was = {
+            let mut st = self.state.lock().unwrap();
+            st.last_seen.insert(msg.from, now);
+            st.liveness.insert(msg.from, Liveness::Alive)
+        };
+        if was.is_some_and(|was| was != Liveness::Alive) {
+            self.log_at(LogLevel::Info, &format!("LIVENESS peer={} {:?} -> Alive: heartbeat received", msg.from, was.unwrap()));
+        }
+    }
+
+    /// Re-grades every peer by how long it has been silent, logging each
+    /// change. A peer never heard from is timed from the first call.
+    pub fn update_liveness(&self) -> Vec<(usize, Liveness)> {
+        let now = self.clock.now();
+        let mut changed = vec![];
+        {
+            let mut st = self.state.lock().unwrap();
+            for (peer, _) in &self.peers {
+                let silent = now.saturating_sub(*st.last_seen.entry(*peer).or_insert(now));
+                let next = if silent > self.dead_after {
+                    Liveness::Dead
+                } else if silent > self.suspect_after {
+                    Liveness::Suspected
+                } else {
+                    Liveness::Alive
+                };
+                let prev = st.liveness.insert(*peer, next).unwrap_or(Liveness::Alive);
+                if prev != next {
+                    changed.push((*peer, prev, next, silent));
+                }
+            }
+        }
+        for (peer, prev, next, silent) in &changed {
+            self.log_at(LogLevel::Info, &format!("LIVENESS peer={} {:?} -> {:?}: silent for {:?}", peer, prev, next, silent));
+        }
+        changed.into_iter().map(|(peer, _, next, _)| (peer, next)).collect()
+    }
+
+    pub fn peer_statuses(&self) -> BTreeMap<usize, PeerStatus> {
+        let now = self.clock.now();
+        let st = self.state.lock().unwrap();
+        self.peers
+            .iter()
+            .map(|(peer, _)| {
+                let state = st.liveness.get(peer).copied().unwrap_or(Liveness::Alive);
+                let silent_ms = st.last_seen.get(peer).map_or(0, |seen| now.saturating_sub(*seen).as_millis() as u64);
+                (*peer, PeerStatus { state, silent_ms })
+            })
+            .collect()
+    }
+
+    /// Sends heartbeats and re-grades peers every interval for as long as
+    /// the process runs.
+    pub fn start_heartbeats(&self) {
+        let Some(interval) = self.heartbeat_interval else { return };
+        let node = self.clone();
+        thread::spawn(move || loop {
+            let payload = serde_json::to_string(&HeartbeatMsg { from: node.id }).unwrap();
+            for (_, port) in node.peers.clone() {
+                let (n, payload) = (node.clone(), payload.clone());
+                // A failed heartbeat is what the peer's detector is for.
+                node.sender.execute(move || {
+                    let _ = n.send_to(port, "/heartbeat", &payload);
+                });
+            }
+            node.update_liveness();
+            thread::sleep(interval);
+        });
+    }
+}
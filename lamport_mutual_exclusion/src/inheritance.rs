@@ -0,0 +1,60 @@
+//! `--priority-inheritance`: a holder whose resource is wanted by a node of
+//! higher `--node-priority` inherits that urgency and cuts its CS short, so
+//! the waiter is blocked for a fraction of `CS_HOLD` instead of all of it.
+//! Meant for real-time scheduling demos; it changes how long the default CS
+//! body sleeps, not who may enter.
+
+use std::{cmp::Reverse, thread, time::Duration};
+
+use crate::{LogLevel, Node, CS_HOLD};
+
+impl Node {
+    fn node_priority(&self, id: usize) -> u8 {
+        self.node_priorities.get(&id).copied().unwrap_or(0)
+    }
+
+    /// How long to hold `resource` given the queue right now: `CS_HOLD`,
+    /// divided by one plus the largest priority lead any waiter has over us.
+    pub fn cs_hold(&self, resource: &str) -> Duration {
+        if !self.priority_inheritance {
+            return CS_HOLD;
+        }
+        let mine = self.node_priority(self.id);
+        let st = self.state.lock().unwrap();
+        let lead = st
+            .request_queues
+            .get(resource)
+            .into_iter()
+            .flat_map(|q| q.iter())
+            .map(|Reverse((_, from))| self.node_priority(*from).saturating_sub(mine))
+            .max()
+            .unwrap_or(0);
+        CS_HOLD / (1 + lead as u32)
+    }
+
+    /// The default CS body: sleeps for `cs_hold`, re-reading it as requests
+    /// arrive so a waiter that shows up mid-hold still shortens it, and
+    /// stopping early if `/release` preempts it.
+    pub fn hold_cs(&self, resource: &str) {
+        let started = self.clock.now();
+        let mut hold = CS_HOLD;
+        loop {
+            if self.is_preempted(resource) {
+                return;
+            }
+            let now_hold = self.cs_hold(resource);
+            if now_hold < hold {
+                self.log_at(
+                    LogLevel::Debug,
+                    &format!("PRIORITY_INHERIT resource={}: hold cut to {:?} for a higher-priority waiter", resource, now_hold),
+                );
+            }
+            hold = now_hold;
+            let held = self.clock.elapsed_since(started);
+            if held >= hold {
+                return;
+            }
+            thread::sleep((hold - held).min(Duration::from_millis(10)));
+        }
+    }
+}
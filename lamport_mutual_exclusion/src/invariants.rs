@@ -0,0 +1,70 @@
+//! `--check-invariants-ms`: a debug-build thread that periodically checks
+//! the node's own state for things that can only mean a bug: more replies
+//! than peers, a `(ts, id)` queued twice, or the Lamport clock behind where
+//! the last check saw it. Each problem is logged as `INVARIANT_VIOLATED`;
+//! with `--invariant-panic` the first one also panics, so a test run stops
+//! where the damage is rather than where it surfaces.
+
+use std::{cmp::Reverse, collections::HashSet, thread};
+
+use crate::{LogLevel, Node, State};
+
+impl State {
+    /// Every invariant broken right now, for a node with `peers` peers.
+    /// Remembers the clock for the next call's monotonicity check.
+    pub fn invariant_violations(&mut self, peers: usize) -> Vec<String> {
+        let mut found = vec![];
+        let mut replies: Vec<_> = self.replies.iter().filter(|(_, r)| r.len() > peers).collect();
+        replies.sort_by_key(|(resource, _)| *resource);
+        for (resource, r) in replies {
+            found.push(format!("resource={} has {} replies from {} peer(s)", resource, r.len(), peers));
+        }
+        let mut resources: Vec<&String> = self.request_queues.keys().collect();
+        resources.sort();
+        for resource in resources {
+            let mut seen = HashSet::new();
+            let mut entries: Vec<_> = self.request_queues[resource].iter().map(|Reverse(e)| *e).collect();
+            entries.sort();
+            for (ts, from) in entries {
+                if !seen.insert((ts, from)) {
+                    found.push(format!("resource={} queues ts={} from={} more than once", resource, ts, from));
+                }
+            }
+        }
+        let now = self.timestamp.get();
+        if now < self.checked_timestamp {
+            found.push(format!("Lamport clock went back from {} to {}", self.checked_timestamp, now));
+        }
+        self.checked_timestamp = now;
+        found
+    }
+}
+
+impl Node {
+    /// Runs one check, logging each violation. Returns what it found.
+    pub fn check_invariants(&self) -> Vec<String> {
+        let found = self.state.lock().unwrap().invariant_violations(self.peers.len());
+        for v in &found {
+            self.log_at(LogLevel::Error, &format!("INVARIANT_VIOLATED {}", v));
+        }
+        if self.invariant_panic {
+            if let Some(first) = found.first() {
+                panic!("[Node {}] invariant violated: {}", self.id, first);
+            }
+        }
+        found
+    }
+
+    /// Release builds skip the checker even when asked for it.
+    pub fn start_invariant_checker(&self) {
+        let Some(every) = self.check_invariants_every else { return };
+        if !cfg!(debug_assertions) {
+            return;
+        }
+        let node = self.clone();
+        thread::spawn(move || loop {
+            thread::sleep(every);
+            node.check_invariants();
+        });
+    }
+}
@@ -0,0 +1,66 @@
+//! `--rebroadcast-jitter`: an extra wait before each rebroadcast, so nodes
+//! that time out together don't all retry together and collide again.
+//! `id:MS` spreads the cluster evenly over MS by each node's place in the
+//! sorted id list; `random:MS` draws from 0..MS, seeded from `--seed`, the
+//! node's id and the attempt, so a seed still names the whole run.
+
+use std::{str::FromStr, time::Duration};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::Node;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Jitter {
+    #[default]
+    None,
+    ById(Duration),
+    Random(Duration),
+}
+
+impl FromStr for Jitter {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        if s.eq_ignore_ascii_case("none") {
+            return Ok(Jitter::None);
+        }
+        let (kind, ms) = s.split_once(':').ok_or(())?;
+        let ms = ms.parse().map(Duration::from_millis).map_err(|_| ())?;
+        match kind.to_ascii_lowercase().as_str() {
+            "id" => Ok(Jitter::ById(ms)),
+            "random" => Ok(Jitter::Random(ms)),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Jitter {
+    pub fn describe(self) -> String {
+        match self {
+            Jitter::None => "none".to_string(),
+            Jitter::ById(d) => format!("id:{}", d.as_millis()),
+            Jitter::Random(d) => format!("random:{}", d.as_millis()),
+        }
+    }
+}
+
+impl Node {
+    /// How much longer than the attempt timeout to wait before rebroadcast
+    /// number `attempt`. The same for a given node and attempt every time
+    /// it is asked.
+    pub fn rebroadcast_jitter(&self, attempt: u32) -> Duration {
+        match self.jitter {
+            Jitter::None => Duration::ZERO,
+            Jitter::ById(spread) => {
+                let below = self.peers.iter().filter(|(p, _)| *p < self.id).count();
+                spread * below as u32 / (self.peers.len() + 1) as u32
+            }
+            Jitter::Random(spread) if spread.is_zero() => Duration::ZERO,
+            Jitter::Random(spread) => {
+                let stream = (self.id as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15) ^ attempt as u64;
+                StdRng::seed_from_u64(self.seed ^ stream).gen_range(Duration::ZERO..spread)
+            }
+        }
+    }
+}
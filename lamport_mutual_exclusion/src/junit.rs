@@ -0,0 +1,47 @@
+//! `--junit PATH`: `--self-test` and `verify` also write their verdict as
+//! JUnit XML, one testcase per invariant checked, so CI dashboards show
+//! which property broke rather than just a failed step.
+
+use std::{fs, io};
+
+/// One invariant and, if it was broken, how.
+#[derive(Debug)]
+pub struct Case {
+    pub name: &'static str,
+    pub failure: Option<String>,
+}
+
+impl Case {
+    pub fn new(name: &'static str, result: Result<(), String>) -> Self {
+        Case { name, failure: result.err() }
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;").replace('\'', "&apos;")
+}
+
+pub fn render(suite: &str, cases: &[Case]) -> String {
+    let failures = cases.iter().filter(|c| c.failure.is_some()).count();
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml += &format!("<testsuite name=\"{}\" tests=\"{}\" failures=\"{}\">\n", escape(suite), cases.len(), failures);
+    for c in cases {
+        let open = format!("  <testcase classname=\"{}\" name=\"{}\"", escape(suite), escape(c.name));
+        match &c.failure {
+            None => xml += &format!("{}/>\n", open),
+            Some(msg) => {
+                xml += &format!("{}>\n    <failure message=\"{}\"/>\n  </testcase>\n", open, escape(msg));
+            }
+        }
+    }
+    xml + "</testsuite>\n"
+}
+
+pub fn write(path: &str, suite: &str, cases: &[Case]) -> io::Result<()> {
+    fs::write(path, render(suite, cases))
+}
+
+/// The value after `--junit` in a subcommand's arguments, if any.
+pub fn path_in(args: &[String]) -> Option<&str> {
+    args.iter().position(|a| a == "--junit").and_then(|i| args.get(i + 1)).map(String::as_str)
+}
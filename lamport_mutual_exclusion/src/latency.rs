@@ -0,0 +1,88 @@
+use std::{collections::HashMap, fs};
+
+use crate::verify::{field, parse_line};
+
+/// Timings joined on one `trace=` id.
+#[derive(Default)]
+struct Trace {
+    kind: String,
+    sent_ms: Option<u64>,
+    /// Arrival of the message itself at each receiver.
+    received_ms: Vec<(usize, u64)>,
+    /// Arrival back at the sender of each receiver's REPLY echoing it.
+    reply_ms: HashMap<usize, u64>,
+}
+
+/// One delivery of a traced message; times in milliseconds.
+pub struct Latency {
+    pub trace_id: String,
+    pub kind: String,
+    pub to: usize,
+    pub one_way_ms: Option<u64>,
+    pub rtt_ms: Option<u64>,
+}
+
+/// One-way and round-trip latencies per traced message and receiver.
+pub fn latencies(contents: &str) -> Vec<Latency> {
+    let mut traces: HashMap<String, Trace> = HashMap::new();
+    let mut order = vec![];
+    for (i, raw) in contents.lines().enumerate() {
+        let Some(l) = parse_line(i + 1, raw) else { continue };
+        let (Some(id), Some(at)) = (field(l.msg, "trace="), field(l.msg, "at_ms=").and_then(|v| v.parse().ok())) else {
+            continue;
+        };
+        let mut words = l.msg.split_whitespace();
+        let (verb, kind) = (words.next().unwrap_or(""), words.next().unwrap_or(""));
+        let t = traces.entry(id.to_string()).or_insert_with(|| {
+            order.push(id.to_string());
+            Trace::default()
+        });
+        match (verb, kind) {
+            ("Sent", "REPLY") => {}
+            ("Sent", k) => {
+                t.kind = k.to_string();
+                t.sent_ms.get_or_insert(at);
+            }
+            ("Received", "REPLY") if t.sent_ms.is_some() => {
+                if let Some(from) = field(l.msg, "from ").and_then(|v| v.parse().ok()) {
+                    t.reply_ms.insert(from, at);
+                }
+            }
+            ("Received", k) => {
+                if t.kind.is_empty() {
+                    t.kind = k.to_string();
+                }
+                t.received_ms.push((l.node, at));
+            }
+            _ => {}
+        }
+    }
+
+    let mut out = vec![];
+    for id in order {
+        let t = &traces[&id];
+        for (node, at) in &t.received_ms {
+            let one_way = t.sent_ms.map(|s| at.saturating_sub(s));
+            let rtt = t.sent_ms.and_then(|s| t.reply_ms.get(node).map(|r| r.saturating_sub(s)));
+            out.push(Latency { trace_id: id.clone(), kind: t.kind.clone(), to: *node, one_way_ms: one_way, rtt_ms: rtt });
+        }
+    }
+    out
+}
+
+/// Entry point for `latency <logfile>`; returns the process exit code.
+pub fn run(path: &str) -> i32 {
+    let contents = match fs::read_to_string(path) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("cannot read {}: {}", path, e);
+            return 2;
+        }
+    };
+    let ms = |v: Option<u64>| v.map(|v| v.to_string()).unwrap_or_else(|| "-".to_string());
+    println!("{:<36} {:<8} {:>4} {:>8} {:>8}", "trace", "kind", "to", "one-way", "rtt");
+    for l in latencies(&contents) {
+        println!("{:<36} {:<8} {:>4} {:>8} {:>8}", l.trace_id, l.kind, l.to, ms(l.one_way_ms), ms(l.rtt_ms));
+    }
+    0
+}
@@ -0,0 +1,80 @@
+//! `--lease-grace-ms`: crash recovery for a node that dies inside its CS.
+//! Every entry at the head of a queue is treated as holding a lease of
+//! `CS_HOLD` plus the grace; if its RELEASE hasn't come by then, the entry
+//! is dropped as if it had. The lease runs from when the entry reached the
+//! head of our queue, the earliest its owner could have entered, so the
+//! grace also has to cover the owner's wait for replies.
+
+use std::{cmp::Reverse, thread, time::Duration};
+
+use crate::{LogLevel, Node, CS_HOLD};
+
+const LEASE_CHECK_INTERVAL: Duration = Duration::from_millis(50);
+
+impl Node {
+    pub fn lease(&self) -> Option<Duration> {
+        self.lease_grace.map(|grace| CS_HOLD + grace)
+    }
+
+    /// The head of every non-empty queue and how long it has been there,
+    /// counted from the first call that saw it.
+    pub fn queue_heads(&self) -> Vec<(String, (u64, usize), Duration)> {
+        let now = self.clock.now();
+        let mut st = self.state.lock().unwrap();
+        let heads: Vec<(String, (u64, usize))> = st
+            .request_queues
+            .iter()
+            .filter_map(|(r, q)| q.peek().map(|Reverse(head)| (r.clone(), *head)))
+            .collect();
+        st.head_since.retain(|r, (head, _)| heads.iter().any(|(hr, h)| hr == r && h == head));
+        heads
+            .into_iter()
+            .map(|(resource, head)| {
+                let (_, since) = *st.head_since.entry(resource.clone()).or_insert((head, now));
+                (resource, head, now.saturating_sub(since))
+            })
+            .collect()
+    }
+
+    /// Drops every queue head, other than our own, that has held its
+    /// resource past the lease. Returns the `(resource, ts, from)` dropped.
+    pub fn expire_leases(&self) -> Vec<(String, u64, usize)> {
+        let Some(lease) = self.lease() else { return vec![] };
+        let mut expired = vec![];
+        let heads = self.queue_heads();
+        {
+            let mut st = self.state.lock().unwrap();
+            for (resource, (ts, from), held) in heads {
+                if from == self.id || held <= lease {
+                    continue;
+                }
+                if let Some(q) = st.request_queues.get_mut(&resource) {
+                    q.retain(|Reverse(e)| *e != (ts, from));
+                }
+                st.readers.remove(&(resource.clone(), ts, from));
+                st.released.insert((resource.clone(), ts, from));
+                st.head_since.remove(&resource);
+                expired.push((resource, ts, from));
+            }
+        }
+        for (resource, ts, from) in &expired {
+            self.log_at(
+                LogLevel::Info,
+                &format!("LEASE_EXPIRED resource={}: dropping ts={} from {} after {:?} without RELEASE", resource, ts, from, lease),
+            );
+        }
+        expired
+    }
+
+    /// Checks leases in the background for as long as the process runs.
+    pub fn start_lease_watch(&self) {
+        if self.lease_grace.is_none() {
+            return;
+        }
+        let node = self.clone();
+        thread::spawn(move || loop {
+            thread::sleep(LEASE_CHECK_INTERVAL);
+            node.expire_leases();
+        });
+    }
+}
@@ -0,0 +1,4016 @@
+use std::{
+    collections::{BinaryHeap, HashMap, HashSet},
+    cmp::Reverse,
+    fs::OpenOptions,
+    net::{Ipv4Addr, SocketAddrV4},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use tiny_http::{Header, Server, Response};
+use reqwest::blocking::Client;
+use serde::{Deserialize, Serialize};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use uuid::Uuid;
+use dc_common::{
+    args::{arg_range, arg_value, cli_args},
+    http::json_header,
+    log::LogRotation,
+    topology::validate_topology,
+};
+
+mod barrier;
+mod bench;
+mod cancel;
+mod chaos;
+mod clock;
+mod compress;
+mod config_file;
+mod contention;
+mod coordinator;
+mod effective;
+mod error;
+mod fairness;
+mod fast_path;
+mod fifo;
+mod happens_before;
+mod heartbeat;
+mod inheritance;
+mod invariants;
+mod jitter;
+mod junit;
+mod latency;
+mod lease;
+mod log_flush;
+mod metrics;
+mod pool;
+mod preempt;
+mod quorum;
+mod reply_batch;
+mod rtt;
+mod scheduler;
+mod selftest;
+mod sink;
+mod snapshot;
+mod stuck;
+mod strategy;
+mod think;
+mod token_ring;
+mod topology;
+mod trace;
+mod transport;
+mod vclock;
+mod verify;
+mod view;
+mod wire;
+
+use barrier::BarrierMsg;
+use clock::{Clock, MonotonicClock, SystemClock};
+use contention::Workload;
+use coordinator::CoordMsg;
+use error::NodeError;
+use fast_path::FastMsg;
+use fifo::{FifoChannels, Inbound};
+use heartbeat::{HeartbeatMsg, Liveness};
+use jitter::Jitter;
+use log_flush::{LogBuffer, LogFlush};
+use metrics::MetricsFormat;
+use pool::SendPool;
+use quorum::QuorumPolicy;
+use reply_batch::ReplyBatchMsg;
+use sink::LogSink;
+use snapshot::MarkerMsg;
+use strategy::{Admission, MutexAlgorithm};
+use think::{Pauses, ThinkTime};
+use token_ring::TokenMsg;
+use transport::{HttpTransport, InProcessTransport, MulticastTransport, Transport, TransportKind};
+use vclock::VectorClock;
+use view::ViewMsg;
+use wire::WireFormat;
+
+type HttpResponse = Response<std::io::Cursor<Vec<u8>>>;
+
+const USAGE: &str = "\
+usage: lamport_mutex [OPTIONS]
+       lamport_mutex verify [LOG] [--junit PATH] | latency [LOG] | happens-before [TRACE]
+       lamport_mutex replay TRACE NODE-ID [OPTIONS] | snapshot | bench ...
+
+Runs every node of the cluster in this process unless --id picks one.
+
+Protocol:
+  --algorithm lamport|token_ring|coordinator
+  --quorum all|majority|weighted|fixed:N
+  --weight ID=W              weight of a node under --quorum weighted
+  --priority RESOURCE=P      acquire higher-priority resources first
+  --node-priority ID=P       priority of a node for --priority-inheritance
+  --priority-inheritance     cut the CS short for a higher-priority waiter
+  --max-consecutive-entries N  let a waiting peer in after N entries in a row
+  --fast-path                uncontended writers ask the leader only
+  --fifo                     deliver each peer's messages in send order
+  --vector-clocks            piggyback vector clocks and log concurrency
+  --generation N             membership view to start in
+  --lease-grace-ms MS        drop a queue head with no RELEASE after CS + MS
+  --stuck-head-ms MS         evict a stuck head's owner if it fails /ping
+  --check-invariants-ms MS   debug builds: check internal state this often
+  --invariant-panic          panic on the first broken invariant
+  --heartbeat-ms MS          send heartbeats; dead peers leave the quorum
+  --rtt-probe-ms MS          time peers and send REQUESTs to the fastest first
+  --suspect-after-ms MS      silence before a peer is suspected
+  --dead-after-ms MS         silence before a peer is dead
+  --attempt-timeout-ms MS    how long one REQUEST waits for replies
+  --max-rebroadcasts N       re-sends to silent peers before giving up
+  --rebroadcast-jitter none|id:MS|random:MS
+                             extra wait before each re-send
+  --max-queue-len N          refuse REQUESTs beyond this queue length
+
+Cluster and network:
+  --config FILE              topology, quorum and timeouts from JSON
+  --topology FILE            nodes and their resources from topology.toml
+  --id ID                    run only this node
+  --transport http|multicast
+  --multicast-group ADDR:PORT
+  --wire-format json|msgpack
+  --gzip                     gzip large HTTP request bodies
+  --send-workers N           threads for outbound sends
+  --server-threads N         threads handling inbound requests
+  --pool-max-idle N          idle HTTP connections kept per peer
+  --request-timeout-ms MS
+  --connect-timeout-ms MS
+
+Workload:
+  --rounds N                 times each node takes A then B
+  --workload rounds|contention
+                             contention: every node hammers A in-process
+  --duration-secs S          how long the contention workload runs
+  --acquire-both             also take A and B together
+  --think-ms MS              pause between rounds
+  --think-dist constant:MS|uniform:MIN..MAX|exp:MEAN
+  --start-delay-ms MIN..MAX
+  --start-barrier ID         start together once every node registers with ID
+  --gap-ms MIN..MAX
+  --seed N
+  --counter-file PATH        shared counter the CS increments
+  --deadline-secs S          abort a run still going after S seconds
+
+Output:
+  --log-level error|info|debug|trace
+  --log-sink file|stdout|tcp:HOST:PORT
+  --log-flush every-line|interval:MS|on-exit
+  --per-node-logs            node_<id>.log instead of lamport.log
+  --max-log-bytes N
+  --log-backups N
+  --metrics-format json|prometheus
+  --record                   append inbound messages to trace.jsonl
+  --print-config             print the resolved configuration and exit
+  --self-test                run the in-process CS oracle and exit
+  --junit PATH               also write the self-test verdict as JUnit XML
+  -h, --help
+";
+
+/// The value of request header `name`, if it was sent.
+fn header(req: &tiny_http::Request, name: &'static str) -> Option<String> {
+    req.headers().iter().find(|h| h.field.equiv(name)).map(|h| h.value.as_str().to_string())
+}
+
+/// The value of `key` in a `a=1&b=2` query string, undecoded.
+fn query_param<'a>(query: &'a str, key: &str) -> Option<&'a str> {
+    query.split('&').filter_map(|kv| kv.split_once('=')).find(|(k, _)| *k == key).map(|(_, v)| v)
+}
+
+/// The sender is behind our membership view; 409 tells it its message was
+/// refused, not lost.
+fn stale_view() -> HttpResponse {
+    Response::from_string("STALE_VIEW").with_status_code(409)
+}
+
+/// Lets the dashboard served by one node poll every other node's `/status`.
+fn cors_header() -> Header {
+    Header::from_bytes("Access-Control-Allow-Origin", "*").unwrap()
+}
+
+const DASHBOARD_HTML: &str = include_str!("dashboard.html");
+
+const PEER_WAIT_TIMEOUT: Duration = Duration::from_secs(10);
+const CS_HOLD: Duration = Duration::from_millis(500);
+/// The resources every node keeps a queue for.
+const RESOURCES: [&str; 2] = ["A", "B"];
+const NODES: [(usize, u16); 4] = [(0, 8000), (1, 8001), (2, 8002), (3, 8003)];
+const LOG_FILE: &str = "lamport.log";
+const TRACE_FILE: &str = "trace.jsonl";
+const WATCHDOG_EXIT_CODE: i32 = 3;
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+struct RequestMsg {
+    from: usize,
+    ts: u64,
+    resource: String,
+    #[serde(default)]
+    seq: u64,
+    #[serde(default)]
+    trace_id: Uuid,
+    #[serde(default)]
+    mode: LockMode,
+    /// Sender's vector clock at the send, with `--vector-clocks`.
+    #[serde(default)]
+    vclock: Option<VectorClock>,
+    /// Sender's membership view; see `view`.
+    #[serde(default)]
+    generation: u64,
+    /// Counts the sender's REQUESTs for this resource; echoed on REPLY so a
+    /// late answer to an earlier one isn't counted. 0 from older senders.
+    #[serde(default)]
+    epoch: u64,
+}
+
+/// Readers may share a resource with each other; a writer holds it alone.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum LockMode {
+    Read,
+    #[default]
+    Write,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+struct ReplyMsg {
+    from: usize,
+    resource: String,
+    #[serde(default)]
+    seq: u64,
+    /// Timestamp of the REQUEST being answered; 0 from older senders.
+    #[serde(default)]
+    ts: u64,
+    /// The `trace_id` of the REQUEST being answered, so the log gives RTTs.
+    #[serde(default)]
+    trace_id: Uuid,
+    #[serde(default)]
+    vclock: Option<VectorClock>,
+    #[serde(default)]
+    generation: u64,
+    /// The `epoch` of the REQUEST being answered.
+    #[serde(default)]
+    epoch: u64,
+}
+
+/// Ordered from most to least severe; a node logs everything at or above
+/// its configured level.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum LogLevel {
+    Error,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl std::str::FromStr for LogLevel {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s.to_ascii_lowercase().as_str() {
+            "error" => Ok(LogLevel::Error),
+            "info" => Ok(LogLevel::Info),
+            "debug" => Ok(LogLevel::Debug),
+            "trace" => Ok(LogLevel::Trace),
+            _ => Err(()),
+        }
+    }
+}
+
+impl LogLevel {
+    fn as_str(self) -> &'static str {
+        match self {
+            LogLevel::Error => "error",
+            LogLevel::Info => "info",
+            LogLevel::Debug => "debug",
+            LogLevel::Trace => "trace",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Algorithm {
+    Lamport,
+    TokenRing,
+    /// One node per resource, by consistent hashing, grants it in turn.
+    Coordinator,
+}
+
+impl std::str::FromStr for Algorithm {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "lamport" => Ok(Algorithm::Lamport),
+            "token_ring" => Ok(Algorithm::TokenRing),
+            "coordinator" => Ok(Algorithm::Coordinator),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Tags log lines for read requests; writes keep the original format.
+fn mode_suffix(mode: LockMode) -> &'static str {
+    match mode {
+        LockMode::Read => " mode=read",
+        LockMode::Write => "",
+    }
+}
+
+/// Withdraws `(ts, from)` from every peer's queue for `resource`.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+struct ReleaseMsg {
+    from: usize,
+    ts: u64,
+    resource: String,
+    #[serde(default)]
+    generation: u64,
+}
+
+struct Config {
+    algorithm: Algorithm,
+    log_level: LogLevel,
+    max_queue_len: usize,
+    seed: Option<u64>,
+    start_delay_ms: (u64, u64),
+    /// Node every other registers with before starting; see `barrier`.
+    start_barrier: Option<usize>,
+    gap_ms: (u64, u64),
+    send_workers: usize,
+    /// Threads pulling inbound requests off the one tiny_http server.
+    server_threads: usize,
+    pool_max_idle_per_host: usize,
+    request_timeout_ms: u64,
+    connect_timeout_ms: u64,
+    fifo: bool,
+    counter_file: Option<String>,
+    /// After the single-resource rounds, also take A and B together.
+    acquire_both: bool,
+    /// How many times each node acquires A then B.
+    rounds: usize,
+    /// `rounds` of A then B, or the single-resource contention benchmark.
+    workload: Workload,
+    /// How long `--workload contention` runs.
+    duration: Duration,
+    /// Pause between rounds.
+    think_time: Duration,
+    /// Draw every gap and think time from this instead, seeded per node.
+    think_dist: Option<ThinkTime>,
+    /// Append every inbound REQUEST/REPLY to `trace.jsonl`.
+    record: bool,
+    /// Abort the whole run if it is still going after this long.
+    deadline: Option<Duration>,
+    quorum: QuorumPolicy,
+    /// Per-node weights for `QuorumPolicy::WeightedMajority`.
+    weights: HashMap<usize, u32>,
+    /// Resources with a higher priority are acquired first when taken
+    /// together; unlisted resources are 0.
+    priorities: HashMap<String, u8>,
+    /// Per-node priorities for `--priority-inheritance`; unlisted nodes are 0.
+    node_priorities: HashMap<usize, u8>,
+    /// The resources each node requests in its rounds, from `--topology`;
+    /// unlisted nodes request all of them.
+    node_resources: HashMap<usize, Vec<String>>,
+    /// Cut a CS short while a higher-priority node waits for the resource.
+    priority_inheritance: bool,
+    /// Entries into one resource in a row before this node lets a waiting
+    /// peer go first.
+    max_consecutive_entries: Option<u32>,
+    /// Grace past `CS_HOLD` before a queue head with no RELEASE is dropped.
+    lease_grace: Option<Duration>,
+    /// How long a queue head may sit unchanged before its owner is pinged
+    /// and, if it doesn't answer, evicted.
+    stuck_head_after: Option<Duration>,
+    /// Debug builds check internal invariants this often.
+    check_invariants_every: Option<Duration>,
+    /// Panic on the first broken invariant instead of only logging it.
+    invariant_panic: bool,
+    /// Send heartbeats this often and grade peers alive, suspected or dead.
+    heartbeat_interval: Option<Duration>,
+    /// Time a `/ping` to each peer this often and send to the fastest first.
+    rtt_probe_every: Option<Duration>,
+    suspect_after: Duration,
+    dead_after: Duration,
+    /// How long each REQUEST attempt waits for replies.
+    attempt_timeout: Duration,
+    /// Re-sends to silent peers before giving up and releasing.
+    max_rebroadcasts: u32,
+    /// Extra wait before each re-send, so nodes don't retry in lockstep.
+    rebroadcast_jitter: Jitter,
+    /// `(id, port)` of every node in the cluster; `NODES` unless `--config`
+    /// says otherwise.
+    nodes: Vec<(usize, u16)>,
+    /// Run only this node from `nodes` instead of the whole cluster.
+    id: Option<usize>,
+    /// How REQUESTs are broadcast; replies are always unicast HTTP.
+    transport: TransportKind,
+    multicast_group: SocketAddrV4,
+    /// JSON or MessagePack between nodes; see `wire`.
+    wire_format: WireFormat,
+    /// Gzip large HTTP request bodies; see `compress`.
+    gzip: bool,
+    /// Print the resolved configuration and exit instead of running.
+    print_config: bool,
+    /// Piggyback vector clocks on REQUEST/REPLY and log concurrency.
+    vector_clocks: bool,
+    /// Rotate `lamport.log` once it reaches this size.
+    max_log_bytes: Option<u64>,
+    /// Rotated logs to keep, as `lamport.log.1` (newest) onwards.
+    log_backups: usize,
+    /// Log each node to `node_<id>.log` instead of the shared `lamport.log`.
+    /// `verify` needs the shared file, which is the only total order.
+    per_node_logs: bool,
+    /// What `/metrics` serves: JSON, or Prometheus text for scraping.
+    metrics_format: MetricsFormat,
+    /// Let uncontended writers take a resource from the leader instead of
+    /// broadcasting.
+    fast_path: bool,
+    /// Run the seeded in-process scenario under the CS oracle and exit with
+    /// its verdict instead of starting the cluster.
+    self_test: bool,
+    /// Also write the `--self-test` verdict here as JUnit XML.
+    junit: Option<String>,
+    /// Membership view to start in; messages from older views are dropped.
+    generation: u64,
+    /// Where log lines go; shared by every node in the process.
+    log_sink: Arc<LogSink>,
+    /// When file log lines are written, and the lines still waiting;
+    /// shared by every node in the process.
+    log_buffer: Arc<LogBuffer>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            algorithm: Algorithm::Lamport,
+            log_level: LogLevel::Debug,
+            max_queue_len: 64,
+            seed: None,
+            start_delay_ms: (1000, 4000),
+            start_barrier: None,
+            gap_ms: (200, 500),
+            send_workers: 8,
+            server_threads: 4,
+            pool_max_idle_per_host: 4,
+            request_timeout_ms: 2000,
+            connect_timeout_ms: 1000,
+            fifo: false,
+            counter_file: None,
+            acquire_both: false,
+            rounds: 1,
+            workload: Workload::Rounds,
+            duration: Duration::from_secs(10),
+            think_time: Duration::ZERO,
+            think_dist: None,
+            record: false,
+            deadline: None,
+            quorum: QuorumPolicy::All,
+            weights: HashMap::new(),
+            priorities: HashMap::new(),
+            node_resources: HashMap::new(),
+            node_priorities: HashMap::new(),
+            priority_inheritance: false,
+            max_consecutive_entries: None,
+            lease_grace: None,
+            stuck_head_after: None,
+            check_invariants_every: None,
+            invariant_panic: false,
+            heartbeat_interval: None,
+            rtt_probe_every: None,
+            suspect_after: heartbeat::DEFAULT_SUSPECT_AFTER,
+            dead_after: heartbeat::DEFAULT_DEAD_AFTER,
+            attempt_timeout: Duration::from_secs(2),
+            max_rebroadcasts: 2,
+            rebroadcast_jitter: Jitter::None,
+            nodes: NODES.to_vec(),
+            id: None,
+            transport: TransportKind::Http,
+            multicast_group: SocketAddrV4::new(Ipv4Addr::new(239, 255, 42, 1), 9042),
+            wire_format: WireFormat::Json,
+            gzip: false,
+            print_config: false,
+            vector_clocks: false,
+            max_log_bytes: None,
+            log_backups: 3,
+            per_node_logs: false,
+            metrics_format: MetricsFormat::Json,
+            fast_path: false,
+            self_test: false,
+            junit: None,
+            generation: 0,
+            log_sink: Arc::new(LogSink::File),
+            log_buffer: Arc::default(),
+        }
+    }
+}
+
+impl Config {
+    fn from_args() -> Self {
+        Self::from_args_after(1)
+    }
+
+    /// Parses flags starting at argument `skip`, past any subcommand.
+    fn from_args_after(skip: usize) -> Self {
+        Self::parse(cli_args().skip(skip))
+    }
+
+    fn parse(mut args: impl Iterator<Item = String>) -> Self {
+        let mut cfg = Config::default();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-h" | "--help" => {
+                    print!("{}", USAGE);
+                    std::process::exit(0);
+                }
+                "--algorithm" => cfg.algorithm = arg_value(&mut args, &arg),
+                "--log-level" => cfg.log_level = arg_value(&mut args, &arg),
+                "--max-queue-len" => cfg.max_queue_len = arg_value(&mut args, &arg),
+                "--seed" => cfg.seed = Some(arg_value(&mut args, &arg)),
+                "--start-delay-ms" => cfg.start_delay_ms = arg_range(&mut args, &arg),
+                "--start-barrier" => cfg.start_barrier = Some(arg_value(&mut args, &arg)),
+                "--gap-ms" => cfg.gap_ms = arg_range(&mut args, &arg),
+                "--send-workers" => cfg.send_workers = arg_value(&mut args, &arg),
+                "--server-threads" => cfg.server_threads = arg_value(&mut args, &arg),
+                "--pool-max-idle" => cfg.pool_max_idle_per_host = arg_value(&mut args, &arg),
+                "--request-timeout-ms" => cfg.request_timeout_ms = arg_value(&mut args, &arg),
+                "--connect-timeout-ms" => cfg.connect_timeout_ms = arg_value(&mut args, &arg),
+                "--fifo" => cfg.fifo = true,
+                "--acquire-both" => cfg.acquire_both = true,
+                "--record" => cfg.record = true,
+                "--attempt-timeout-ms" => cfg.attempt_timeout = Duration::from_millis(arg_value(&mut args, &arg)),
+                "--transport" => cfg.transport = arg_value(&mut args, &arg),
+                "--multicast-group" => cfg.multicast_group = arg_value(&mut args, &arg),
+                "--wire-format" => cfg.wire_format = arg_value(&mut args, &arg),
+                "--gzip" => cfg.gzip = true,
+                "--id" => cfg.id = Some(arg_value(&mut args, &arg)),
+                "--max-rebroadcasts" => cfg.max_rebroadcasts = arg_value(&mut args, &arg),
+                "--rebroadcast-jitter" => cfg.rebroadcast_jitter = arg_value(&mut args, &arg),
+                "--quorum" => cfg.quorum = arg_value(&mut args, &arg),
+                "--weight" => {
+                    let v: String = arg_value(&mut args, &arg);
+                    let parsed = v.split_once('=').and_then(|(id, w)| Some((id.parse().ok()?, w.parse().ok()?)));
+                    let (id, w) = parsed.unwrap_or_else(|| panic!("--weight expects ID=WEIGHT, got {:?}", v));
+                    cfg.weights.insert(id, w);
+                }
+                "--priority" => {
+                    let v: String = arg_value(&mut args, &arg);
+                    let parsed = v.split_once('=').and_then(|(r, p)| Some((r.to_string(), p.parse().ok()?)));
+                    let (r, p) = parsed.unwrap_or_else(|| panic!("--priority expects RESOURCE=PRIORITY, got {:?}", v));
+                    cfg.priorities.insert(r, p);
+                }
+                "--node-priority" => {
+                    let v: String = arg_value(&mut args, &arg);
+                    let parsed = v.split_once('=').and_then(|(id, p)| Some((id.parse().ok()?, p.parse().ok()?)));
+                    let (id, p) = parsed.unwrap_or_else(|| panic!("--node-priority expects ID=PRIORITY, got {:?}", v));
+                    cfg.node_priorities.insert(id, p);
+                }
+                "--priority-inheritance" => cfg.priority_inheritance = true,
+                "--max-consecutive-entries" => cfg.max_consecutive_entries = Some(arg_value(&mut args, &arg)),
+                "--lease-grace-ms" => cfg.lease_grace = Some(Duration::from_millis(arg_value(&mut args, &arg))),
+                "--stuck-head-ms" => cfg.stuck_head_after = Some(Duration::from_millis(arg_value(&mut args, &arg))),
+                "--check-invariants-ms" => cfg.check_invariants_every = Some(Duration::from_millis(arg_value(&mut args, &arg))),
+                "--invariant-panic" => cfg.invariant_panic = true,
+                "--heartbeat-ms" => cfg.heartbeat_interval = Some(Duration::from_millis(arg_value(&mut args, &arg))),
+                "--rtt-probe-ms" => cfg.rtt_probe_every = Some(Duration::from_millis(arg_value(&mut args, &arg))),
+                "--suspect-after-ms" => cfg.suspect_after = Duration::from_millis(arg_value(&mut args, &arg)),
+                "--dead-after-ms" => cfg.dead_after = Duration::from_millis(arg_value(&mut args, &arg)),
+                "--rounds" => cfg.rounds = arg_value(&mut args, &arg),
+                "--workload" => cfg.workload = arg_value(&mut args, &arg),
+                "--duration-secs" => cfg.duration = Duration::from_secs(arg_value(&mut args, &arg)),
+                "--think-ms" => cfg.think_time = Duration::from_millis(arg_value(&mut args, &arg)),
+                "--think-dist" => cfg.think_dist = Some(arg_value(&mut args, &arg)),
+                "--deadline-secs" => cfg.deadline = Some(Duration::from_secs(arg_value(&mut args, &arg))),
+                "--counter-file" => cfg.counter_file = Some(arg_value(&mut args, &arg)),
+                "--print-config" => cfg.print_config = true,
+                "--config" => {
+                    let path: String = arg_value(&mut args, &arg);
+                    match config_file::load(&path) {
+                        Ok(v) => cfg.apply_file(&v),
+                        Err(problems) => {
+                            for p in problems {
+                                eprintln!("{}: {}", path, p);
+                            }
+                            std::process::exit(2);
+                        }
+                    }
+                }
+                "--topology" => {
+                    let path: String = arg_value(&mut args, &arg);
+                    match topology::load(&path) {
+                        Ok(t) => cfg.apply_topology(&t),
+                        Err(problems) => {
+                            for p in problems {
+                                eprintln!("{}: {}", path, p);
+                            }
+                            std::process::exit(2);
+                        }
+                    }
+                }
+                "--vector-clocks" => cfg.vector_clocks = true,
+                "--max-log-bytes" => cfg.max_log_bytes = Some(arg_value(&mut args, &arg)),
+                "--log-backups" => cfg.log_backups = arg_value(&mut args, &arg),
+                "--per-node-logs" => cfg.per_node_logs = true,
+                "--metrics-format" => cfg.metrics_format = arg_value(&mut args, &arg),
+                "--self-test" => cfg.self_test = true,
+                "--junit" => cfg.junit = Some(arg_value(&mut args, &arg)),
+                "--fast-path" => cfg.fast_path = true,
+                "--generation" => cfg.generation = arg_value(&mut args, &arg),
+                "--log-sink" => cfg.log_sink = Arc::new(arg_value(&mut args, &arg)),
+                "--log-flush" => cfg.log_buffer = Arc::new(LogBuffer::new(arg_value(&mut args, &arg))),
+                other => panic!("unknown argument: {}", other),
+            }
+        }
+        cfg
+    }
+
+    fn http_client(&self) -> Client {
+        Client::builder()
+            .pool_max_idle_per_host(self.pool_max_idle_per_host)
+            .timeout(Duration::from_millis(self.request_timeout_ms))
+            .connect_timeout(Duration::from_millis(self.connect_timeout_ms))
+            .build()
+            .expect("failed to build HTTP client")
+    }
+
+    fn transport(&self) -> Arc<dyn Transport> {
+        match self.transport {
+            TransportKind::Http => {
+                Arc::new(HttpTransport::new(self.http_client()).with_format(self.wire_format).with_gzip(self.gzip))
+            }
+            TransportKind::Multicast => Arc::new(
+                MulticastTransport::new(self.http_client(), self.multicast_group, self.wire_format, self.gzip)
+                    .expect("failed to open multicast socket"),
+            ),
+        }
+    }
+}
+
+/// Per-node (initial delay, gap between the A and B requests).
+fn node_delays(cfg: &Config, ids: &[usize]) -> HashMap<usize, (Duration, Duration)> {
+    let ms = Duration::from_millis;
+    match cfg.seed {
+        Some(seed) => {
+            let mut rng = StdRng::seed_from_u64(seed);
+            ids.iter()
+                .map(|&id| {
+                    let start = rng.gen_range(cfg.start_delay_ms.0..=cfg.start_delay_ms.1);
+                    let gap = rng.gen_range(cfg.gap_ms.0..=cfg.gap_ms.1);
+                    (id, (ms(start), ms(gap)))
+                })
+                .collect()
+        }
+        None => ids
+            .iter()
+            .map(|&id| (id, (Duration::from_secs(1 + id as u64), ms(200 + id as u64 * 100))))
+            .collect(),
+    }
+}
+
+#[derive(Clone)]
+struct Node {
+    id: usize,
+    port: u16,
+    peers: Vec<(usize, u16)>,
+    algorithm: Algorithm,
+    log_level: LogLevel,
+    max_queue_len: usize,
+    fifo: bool,
+    quorum: QuorumPolicy,
+    weights: HashMap<usize, u32>,
+    priorities: HashMap<String, u8>,
+    node_priorities: HashMap<usize, u8>,
+    /// What this node requests in its rounds.
+    resources: Vec<String>,
+    priority_inheritance: bool,
+    max_consecutive_entries: Option<u32>,
+    lease_grace: Option<Duration>,
+    stuck_head_after: Option<Duration>,
+    check_invariants_every: Option<Duration>,
+    invariant_panic: bool,
+    heartbeat_interval: Option<Duration>,
+    rtt_probe_every: Option<Duration>,
+    start_barrier: Option<usize>,
+    suspect_after: Duration,
+    dead_after: Duration,
+    attempt_timeout: Duration,
+    max_rebroadcasts: u32,
+    jitter: Jitter,
+    /// `--seed`, or 0; for anything a node draws at random.
+    seed: u64,
+    fast_path: bool,
+    state: Arc<Mutex<State>>,
+    /// Resources this node is executing a CS body for right now.
+    in_cs: Arc<Mutex<HashMap<String, bool>>>,
+    /// The thread running each of those bodies. Only a nested enter from
+    /// that thread is reentrant; another thread waits its turn.
+    cs_threads: Arc<Mutex<HashMap<String, thread::ThreadId>>>,
+    metrics: Arc<Mutex<Metrics>>,
+    metrics_format: MetricsFormat,
+    client: Client,
+    transport: Arc<dyn Transport>,
+    /// Fault injection applied by `send_to`, set through `/chaos`.
+    chaos: Arc<Mutex<chaos::ChaosConfig>>,
+    sender: Arc<SendPool>,
+    server_threads: usize,
+    log_file: Arc<Mutex<std::fs::File>>,
+    log_rotation: Option<LogRotation>,
+    log_sink: Arc<LogSink>,
+    log_buffer: Arc<LogBuffer>,
+    trace_file: Option<Arc<Mutex<std::fs::File>>>,
+    /// Replay mode: handle messages but never send anything to peers.
+    offline: bool,
+    clock: Arc<dyn Clock>,
+    vector_clocks: bool,
+    /// Decides who may enter the CS; `strategy::Lamport` by default.
+    strategy: Arc<dyn MutexAlgorithm>,
+    /// Set once `start_server` is listening, so `shutdown` can stop it.
+    server: Arc<Mutex<Option<Arc<Server>>>>,
+}
+
+#[derive(Debug)]
+struct State {
+    timestamp: MonotonicClock,
+    request_queues: HashMap<String, BinaryHeap<Reverse<(u64, usize)>>>,
+    replies: HashMap<String, HashSet<usize>>,
+    /// Token-ring mode: resources whose token this node currently holds.
+    tokens: HashSet<String>,
+    /// Token-ring mode: resources this node is waiting to enter.
+    wanted: HashSet<String>,
+    /// Coordinator mode: who holds and who awaits each resource this node
+    /// coordinates.
+    grants: HashMap<String, coordinator::Grants>,
+    /// Coordinator mode: resources granted to this node and not yet entered.
+    granted: HashSet<String>,
+    /// Fast-path leader: the node holding each fast-granted resource.
+    fast_grants: HashMap<String, usize>,
+    /// Fast-path leader: REQUESTs to answer once the fast grant is released.
+    deferred_replies: HashMap<String, Vec<RequestMsg>>,
+    /// Fast-path requester: the leader's answer, per resource.
+    fast_answers: HashMap<String, bool>,
+    fifo: FifoChannels,
+    /// Resources this node has an outstanding or held request for.
+    in_flight: HashMap<String, bool>,
+    /// `(resource, ts, from)` of requests already RELEASEd, so a REQUEST
+    /// that arrives after its own RELEASE isn't queued forever.
+    released: HashSet<(String, u64, usize)>,
+    /// `(resource, ts, from)` of queued requests that only want to read;
+    /// every other entry is a writer.
+    readers: HashSet<(String, u64, usize)>,
+    snapshots: snapshot::Recorder,
+    vclock: VectorClock,
+    /// Vector clock stamped on each of our outstanding REQUESTs.
+    request_vclocks: HashMap<String, VectorClock>,
+    /// Membership view this node is in; see `view`.
+    generation: u64,
+    /// Our own entries per resource since another node's last RELEASE.
+    consecutive: HashMap<String, u32>,
+    /// The head of each queue and when it got there; see `lease`.
+    head_since: HashMap<String, ((u64, usize), Duration)>,
+    /// Epoch of our latest REQUEST per resource.
+    epochs: HashMap<String, u64>,
+    /// When each peer's last heartbeat arrived, and what that makes it.
+    last_seen: HashMap<usize, Duration>,
+    liveness: HashMap<usize, Liveness>,
+    /// The Lamport clock at the last invariant check; see `invariants`.
+    checked_timestamp: u64,
+    /// Smoothed round trip to each peer; see `rtt`.
+    rtts: HashMap<usize, Duration>,
+    /// Resources whose waiting `enter_cs` should give up; see `cancel`.
+    cancelled: HashSet<String>,
+    /// Resources whose CS `/release` asked us to leave; see `preempt`.
+    preempted: HashSet<String>,
+    /// `--start-barrier`: nodes registered with us, and whether START has
+    /// been given.
+    registered: HashSet<usize>,
+    started: bool,
+}
+
+impl State {
+    fn mode_of(&self, resource: &str, (ts, from): (u64, usize)) -> LockMode {
+        if self.readers.contains(&(resource.to_string(), ts, from)) {
+            LockMode::Read
+        } else {
+            LockMode::Write
+        }
+    }
+
+    /// Drops repeated `(ts, id)` entries from every queue, since the order
+    /// between two identical entries is undefined. Returns one
+    /// `(resource, ts, from)` per copy dropped.
+    fn dedup_queues(&mut self) -> Vec<(String, u64, usize)> {
+        let mut dropped = vec![];
+        for (resource, q) in self.request_queues.iter_mut() {
+            if q.iter().collect::<HashSet<_>>().len() == q.len() {
+                continue;
+            }
+            let mut seen = HashSet::new();
+            for Reverse(entry) in std::mem::take(q).into_vec() {
+                if seen.insert(entry) {
+                    q.push(Reverse(entry));
+                } else {
+                    dropped.push((resource.clone(), entry.0, entry.1));
+                }
+            }
+        }
+        dropped
+    }
+
+    fn new() -> Self {
+        State {
+            timestamp: MonotonicClock::default(),
+            request_queues: RESOURCES.iter().map(|r| (r.to_string(), BinaryHeap::new())).collect(),
+            replies: RESOURCES.iter().map(|r| (r.to_string(), HashSet::new())).collect(),
+            tokens: HashSet::new(),
+            wanted: HashSet::new(),
+            grants: HashMap::new(),
+            granted: HashSet::new(),
+            fast_grants: HashMap::new(),
+            deferred_replies: HashMap::new(),
+            fast_answers: HashMap::new(),
+            fifo: FifoChannels::default(),
+            in_flight: HashMap::new(),
+            released: HashSet::new(),
+            readers: HashSet::new(),
+            snapshots: snapshot::Recorder::default(),
+            vclock: VectorClock::default(),
+            request_vclocks: HashMap::new(),
+            generation: 0,
+            consecutive: HashMap::new(),
+            head_since: HashMap::new(),
+            epochs: HashMap::new(),
+            last_seen: HashMap::new(),
+            liveness: HashMap::new(),
+            checked_timestamp: 0,
+            rtts: HashMap::new(),
+            cancelled: HashSet::new(),
+            preempted: HashSet::new(),
+            registered: HashSet::new(),
+            started: false,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Metrics {
+    /// Time from broadcasting a REQUEST to entering the CS, per resource.
+    cs_wait: HashMap<String, Vec<Duration>>,
+    /// Wall time of each completed A-then-B round, in order.
+    rounds: Vec<Duration>,
+    malformed_messages: u64,
+    /// REQUESTs sent, one per peer even when a broadcast carries them.
+    requests_sent: u64,
+    replies_received: u64,
+    cs_entries: u64,
+    /// Requests given up on for lack of replies or the token.
+    timeouts: u64,
+    /// CS entries granted by the `--fast-path` leader.
+    fast_path_entries: u64,
+    /// CS entries through the full Lamport broadcast.
+    slow_path_entries: u64,
+}
+
+struct LatencySummary {
+    count: usize,
+    min: Duration,
+    mean: Duration,
+    p50: Duration,
+    p95: Duration,
+    max: Duration,
+}
+
+impl LatencySummary {
+    fn from_samples(samples: &[Duration]) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        let mut sorted = samples.to_vec();
+        sorted.sort();
+        let pct = |p: f64| sorted[((p / 100.0) * (sorted.len() - 1) as f64).round() as usize];
+        Some(LatencySummary {
+            count: sorted.len(),
+            min: sorted[0],
+            mean: sorted.iter().sum::<Duration>() / sorted.len() as u32,
+            p50: pct(50.0),
+            p95: pct(95.0),
+            max: sorted[sorted.len() - 1],
+        })
+    }
+}
+
+fn print_latency_table(nodes: &[Node]) {
+    let mut by_resource: HashMap<String, Vec<Duration>> = HashMap::new();
+    for n in nodes {
+        for (res, samples) in &n.metrics.lock().unwrap().cs_wait {
+            by_resource.entry(res.clone()).or_default().extend(samples);
+        }
+    }
+    let mut resources: Vec<_> = by_resource.keys().cloned().collect();
+    resources.sort();
+    println!("CS wait latency (ms):");
+    println!("{:<10} {:>5} {:>8} {:>8} {:>8} {:>8} {:>8}", "resource", "n", "min", "mean", "p50", "p95", "max");
+    for res in resources {
+        if let Some(s) = LatencySummary::from_samples(&by_resource[&res]) {
+            let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+            println!(
+                "{:<10} {:>5} {:>8.1} {:>8.1} {:>8.1} {:>8.1} {:>8.1}",
+                res, s.count, ms(s.min), ms(s.mean), ms(s.p50), ms(s.p95), ms(s.max)
+            );
+        }
+    }
+
+    let mut by_round: Vec<Vec<Duration>> = vec![];
+    for n in nodes {
+        for (i, d) in n.metrics.lock().unwrap().rounds.iter().enumerate() {
+            if by_round.len() <= i {
+                by_round.resize(i + 1, vec![]);
+            }
+            by_round[i].push(*d);
+        }
+    }
+    if by_round.len() > 1 {
+        println!("Round duration (ms):");
+        println!("{:<10} {:>5} {:>8} {:>8} {:>8}", "round", "n", "min", "mean", "max");
+        for (i, samples) in by_round.iter().enumerate() {
+            if let Some(s) = LatencySummary::from_samples(samples) {
+                let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+                println!("{:<10} {:>5} {:>8.1} {:>8.1} {:>8.1}", i + 1, s.count, ms(s.min), ms(s.mean), ms(s.max));
+            }
+        }
+    }
+}
+
+impl Node {
+    fn new(id: usize, port: u16, peers: Vec<(usize, u16)>, cfg: &Config, log_file: Arc<Mutex<std::fs::File>>) -> Self {
+        Self {
+            id,
+            port,
+            peers,
+            algorithm: cfg.algorithm,
+            log_level: cfg.log_level,
+            max_queue_len: cfg.max_queue_len,
+            fifo: cfg.fifo,
+            quorum: cfg.quorum.clone(),
+            weights: cfg.weights.clone(),
+            priorities: cfg.priorities.clone(),
+            resources: cfg.node_resources.get(&id).cloned().unwrap_or_else(|| RESOURCES.iter().map(|r| r.to_string()).collect()),
+            node_priorities: cfg.node_priorities.clone(),
+            priority_inheritance: cfg.priority_inheritance,
+            max_consecutive_entries: cfg.max_consecutive_entries,
+            lease_grace: cfg.lease_grace,
+            stuck_head_after: cfg.stuck_head_after,
+            check_invariants_every: cfg.check_invariants_every,
+            invariant_panic: cfg.invariant_panic,
+            heartbeat_interval: cfg.heartbeat_interval,
+            rtt_probe_every: cfg.rtt_probe_every,
+            start_barrier: cfg.start_barrier,
+            suspect_after: cfg.suspect_after,
+            dead_after: cfg.dead_after,
+            attempt_timeout: cfg.attempt_timeout,
+            max_rebroadcasts: cfg.max_rebroadcasts,
+            jitter: cfg.rebroadcast_jitter,
+            seed: cfg.seed.unwrap_or(0),
+            fast_path: cfg.fast_path,
+            vector_clocks: cfg.vector_clocks,
+            state: Arc::new(Mutex::new(State { generation: cfg.generation, ..State::new() })),
+            in_cs: Arc::new(Mutex::new(HashMap::new())),
+            cs_threads: Arc::new(Mutex::new(HashMap::new())),
+            metrics: Arc::new(Mutex::new(Metrics::default())),
+            metrics_format: cfg.metrics_format,
+            client: cfg.http_client(),
+            transport: cfg.transport(),
+            chaos: Arc::default(),
+            sender: Arc::new(SendPool::new(cfg.send_workers)),
+            server_threads: cfg.server_threads,
+            log_file,
+            log_rotation: None,
+            log_sink: cfg.log_sink.clone(),
+            log_buffer: cfg.log_buffer.clone(),
+            trace_file: None,
+            offline: false,
+            clock: Arc::new(SystemClock),
+            strategy: Arc::new(strategy::Lamport),
+            server: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    fn with_trace(mut self, trace_file: Arc<Mutex<std::fs::File>>) -> Self {
+        self.trace_file = Some(trace_file);
+        self
+    }
+
+    fn with_log_rotation(mut self, rotation: Option<LogRotation>) -> Self {
+        self.log_rotation = rotation;
+        self
+    }
+
+    fn offline(mut self) -> Self {
+        self.offline = true;
+        self
+    }
+
+    #[cfg(test)]
+    fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    fn log(&self, msg: &str) {
+        self.log_at(LogLevel::Info, msg);
+    }
+
+    /// Suffix for send/receive log lines that `latency` joins on.
+    fn trace_tag(&self, trace_id: Uuid) -> String {
+        format!("trace={} at_ms={}", trace_id, self.clock.now().as_millis())
+    }
+
+    fn log_at(&self, level: LogLevel, msg: &str) {
+        if level > self.log_level {
+            return;
+        }
+        let now = self.clock.now().as_secs();
+        let line = dc_common::log::line(now, self.id, msg);
+        match &*self.log_sink {
+            LogSink::File => {}
+            LogSink::Stdout => {
+                print!("{}", line);
+                return;
+            }
+            LogSink::Tcp(tcp) => {
+                if let Err(e) = tcp.send(now, self.id, level.as_str(), msg) {
+                    eprintln!("[Node {}] cannot ship log line: {}: {}", self.id, e, line.trim_end());
+                }
+                return;
+            }
+        }
+        print!("{}", line);
+        if self.log_buffer.policy != LogFlush::EveryLine {
+            self.log_buffer.push(&self.log_file, &self.log_rotation, line);
+            return;
+        }
+        dc_common::log::append(self.id, &self.log_file, self.log_rotation.as_ref(), &line);
+    }
+
+    /// Writes out whatever `--log-flush` is still holding back.
+    fn flush_log(&self) {
+        if let Err(e) = self.log_buffer.flush() {
+            eprintln!("[Node {}] cannot flush log: {}", self.id, e);
+        }
+    }
+
+    fn start_server(&self) {
+        let addr = format!("0.0.0.0:{}", self.port);
+        let server = Arc::new(Server::http(&addr).unwrap());
+        *self.server.lock().unwrap() = Some(server.clone());
+        self.log(&format!("Server started on {} with {} handler threads", addr, self.server_threads));
+        // Every worker pulls from the same server, so one slow handler
+        // doesn't hold up the messages queued behind it.
+        for _ in 0..self.server_threads.max(1) {
+            let (node, server) = (self.clone(), server.clone());
+            thread::spawn(move || {
+                for mut req in server.incoming_requests() {
+                    let url = req.url().to_string();
+                    let (content_type, content_encoding) = (header(&req, "Content-Type"), header(&req, "Content-Encoding"));
+                    let mut body = vec![];
+                    let _ = req.as_reader().read_to_end(&mut body);
+                    let len = body.len();
+                    let decoded = compress::decode_body(content_encoding.as_deref(), body)
+                        .and_then(|body| WireFormat::of_content_type(content_type.as_deref()).wire_to_json(&body));
+                    let resp = match decoded {
+                        Ok(content) => node.handle(&url, &content),
+                        Err(e) => node.bad_payload("wire", &format!("{} bytes", len), e),
+                    };
+                    let _ = req.respond(resp);
+                }
+            });
+        }
+        let node = self.clone();
+        if let Err(e) = self.transport.clone().listen(Box::new(move |datagram| {
+            // Our own broadcasts loop back to us too.
+            if serde_json::from_str::<RequestMsg>(datagram).is_ok_and(|m| m.from == node.id) {
+                return;
+            }
+            let _ = node.handle("/receive_request", datagram);
+        })) {
+            self.log_at(LogLevel::Error, &format!("Cannot listen for broadcasts: {}", e));
+        }
+    }
+
+    fn handle(&self, url: &str, content: &str) -> HttpResponse {
+        self.log_at(LogLevel::Trace, &format!("HTTP {} ({} bytes)", url, content.len()));
+        let (url, query) = url.split_once('?').unwrap_or((url, ""));
+        match url {
+            "/ping" => Response::from_string("pong"),
+            "/receive_request" => match decode::<RequestMsg>(content) {
+                Ok(msg) if self.is_stale("REQUEST", msg.from, msg.generation) => stale_view(),
+                Ok(msg) => {
+                    if self.deliver(Inbound::Request(msg)) {
+                        Response::from_string("OK")
+                    } else {
+                        Response::from_string("queue full").with_status_code(503)
+                    }
+                }
+                Err(e) => self.bad_payload("REQUEST", content, e),
+            },
+            "/receive_reply" => match decode::<ReplyMsg>(content) {
+                Ok(msg) if self.is_stale("REPLY", msg.from, msg.generation) => stale_view(),
+                Ok(msg) => {
+                    self.deliver(Inbound::Reply(msg));
+                    Response::from_string("OK")
+                }
+                Err(e) => self.bad_payload("REPLY", content, e),
+            },
+            "/receive_reply_batch" => match decode::<ReplyBatchMsg>(content) {
+                Ok(msg) if self.is_stale("REPLY batch", &msg.from, msg.generation) => stale_view(),
+                Ok(msg) => {
+                    self.receive_reply_batch(msg);
+                    Response::from_string("OK")
+                }
+                Err(e) => self.bad_payload("REPLY batch", content, e),
+            },
+            "/receive_release" => match decode::<ReleaseMsg>(content) {
+                Ok(msg) if self.is_stale("RELEASE", msg.from, msg.generation) => stale_view(),
+                Ok(msg) => {
+                    self.receive_release(msg);
+                    Response::from_string("OK")
+                }
+                Err(e) => self.bad_payload("RELEASE", content, e),
+            },
+            "/receive_cancel" => match decode::<ReleaseMsg>(content) {
+                Ok(msg) if self.is_stale("CANCEL", msg.from, msg.generation) => stale_view(),
+                Ok(msg) => {
+                    self.receive_cancel(msg);
+                    Response::from_string("OK")
+                }
+                Err(e) => self.bad_payload("CANCEL", content, e),
+            },
+            // Control and non-Lamport messages skip the view check; view.rs says why.
+            "/register" => match decode::<BarrierMsg>(content) {
+                Ok(msg) => {
+                    self.receive_register(msg);
+                    Response::from_string("OK")
+                }
+                Err(e) => self.bad_payload("REGISTER", content, e),
+            },
+            "/start" => match decode::<BarrierMsg>(content) {
+                Ok(msg) => {
+                    self.receive_start(msg);
+                    Response::from_string("OK")
+                }
+                Err(e) => self.bad_payload("START", content, e),
+            },
+            "/receive_token" => match decode::<TokenMsg>(content) {
+                Ok(msg) => {
+                    self.receive_token(msg);
+                    Response::from_string("OK")
+                }
+                Err(e) => self.bad_payload("TOKEN", content, e),
+            },
+            "/coord_request" | "/coord_grant" | "/coord_release" => match decode::<CoordMsg>(content) {
+                Ok(msg) => {
+                    match url {
+                        "/coord_request" => self.receive_coord_request(msg),
+                        "/coord_grant" => self.receive_coord_grant(msg),
+                        _ => self.receive_coord_release(msg),
+                    }
+                    Response::from_string("OK")
+                }
+                Err(e) => self.bad_payload("COORD", content, e),
+            },
+            "/fast_request" | "/fast_answer" | "/fast_release" => match decode::<FastMsg>(content) {
+                Ok(msg) => {
+                    match url {
+                        "/fast_request" => self.receive_fast_request(msg),
+                        "/fast_answer" => self.receive_fast_answer(msg),
+                        _ => self.receive_fast_release(msg),
+                    }
+                    Response::from_string("OK")
+                }
+                Err(e) => self.bad_payload("FAST", content, e),
+            },
+            "/" => Response::from_string(DASHBOARD_HTML)
+                .with_header(Header::from_bytes("Content-Type", "text/html; charset=utf-8").unwrap()),
+            "/marker" => match decode::<MarkerMsg>(content) {
+                Ok(msg) => {
+                    self.deliver(Inbound::Marker(msg));
+                    Response::from_string("OK")
+                }
+                Err(e) => self.bad_payload("MARKER", content, e),
+            },
+            "/heartbeat" => match decode::<HeartbeatMsg>(content) {
+                Ok(msg) => {
+                    self.receive_heartbeat(msg);
+                    Response::from_string("OK")
+                }
+                Err(e) => self.bad_payload("HEARTBEAT", content, e),
+            },
+            "/peers" => Response::from_string(serde_json::to_string(&self.peer_statuses()).unwrap()).with_header(json_header()),
+            "/view" => {
+                if !content.trim().is_empty() {
+                    match decode::<ViewMsg>(content) {
+                        Ok(v) => self.adopt_view(v.generation),
+                        Err(e) => return self.bad_payload("VIEW", content, e),
+                    }
+                }
+                let body = serde_json::to_string(&ViewMsg { generation: self.generation() }).unwrap();
+                Response::from_string(body).with_header(json_header())
+            }
+            "/chaos" => match self.update_chaos(content) {
+                Ok(rules) => Response::from_string(rules).with_header(json_header()),
+                Err(e) => Response::from_string(e).with_status_code(400),
+            },
+            "/snapshot/start" => {
+                let id = self.start_snapshot();
+                Response::from_string(serde_json::json!({ "snapshot_id": id }).to_string()).with_header(json_header())
+            }
+            "/snapshot" => Response::from_string(serde_json::to_string(&self.snapshot()).unwrap()).with_header(json_header()),
+            "/metrics" => {
+                let body = self.metrics.lock().unwrap().render(self.id, self.metrics_format);
+                let content_type = Header::from_bytes("Content-Type", self.metrics_format.content_type()).unwrap();
+                Response::from_string(body).with_header(content_type)
+            }
+            "/status" => Response::from_string(self.status_json().to_string())
+                .with_header(json_header())
+                .with_header(cors_header()),
+            "/release" => {
+                let resource = query_param(query, "resource").unwrap_or_default();
+                if self.preempt(resource) {
+                    Response::from_string("OK")
+                } else {
+                    Response::from_string(format!("not holding resource={}", resource)).with_status_code(409)
+                }
+            }
+            "/cancel" => {
+                let resource = query_param(query, "resource").unwrap_or_default();
+                if self.cancel_request(resource) {
+                    Response::from_string("OK")
+                } else {
+                    Response::from_string(format!("no request waiting on resource={}", resource)).with_status_code(409)
+                }
+            }
+            "/queue_position" => {
+                let resource = query_param(query, "resource").unwrap_or_default();
+                let Some(node) = query_param(query, "node").and_then(|v| v.parse::<usize>().ok()) else {
+                    return Response::from_string("expected ?resource=R&node=ID").with_status_code(400);
+                };
+                match self.queue_position(resource, node) {
+                    Ok(position) => {
+                        let body = serde_json::json!({ "resource": resource, "node": node, "position": position });
+                        Response::from_string(body.to_string()).with_header(json_header()).with_header(cors_header())
+                    }
+                    Err(e) => Response::from_string(e.to_string()).with_status_code(404),
+                }
+            }
+            _ => Response::from_string("OK"),
+        }
+    }
+
+    /// `node`'s place in this node's queue for `resource`, 0 being next to
+    /// enter, or None if it has no request queued here.
+    fn queue_position(&self, resource: &str, node: usize) -> Result<Option<usize>, NodeError> {
+        let st = self.state.lock().unwrap();
+        let q = st.request_queues.get(resource).ok_or_else(|| NodeError::UnknownResource(resource.to_string()))?;
+        let mut queue: Vec<(u64, usize)> = q.iter().map(|Reverse(e)| *e).collect();
+        queue.sort();
+        Ok(queue.iter().position(|&(_, nid)| nid == node))
+    }
+
+    /// Every queue and our part in it. Per resource, `queue_depth`,
+    /// `replies_received` for our outstanding request, `waiting` (requested
+    /// but not yet in the CS) and `entries` so far give a one-shot health
+    /// view.
+    fn status_json(&self) -> serde_json::Value {
+        let entries: HashMap<String, usize> =
+            self.metrics.lock().unwrap().cs_wait.iter().map(|(res, waits)| (res.clone(), waits.len())).collect();
+        let in_cs = self.in_cs.lock().unwrap().clone();
+        let st = self.state.lock().unwrap();
+        let mut resources = serde_json::Map::new();
+        for (res, q) in &st.request_queues {
+            let mut queue: Vec<(u64, usize)> = q.iter().map(|Reverse(e)| *e).collect();
+            queue.sort();
+            let mut replies: Vec<usize> = st.replies.get(res).map(|r| r.iter().cloned().collect()).unwrap_or_default();
+            replies.sort();
+            let in_flight = st.in_flight.get(res).copied().unwrap_or(false);
+            let holding = in_cs.get(res).copied().unwrap_or(false);
+            resources.insert(
+                res.clone(),
+                serde_json::json!({
+                    "queue": queue,
+                    "queue_depth": queue.len(),
+                    "replies": replies,
+                    "replies_received": replies.len(),
+                    "in_flight": in_flight,
+                    "in_cs": holding,
+                    "waiting": in_flight && !holding,
+                    "entries": entries.get(res).copied().unwrap_or(0),
+                }),
+            );
+        }
+        serde_json::json!({ "id": self.id, "timestamp": st.timestamp.get(), "peers": self.peers, "resources": resources })
+    }
+
+    /// Hands a REQUEST/REPLY to the protocol, in per-sender send order when
+    /// FIFO mode is on. Returns false only if the message itself was a
+    /// REQUEST rejected for a full queue.
+    fn deliver(&self, msg: Inbound) -> bool {
+        if let Some(f) = &self.trace_file {
+            trace::record(f, self.id, &msg, self.vclock_on_receipt(msg.vclock()));
+        }
+        let (from, seq) = (msg.from(), msg.seq());
+        let ready = if self.fifo && seq > 0 {
+            let mut st = self.state.lock().unwrap();
+            let ready = st.fifo.accept(from, msg);
+            if ready.is_empty() {
+                let held = st.fifo.buffered_from(from);
+                drop(st);
+                self.log_at(LogLevel::Debug, &format!("Buffering out-of-order message seq={} from {} ({} held)", seq, from, held));
+            }
+            ready
+        } else {
+            vec![msg]
+        };
+        let mut accepted = true;
+        for m in ready {
+            if !matches!(m, Inbound::Marker(_)) {
+                self.state.lock().unwrap().snapshots.note_received(m.from(), &m);
+            }
+            match m {
+                Inbound::Request(r) => {
+                    let this = r.seq == seq;
+                    if !self.receive_request(r) && this {
+                        accepted = false;
+                    }
+                }
+                Inbound::Reply(r) => self.receive_reply(r),
+                Inbound::Marker(r) => self.receive_marker(r),
+            }
+        }
+        accepted
+    }
+
+    /// Sequence number for the next REQUEST/REPLY to `dest`, counted as
+    /// sent for snapshots in the same step.
+    fn next_seq(&self, dest: usize) -> u64 {
+        let mut st = self.state.lock().unwrap();
+        st.snapshots.note_sent(dest);
+        if self.fifo {
+            st.fifo.next_seq(dest)
+        } else {
+            0
+        }
+    }
+
+    fn bad_payload(&self, kind: &str, content: &str, err: NodeError) -> HttpResponse {
+        self.log_at(LogLevel::Error, &format!("Bad {} payload: {}", kind, content));
+        self.metrics.lock().unwrap().malformed_messages += 1;
+        let body = serde_json::json!({ "error": format!("malformed {} payload: {}", kind, err) });
+        Response::from_string(body.to_string())
+            .with_status_code(400)
+            .with_header(json_header())
+    }
+
+    fn ping(&self, port: u16) -> bool {
+        let url = format!("http://127.0.0.1:{}/ping", port);
+        matches!(self.client.get(&url).send(), Ok(r) if r.status().is_success())
+    }
+
+    fn wait_for_peers(&self, timeout: Duration) -> bool {
+        if let Err(ids) = dc_common::http::wait_for_peers(&self.peers, timeout, |port| self.ping(port)) {
+            self.log_at(LogLevel::Error, &format!("Timed out waiting for peers {:?}", ids));
+            return false;
+        }
+        self.log("All peers reachable");
+        true
+    }
+
+    /// Returns the new request's timestamp, or None, without sending, if
+    /// this node already has a request in flight for `resource`.
+    fn broadcast_request(&self, resource: &str, mode: LockMode) -> Option<u64> {
+        let ts = {
+            let mut st = self.state.lock().unwrap();
+            if st.in_flight.get(resource).copied().unwrap_or(false) {
+                drop(st);
+                self.log(&format!("Refusing REQUEST for resource={}: already in flight", resource));
+                return None;
+            }
+            st.in_flight.insert(resource.to_string(), true);
+            *st.epochs.entry(resource.to_string()).or_default() += 1;
+            let ts = st.timestamp.tick();
+            if let Some(q) = st.request_queues.get_mut(resource) {
+                q.push(Reverse((ts, self.id)));
+            }
+            if mode == LockMode::Read {
+                st.readers.insert((resource.to_string(), ts, self.id));
+            }
+            if let Some(rset) = st.replies.get_mut(resource) {
+                rset.clear();
+            }
+            if self.vector_clocks {
+                st.vclock.tick(self.id);
+                let stamp = st.vclock.clone();
+                st.request_vclocks.insert(resource.to_string(), stamp);
+            }
+            ts
+        };
+
+        self.log_at(
+            LogLevel::Debug,
+            &format!("Broadcasting REQUEST ts={} for resource={}{}", ts, resource, mode_suffix(mode)),
+        );
+        if !self.offline {
+            // One datagram can't carry a per-peer FIFO seq, so it goes
+            // unsequenced.
+            // Bound first: a guard taken inside the literal would live to
+            // the end of it and deadlock `generation()`.
+            let vclock = self.state.lock().unwrap().request_vclocks.get(resource).cloned();
+            let msg = RequestMsg {
+                from: self.id,
+                ts,
+                resource: resource.to_string(),
+                seq: 0,
+                trace_id: Uuid::new_v4(),
+                mode,
+                vclock,
+                generation: self.generation(),
+                epoch: self.request_epoch(resource),
+            };
+            {
+                let mut st = self.state.lock().unwrap();
+                for (nid, _) in &self.peers {
+                    st.snapshots.note_sent(*nid);
+                }
+            }
+            self.log_at(LogLevel::Debug, &format!("Sent REQUEST ts={} for resource={} to all {}", ts, resource, self.trace_tag(msg.trace_id)));
+            match self.transport.broadcast(&serde_json::to_string(&msg).unwrap()) {
+                Ok(true) => {
+                    self.metrics.lock().unwrap().requests_sent += self.peers.len() as u64;
+                    return Some(ts);
+                }
+                Ok(false) => {}
+                Err(e) => self.log_at(LogLevel::Error, &format!("Error broadcasting REQUEST: {}; falling back to unicast", e)),
+            }
+        }
+        for (nid, port) in self.peers_by_rtt() {
+            self.send_request_to(nid, port, resource, ts, mode);
+        }
+        Some(ts)
+    }
+
+    fn request_epoch(&self, resource: &str) -> u64 {
+        self.state.lock().unwrap().epochs.get(resource).copied().unwrap_or(0)
+    }
+
+    fn send_request_to(&self, nid: usize, port: u16, resource: &str, ts: u64, mode: LockMode) {
+        if self.offline {
+            return;
+        }
+        let vclock = self.state.lock().unwrap().request_vclocks.get(resource).cloned();
+        let msg = RequestMsg {
+            from: self.id,
+            ts,
+            resource: resource.to_string(),
+            seq: self.next_seq(nid),
+            trace_id: Uuid::new_v4(),
+            mode,
+            vclock,
+            generation: self.generation(),
+            epoch: self.request_epoch(resource),
+        };
+        let payload = serde_json::to_string(&msg).unwrap();
+        self.metrics.lock().unwrap().requests_sent += 1;
+        let node = self.clone();
+        self.sender.execute(move || {
+            node.log_at(
+                LogLevel::Debug,
+                &format!("Sent REQUEST ts={} for resource={} to {} {}", ts, msg.resource, nid, node.trace_tag(msg.trace_id)),
+            );
+            if let Err(e) = node.send_to(port, "/receive_request", &payload) {
+                node.log_at(LogLevel::Error, &format!("Error sending REQUEST to {}: {}", nid, e));
+            }
+        });
+    }
+
+    /// Delivers to node `to`, which may be this node itself.
+    fn send_to_node(&self, to: usize, path: &str, payload: &str) -> Result<(), NodeError> {
+        if to == self.id {
+            self.handle(path, payload);
+            return Ok(());
+        }
+        let (_, port) = self.peers.iter().find(|(nid, _)| *nid == to).ok_or_else(|| NodeError::Delivery(format!("no peer {}", to)))?;
+        self.send_to(*port, path, payload)
+    }
+
+    /// Re-sends the outstanding request to every peer that hasn't replied.
+    fn rebroadcast_request(&self, resource: &str, ts: u64, attempt: u32) {
+        let (missing, mode): (Vec<(usize, u16)>, LockMode) = {
+            let st = self.state.lock().unwrap();
+            let replied = st.replies.get(resource);
+            let missing = self
+                .peers
+                .iter()
+                .filter(|(nid, _)| !replied.is_some_and(|r| r.contains(nid)) && st.counts_on(*nid))
+                .cloned()
+                .collect();
+            (missing, st.mode_of(resource, (ts, self.id)))
+        };
+        if missing.is_empty() {
+            return;
+        }
+        let ids: Vec<usize> = missing.iter().map(|(nid, _)| *nid).collect();
+        self.log(&format!(
+            "Re-broadcasting REQUEST ts={} for resource={} to {:?} (attempt {})",
+            ts, resource, ids, attempt
+        ));
+        for (nid, port) in missing {
+            self.send_request_to(nid, port, resource, ts, mode);
+        }
+    }
+
+    /// Gives up on request `ts`: drops it locally and tells every peer to
+    /// drop it too, so it can't sit at the head of their queues forever.
+    fn abort_request(&self, resource: &str, ts: u64) {
+        self.drop_own_request(resource, ts);
+        self.log(&format!("Aborting REQUEST ts={} for resource={}; broadcasting RELEASE", ts, resource));
+        self.broadcast_release(resource, ts);
+    }
+
+    /// Forgets our unserved request `ts` locally.
+    fn drop_own_request(&self, resource: &str, ts: u64) {
+        let mut st = self.state.lock().unwrap();
+        if let Some(q) = st.request_queues.get_mut(resource) {
+            q.retain(|Reverse(e)| *e != (ts, self.id));
+        }
+        st.readers.remove(&(resource.to_string(), ts, self.id));
+        st.request_vclocks.remove(resource);
+        if let Some(rset) = st.replies.get_mut(resource) {
+            rset.clear();
+        }
+        st.in_flight.insert(resource.to_string(), false);
+    }
+
+    fn broadcast_release(&self, resource: &str, ts: u64) {
+        self.broadcast_withdrawal("/receive_release", "RELEASE", resource, ts);
+    }
+
+    /// Tells every peer to drop our request `ts`, as a RELEASE or a CANCEL.
+    fn broadcast_withdrawal(&self, path: &'static str, kind: &'static str, resource: &str, ts: u64) {
+        if self.offline {
+            return;
+        }
+        let payload = serde_json::to_string(&ReleaseMsg { from: self.id, ts, resource: resource.to_string(), generation: self.generation() }).unwrap();
+        for (nid, port) in self.peers.clone() {
+            let node = self.clone();
+            let payload = payload.clone();
+            self.sender.execute(move || {
+                if let Err(e) = node.send_to(port, path, &payload) {
+                    node.log_at(LogLevel::Error, &format!("Error sending {} to {}: {}", kind, nid, e));
+                }
+            });
+        }
+    }
+
+    fn receive_release(&self, msg: ReleaseMsg) {
+        {
+            let mut st = self.state.lock().unwrap();
+            if let Some(q) = st.request_queues.get_mut(&msg.resource) {
+                q.retain(|Reverse(e)| *e != (msg.ts, msg.from));
+            }
+            st.readers.remove(&(msg.resource.clone(), msg.ts, msg.from));
+            st.released.insert((msg.resource.clone(), msg.ts, msg.from));
+            if msg.from != self.id {
+                st.consecutive.remove(&msg.resource);
+            }
+        }
+        self.log_at(
+            LogLevel::Debug,
+            &format!("Received RELEASE from {} ts={} for resource={}", msg.from, msg.ts, msg.resource),
+        );
+    }
+
+    /// Returns false if the request was rejected because the queue is full.
+    fn receive_request(&self, msg: RequestMsg) -> bool {
+        self.vclock_observe_request(&msg);
+        let admission = self.strategy.on_request_received(self, &mut self.state.lock().unwrap(), &msg);
+        match admission {
+            Admission::Queued => {}
+            Admission::QueueFull => {
+                self.log_at(
+                    LogLevel::Error,
+                    &format!(
+                        "Dropping REQUEST from {} ts={} for resource={}: queue full ({} entries)",
+                        msg.from, msg.ts, msg.resource, self.max_queue_len
+                    ),
+                );
+                return false;
+            }
+            Admission::UnknownResource => self.log_at(
+                LogLevel::Error,
+                &format!("REQUEST from {} names unknown resource={}; replying anyway", msg.from, msg.resource),
+            ),
+        }
+        self.log_at(
+            LogLevel::Debug,
+            &format!(
+                "Received REQUEST from {} ts={} for resource={} {}",
+                msg.from, msg.ts, msg.resource, self.trace_tag(msg.trace_id)
+            ),
+        );
+        if self.offline || self.defer_reply(&msg) {
+            return true;
+        }
+        self.send_reply(&msg);
+        true
+    }
+
+    fn send_reply(&self, msg: &RequestMsg) {
+        if let Some((_nid, port)) = self.peers.iter().find(|(nid, _)| *nid == msg.from) {
+            let reply = ReplyMsg {
+                from: self.id,
+                resource: msg.resource.clone(),
+                seq: self.next_seq(msg.from),
+                ts: msg.ts,
+                trace_id: msg.trace_id,
+                vclock: self.vclock_send(),
+                generation: self.generation(),
+                epoch: msg.epoch,
+            };
+            let payload = serde_json::to_string(&reply).unwrap();
+            self.log_at(
+                LogLevel::Debug,
+                &format!("Sent REPLY for resource={} to {} {}", reply.resource, msg.from, self.trace_tag(reply.trace_id)),
+            );
+            if let Err(e) = self.send_to(*port, "/receive_reply", &payload) {
+                self.log_at(LogLevel::Error, &format!("Error sending REPLY to {}: {}", msg.from, e));
+            }
+        }
+    }
+
+    fn receive_reply(&self, msg: ReplyMsg) {
+        self.vclock_receive(msg.vclock.as_ref());
+        if !self.strategy.on_reply_received(self, &mut self.state.lock().unwrap(), &msg) {
+            self.log_at(
+                LogLevel::Info,
+                &format!("STRAY_REPLY from {} ts={} epoch={} for resource={}; ignoring", msg.from, msg.ts, msg.epoch, msg.resource),
+            );
+            return;
+        }
+        self.metrics.lock().unwrap().replies_received += 1;
+        self.log_at(
+            LogLevel::Debug,
+            &format!("Received REPLY from {} for resource={} {}", msg.from, msg.resource, self.trace_tag(msg.trace_id)),
+        );
+    }
+
+    /// Holds every resource in `resources` while running `body`. Resources
+    /// are always acquired in one global order (highest priority first,
+    /// then by name) and released in reverse, so two nodes asking for {A, B}
+    /// and {B, A} can never each hold one while waiting on the other: a
+    /// circular wait needs someone to acquire out of order. Every node must
+    /// therefore share the same `--priority` settings.
+    fn acquire_all_ordered<F: FnOnce()>(&self, resources: &[&str], body: F) -> bool {
+        let sorted = self.acquisition_order(resources);
+        self.enter_nested(&sorted, body)
+    }
+
+    fn acquisition_order<'a>(&self, resources: &[&'a str]) -> Vec<&'a str> {
+        let mut sorted = resources.to_vec();
+        sorted.sort_by_key(|r| (Reverse(self.priorities.get(*r).copied().unwrap_or(0)), *r));
+        sorted.dedup();
+        sorted
+    }
+
+    fn enter_nested<F: FnOnce()>(&self, resources: &[&str], body: F) -> bool {
+        match resources.split_first() {
+            None => {
+                body();
+                true
+            }
+            Some((first, rest)) => {
+                let mut inner_ok = false;
+                let entered = self.enter_cs_with(first, || inner_ok = self.enter_nested(rest, body));
+                entered && inner_ok
+            }
+        }
+    }
+
+    fn record_cs_entry(&self, resource: &str, waited: Duration) {
+        let mut m = self.metrics.lock().unwrap();
+        m.cs_entries += 1;
+        m.cs_wait.entry(resource.to_string()).or_default().push(waited);
+    }
+
+    /// Runs `body` between the Entering/Exiting log lines with `in_cs` set.
+    fn run_cs_body<F: FnOnce()>(&self, resource: &str, body: F) {
+        self.mark_in_cs(resource, true);
+        body();
+        self.state.lock().unwrap().preempted.remove(resource);
+        self.mark_in_cs(resource, false);
+    }
+
+    fn mark_in_cs(&self, resource: &str, inside: bool) {
+        if inside {
+            let mode = {
+                let st = self.state.lock().unwrap();
+                let mine = st.request_queues.get(resource).and_then(|q| q.iter().map(|Reverse(e)| *e).find(|e| e.1 == self.id));
+                mine.map(|e| st.mode_of(resource, e)).unwrap_or_default()
+            };
+            self.log(&format!("Entering Critical Section for resource={}{}", resource, mode_suffix(mode)));
+            self.in_cs.lock().unwrap().insert(resource.to_string(), true);
+            self.cs_threads.lock().unwrap().insert(resource.to_string(), thread::current().id());
+        } else {
+            self.cs_threads.lock().unwrap().remove(resource);
+            self.in_cs.lock().unwrap().insert(resource.to_string(), false);
+            self.log(&format!("Exiting Critical Section for resource={}", resource));
+        }
+    }
+
+    /// Serializes this node's own acquisitions of one resource.
+    fn wait_until_not_in_flight(&self, resource: &str) {
+        while self.state.lock().unwrap().in_flight.get(resource).copied().unwrap_or(false) {
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    /// A fast grant held by another node blocks entry too; only the leader
+    /// ever has one on record.
+    fn can_enter_cs(&self, resource: &str) -> bool {
+        let dropped = self.state.lock().unwrap().dedup_queues();
+        for (r, ts, from) in dropped {
+            self.log_at(LogLevel::Error, &format!("DUPLICATE_ENTRY resource={} ts={} from={}: dropped the extra copy", r, ts, from));
+        }
+        let st = self.state.lock().unwrap();
+        st.fast_grants.get(resource).is_none_or(|h| *h == self.id) && self.strategy.can_enter(self, &st, resource)
+    }
+
+    fn enter_cs(&self, resource: &str) {
+        self.enter_cs_with(resource, || self.hold_cs(resource));
+    }
+
+    /// Drops our satisfied request `ts` after leaving the CS, wherever it
+    /// sits in the queue, and releases it to every peer.
+    fn finish_cs(&self, resource: &str, ts: u64) {
+        {
+            let mut st = self.state.lock().unwrap();
+            self.strategy.on_exit(self, &mut st, resource, ts);
+            st.request_vclocks.remove(resource);
+            // A cancel that lost the race to enter has nothing left to cancel.
+            st.cancelled.remove(resource);
+        }
+        self.broadcast_release(resource, ts);
+    }
+
+    fn enter_cs_with<F: FnOnce()>(&self, resource: &str, body: F) -> bool {
+        self.enter_cs_as(resource, LockMode::Write, body)
+    }
+
+    /// Runs `body` while holding `resource` in `mode`. Returns false,
+    /// without running `body`, if the node gave up waiting.
+    fn enter_cs_as<F: FnOnce()>(&self, resource: &str, mode: LockMode, body: F) -> bool {
+        match self.try_enter_cs_as(resource, mode, body) {
+            Ok(()) => true,
+            Err(NodeError::Timeout | NodeError::Cancelled) => false,
+            Err(NodeError::Reentrant(r)) => {
+                self.log_at(LogLevel::Error, &format!("Rejected reentrant enter_cs for resource={}: already inside its CS", r));
+                false
+            }
+            Err(NodeError::UnknownResource(r)) => {
+                self.log_at(LogLevel::Error, &format!("Rejected enter_cs for unknown resource={} (known: {:?})", r, RESOURCES));
+                false
+            }
+            Err(e) => {
+                self.log_at(LogLevel::Error, &format!("Error entering CS for resource={}: {}", resource, e));
+                false
+            }
+        }
+    }
+
+    /// `enter_cs_as`, saying why it didn't enter. Timeouts are logged, and
+    /// the request withdrawn, before `NodeError::Timeout` comes back.
+    fn try_enter_cs_as<F: FnOnce()>(&self, resource: &str, mode: LockMode, body: F) -> Result<(), NodeError> {
+        if self.cs_threads.lock().unwrap().get(resource) == Some(&thread::current().id()) {
+            return Err(NodeError::Reentrant(resource.to_string()));
+        }
+        if !self.state.lock().unwrap().request_queues.contains_key(resource) {
+            return Err(NodeError::UnknownResource(resource.to_string()));
+        }
+        if self.algorithm == Algorithm::TokenRing {
+            return self.enter_cs_token_ring(resource, body);
+        }
+        if self.algorithm == Algorithm::Coordinator {
+            return self.enter_cs_coordinator(resource, body);
+        }
+        let mut body = Some(body);
+        if self.fast_path && mode == LockMode::Write && self.try_fast_path(resource, || (body.take().unwrap())()) {
+            return Ok(());
+        }
+        let body = body.unwrap();
+        self.wait_until_not_in_flight(resource);
+        self.yield_turn(resource);
+        let requested_at = self.clock.now();
+        let ts = loop {
+            if let Some(ts) = self.broadcast_request(resource, mode) {
+                break ts;
+            }
+            self.wait_until_not_in_flight(resource);
+        };
+        let mut attempt = 0;
+        let mut window_start = self.clock.now();
+        loop {
+            if self.take_cancel(resource) {
+                self.withdraw_request(resource, ts);
+                return Err(NodeError::Cancelled);
+            }
+            if self.can_enter_cs(resource) {
+                let waited = self.clock.elapsed_since(requested_at);
+                self.record_cs_entry(resource, waited);
+                self.note_own_entry(resource);
+                self.metrics.lock().unwrap().slow_path_entries += 1;
+                self.run_cs_body(resource, body);
+                self.finish_cs(resource, ts);
+                return Ok(());
+            }
+            let jitter = if attempt < self.max_rebroadcasts { self.rebroadcast_jitter(attempt + 1) } else { Duration::ZERO };
+            if self.clock.elapsed_since(window_start) > self.attempt_timeout + jitter {
+                if attempt < self.max_rebroadcasts {
+                    attempt += 1;
+                    self.rebroadcast_request(resource, ts, attempt);
+                    window_start = self.clock.now();
+                } else {
+                    self.log("Timeout waiting for replies");
+                    self.metrics.lock().unwrap().timeouts += 1;
+                    self.abort_request(resource, ts);
+                    return Err(NodeError::Timeout);
+                }
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+}
+
+/// Parses an inbound message body.
+fn decode<T: serde::de::DeserializeOwned>(content: &str) -> Result<T, NodeError> {
+    Ok(serde_json::from_str(content)?)
+}
+
+/// Read-modify-write of an integer in `path`. Only safe inside the CS:
+/// concurrent callers would lose updates.
+fn increment_counter_file(path: &str) -> std::io::Result<u64> {
+    let current = match std::fs::read_to_string(path) {
+        Ok(s) => s.trim().parse().unwrap_or(0),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
+        Err(e) => return Err(e),
+    };
+    std::fs::write(path, format!("{}\n", current + 1))?;
+    Ok(current + 1)
+}
+
+fn run_cs(n: &Node, resource: &str, counter_file: Option<&str>) {
+    if !n.resources.iter().any(|r| r == resource) {
+        return;
+    }
+    let Some(path) = counter_file else {
+        n.enter_cs(resource);
+        return;
+    };
+    n.enter_cs_with(resource, || {
+        n.hold_cs(resource);
+        match increment_counter_file(path) {
+            Ok(v) => n.log(&format!("Counter in {} is now {}", path, v)),
+            Err(e) => n.log_at(LogLevel::Error, &format!("Error updating {}: {}", path, e)),
+        }
+    });
+}
+
+/// Acquires A then B, `rounds` times, sleeping a gap between the two and a
+/// think time between rounds, both taken from `pauses`.
+fn run_rounds(n: &Node, rounds: usize, pauses: &mut Pauses, counter_file: Option<&str>) {
+    for round in 1..=rounds {
+        let started = n.clock.now();
+        run_cs(n, "A", counter_file);
+        thread::sleep(pauses.gap());
+        run_cs(n, "B", counter_file);
+        let took = n.clock.elapsed_since(started);
+        n.metrics.lock().unwrap().rounds.push(took);
+        n.log_at(LogLevel::Debug, &format!("Round {}/{} finished in {:?}", round, rounds, took));
+        if round < rounds {
+            thread::sleep(pauses.think());
+        }
+    }
+}
+
+/// Exits the process with `WATCHDOG_EXIT_CODE` if the run outlives
+/// `deadline`, after writing every node's status to the log.
+fn spawn_watchdog(deadline: Duration, cluster: Vec<Node>) {
+    thread::spawn(move || {
+        thread::sleep(deadline);
+        for n in &cluster {
+            n.log_at(LogLevel::Error, &format!("WATCHDOG deadline {:?} exceeded; status {}", deadline, n.status_json()));
+        }
+        if let Some(n) = cluster.first() {
+            n.flush_log();
+        }
+        std::process::exit(WATCHDOG_EXIT_CODE);
+    });
+}
+
+/// Graceful exit on SIGINT/SIGTERM: stops every server, flushes the log,
+/// prints the latency summary and exits 0.
+fn shutdown(cluster: &[Node]) -> ! {
+    for n in cluster {
+        n.log("Shutting down on signal");
+        if let Some(server) = n.server.lock().unwrap().take() {
+            server.unblock();
+        }
+    }
+    if let Some(n) = cluster.first() {
+        n.flush_log();
+        let _ = n.log_file.lock().unwrap().sync_all();
+    }
+    print_latency_table(cluster);
+    println!("Shutdown complete: {} node(s) stopped", cluster.len());
+    std::process::exit(0);
+}
+
+/// Builds nodes `ids` wired together through one `InProcessTransport`, each
+/// draining its inbox on its own thread. Node `id` "listens" on port `id`.
+fn in_process_cluster(
+    ids: &[usize],
+    cfg: &Config,
+    log_file: Arc<Mutex<std::fs::File>>,
+) -> (Vec<Node>, Arc<InProcessTransport>) {
+    let transport = Arc::new(InProcessTransport::default());
+    let nodes = ids
+        .iter()
+        .map(|&id| {
+            let peers = ids.iter().filter(|&&p| p != id).map(|&p| (p, p as u16)).collect();
+            let mut node = Node::new(id, id as u16, peers, cfg, log_file.clone());
+            node.transport = transport.clone();
+            let inbox = transport.register(id as u16);
+            let n = node.clone();
+            thread::spawn(move || {
+                for (path, payload) in inbox {
+                    n.handle(&path, &payload);
+                }
+            });
+            node
+        })
+        .collect();
+    (nodes, transport)
+}
+
+fn open_log(path: &Path) -> Arc<Mutex<std::fs::File>> {
+    let file = OpenOptions::new().create(true).append(true).open(path);
+    Arc::new(Mutex::new(file.unwrap_or_else(|e| panic!("cannot open {}: {}", path.display(), e))))
+}
+
+/// Where node `id` logs: its own file in `dir`, or the shared log.
+fn log_path_for(dir: &Path, id: usize, per_node: bool) -> PathBuf {
+    if per_node {
+        dir.join(format!("node_{}.log", id))
+    } else {
+        dir.join(LOG_FILE)
+    }
+}
+
+fn peers_of(nodes: &[(usize, u16)], id: usize) -> Vec<(usize, u16)> {
+    nodes.iter().filter(|(nid, _)| *nid != id).cloned().collect()
+}
+
+/// `replay <trace> <node-id>`: rebuilds one node's state from a recorded
+/// trace and prints its final queues. Returns the process exit code.
+fn run_replay(args: &[String]) -> i32 {
+    let (Some(path), Some(id)) = (args.get(2), args.get(3).and_then(|a| a.parse::<usize>().ok())) else {
+        eprintln!("usage: replay <trace.jsonl> <node-id>");
+        return 2;
+    };
+    let entries = match trace::load(path) {
+        Ok(e) => e,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 2;
+        }
+    };
+    let log_file = Arc::new(Mutex::new(
+        OpenOptions::new().create(true).append(true).open("replay.log").unwrap(),
+    ));
+    let cfg = Config::from_args_after(4);
+    let port = cfg.nodes.iter().find(|(nid, _)| *nid == id).map(|(_, p)| *p).unwrap_or(0);
+    let node = Node::new(id, port, peers_of(&cfg.nodes, id), &cfg, log_file).offline();
+    let fed = trace::replay(&node, entries);
+    let st = node.state.lock().unwrap();
+    println!("Replayed {} messages into node {} (final timestamp {})", fed, id, st.timestamp.get());
+    let mut resources: Vec<_> = st.request_queues.keys().collect();
+    resources.sort();
+    for r in resources {
+        let mut q: Vec<(u64, usize)> = st.request_queues[r].iter().map(|Reverse(e)| *e).collect();
+        q.sort();
+        let mut replies: Vec<usize> = st.replies.get(r).map(|s| s.iter().cloned().collect()).unwrap_or_default();
+        replies.sort();
+        println!("resource={} queue={:?} replies={:?}", r, q, replies);
+    }
+    0
+}
+
+/// Runs the `lamport_mutex` command line, for its own binary and for
+/// `dc mutex`.
+pub fn run(args: Vec<String>) {
+    let args = dc_common::args::init(args);
+    match args.get(1).map(String::as_str) {
+        Some("verify") => {
+            let path = args.get(2).filter(|a| !a.starts_with("--")).map(String::as_str).unwrap_or("lamport.log");
+            std::process::exit(verify::run(path, junit::path_in(&args)));
+        }
+        Some("replay") => std::process::exit(run_replay(&args)),
+        Some("snapshot") => std::process::exit(snapshot::run()),
+        Some("bench") => std::process::exit(bench::run(&args)),
+        Some("happens-before") => {
+            let path = args.get(2).map(String::as_str).unwrap_or(TRACE_FILE);
+            std::process::exit(happens_before::run(path));
+        }
+        Some("latency") => {
+            let path = args.get(2).map(String::as_str).unwrap_or("lamport.log");
+            std::process::exit(latency::run(path));
+        }
+        _ => {}
+    }
+    let cfg = Config::from_args();
+    if cfg.self_test {
+        std::process::exit(selftest::run(&cfg));
+    }
+    if let Err(e) = validate_topology(&cfg.nodes) {
+        eprintln!("invalid topology: {}", e);
+        std::process::exit(2);
+    }
+    if let Some(b) = cfg.start_barrier.filter(|b| !cfg.nodes.iter().any(|(id, _)| id == b)) {
+        eprintln!("--start-barrier {} is not in the node list", b);
+        std::process::exit(2);
+    }
+    if cfg.workload == Workload::Contention {
+        std::process::exit(contention::run(&cfg));
+    }
+    let nodes: Vec<(usize, u16)> = cfg.nodes.iter().copied().filter(|(id, _)| cfg.id.is_none_or(|want| *id == want)).collect();
+    if nodes.is_empty() {
+        panic!("--id {:?} is not in the node list", cfg.id);
+    }
+    if cfg.print_config {
+        println!("{}", serde_json::to_string_pretty(&cfg.effective(&nodes)).unwrap());
+        return;
+    }
+    let shared_log = (!cfg.per_node_logs).then(|| open_log(Path::new(LOG_FILE)));
+    let trace_file = cfg.record.then(|| {
+        Arc::new(Mutex::new(OpenOptions::new().create(true).append(true).open(TRACE_FILE).unwrap()))
+    });
+
+    let ids: Vec<usize> = nodes.iter().map(|(id, _)| *id).collect();
+    let delays = node_delays(&cfg, &ids);
+
+    let mut handles = vec![];
+    let mut cluster: Vec<Node> = vec![];
+    for (id, port) in nodes {
+        let log_path = log_path_for(Path::new("."), id, cfg.per_node_logs);
+        let log_file = shared_log.clone().unwrap_or_else(|| open_log(&log_path));
+        let rotation = cfg.max_log_bytes.map(|max_bytes| LogRotation { path: log_path, max_bytes, backups: cfg.log_backups });
+        let mut node = Node::new(id, port, peers_of(&cfg.nodes, id), &cfg, log_file).with_log_rotation(rotation);
+        if let Some(t) = &trace_file {
+            node = node.with_trace(t.clone());
+        }
+        node.start_server();
+        node.start_lease_watch();
+        node.start_stuck_head_watch();
+        node.start_invariant_checker();
+        node.start_heartbeats();
+        node.start_rtt_probe();
+        cluster.push(node.clone());
+        if let Some(seed) = cfg.seed {
+            node.log(&format!("Using seed {}", seed));
+        }
+
+        let n = node.clone();
+        let (start_delay, gap) = delays[&id];
+        let counter_file = cfg.counter_file.clone();
+        let acquire_both = cfg.acquire_both;
+        let rounds = cfg.rounds;
+        let mut pauses = match cfg.think_dist {
+            Some(dist) => Pauses::drawn(dist, cfg.seed.unwrap_or(0), id),
+            None => Pauses::Fixed { gap, think: cfg.think_time },
+        };
+        let h = thread::spawn(move || {
+            match n.start_barrier {
+                Some(coordinator) => {
+                    n.wait_for_start(coordinator, PEER_WAIT_TIMEOUT);
+                }
+                None => {
+                    n.wait_for_peers(PEER_WAIT_TIMEOUT);
+                    thread::sleep(start_delay);
+                }
+            }
+            run_rounds(&n, rounds, &mut pauses, counter_file.as_deref());
+            if acquire_both {
+                thread::sleep(pauses.gap());
+                // Ask in opposite orders on odd and even nodes; the helper
+                // sorts them, so this can't deadlock.
+                let wanted: &[&str] = if n.id.is_multiple_of(2) { &["A", "B"] } else { &["B", "A"] };
+                n.acquire_all_ordered(wanted, || thread::sleep(CS_HOLD));
+            }
+            thread::sleep(Duration::from_secs(1));
+        });
+        handles.push(h);
+    }
+
+    cfg.log_buffer.start_flusher();
+    if let Some(deadline) = cfg.deadline {
+        spawn_watchdog(deadline, cluster.clone());
+    }
+    let on_signal = cluster.clone();
+    ctrlc::set_handler(move || shutdown(&on_signal)).expect("failed to install signal handler");
+
+    if cfg.algorithm == Algorithm::TokenRing {
+        // With --id the ring is seeded only by the process running node 0.
+        if let Some(first) = cluster.iter().find(|n| n.id == cfg.nodes[0].0) {
+            first.wait_for_peers(PEER_WAIT_TIMEOUT);
+            first.start_token_ring(&RESOURCES);
+        }
+    }
+
+    for h in handles {
+        let _ = h.join();
+    }
+    if let Some(n) = cluster.first() {
+        n.flush_log();
+    }
+    print_latency_table(&cluster);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clock::MockClock;
+    use std::{io::Write, time::Instant};
+
+    fn test_node(id: usize, peers: Vec<(usize, u16)>) -> Node {
+        let path = std::env::temp_dir().join(format!("lamport-test-{}-{}.log", std::process::id(), id));
+        let log_file = Arc::new(Mutex::new(OpenOptions::new().create(true).append(true).open(path).unwrap()));
+        Node::new(id, 0, peers, &Config::default(), log_file).offline()
+    }
+
+    /// A write REQUEST for resource A from `from` at `ts`, in epoch 0.
+    fn request_msg(from: usize, ts: u64) -> RequestMsg {
+        RequestMsg { from, ts, resource: "A".into(), seq: 0, trace_id: Uuid::nil(), mode: LockMode::Write, vclock: None, generation: 0, epoch: 0 }
+    }
+
+    /// A REPLY for resource A from `from` to the request at `ts`, in epoch 0.
+    fn reply_msg(from: usize, ts: u64) -> ReplyMsg {
+        ReplyMsg { from, resource: "A".into(), seq: 0, ts, trace_id: Uuid::nil(), vclock: None, generation: 0, epoch: 0 }
+    }
+
+    /// Hands every send straight to the target node's `handle`.
+    struct Direct(Node);
+
+    impl Transport for Direct {
+        fn send(&self, _port: u16, path: &str, payload: &str) -> Result<(), NodeError> {
+            self.0.handle(path, payload);
+            Ok(())
+        }
+    }
+
+    fn log_of(id: usize) -> String {
+        std::fs::read_to_string(std::env::temp_dir().join(format!("lamport-test-{}-{}.log", std::process::id(), id)))
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn server_threads_handle_concurrent_requests() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let path = std::env::temp_dir().join(format!("lamport-test-{}-220.log", std::process::id()));
+        let log_file = Arc::new(Mutex::new(OpenOptions::new().create(true).append(true).open(path).unwrap()));
+        let cfg = Config { server_threads: 4, log_level: LogLevel::Error, ..Config::default() };
+        let node = Node::new(220, port, vec![], &cfg, log_file).offline();
+        node.start_server();
+
+        let senders: Vec<_> = (0..32)
+            .map(|from| {
+                thread::spawn(move || {
+                    let req = request_msg(1000 + from, 1);
+                    Client::new()
+                        .post(format!("http://127.0.0.1:{}/receive_request", port))
+                        .body(serde_json::to_string(&req).unwrap())
+                        .send()
+                        .map(|r| r.status().as_u16())
+                })
+            })
+            .collect();
+        for s in senders {
+            assert_eq!(s.join().unwrap().unwrap(), 200);
+        }
+        assert_eq!(node.state.lock().unwrap().request_queues["A"].len(), 32);
+    }
+
+    #[test]
+    fn trace_id_appears_at_send_and_receive() {
+        let receiver = test_node(11, vec![(10, 1)]);
+        let mut sender = test_node(10, vec![(11, 1)]);
+        sender.offline = false;
+        sender.transport = Arc::new(Direct(receiver));
+        sender.broadcast_request("A", LockMode::Write).unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(2);
+        let received = loop {
+            if let Some(line) = log_of(11).lines().find(|l| l.contains("Received REQUEST from 10")) {
+                break line.to_string();
+            }
+            assert!(Instant::now() < deadline, "REQUEST never arrived");
+            thread::sleep(Duration::from_millis(10));
+        };
+        let trace = verify::field(&received, "trace=").unwrap().to_string();
+        assert!(log_of(10).lines().any(|l| l.contains("Sent REQUEST") && l.contains(&format!("trace={}", trace))));
+    }
+
+    fn routed_cluster(ids: &[usize]) -> Vec<Node> {
+        let path = std::env::temp_dir().join(format!("lamport-test-{}-{}.log", std::process::id(), ids[0]));
+        let log_file = Arc::new(Mutex::new(OpenOptions::new().create(true).append(true).open(path).unwrap()));
+        let cfg = Config { fifo: true, ..Config::default() };
+        in_process_cluster(ids, &cfg, log_file).0
+    }
+
+    #[test]
+    fn snapshot_cut_is_consistent() {
+        let nodes = routed_cluster(&[20, 21, 22]);
+        for n in &nodes {
+            n.broadcast_request("A", LockMode::Write).unwrap();
+        }
+        let id = nodes[0].start_snapshot();
+
+        let deadline = Instant::now() + Duration::from_secs(2);
+        let parts: Vec<snapshot::NodeSnapshot> = loop {
+            let parts: Vec<_> = nodes.iter().filter_map(|n| n.snapshot()).filter(|s| s.snapshot_id == id && s.complete).collect();
+            if parts.len() == nodes.len() {
+                break parts;
+            }
+            assert!(Instant::now() < deadline, "snapshot never completed");
+            thread::sleep(Duration::from_millis(10));
+        };
+        snapshot::check_cut(&parts).unwrap();
+        // Our own REQUEST was broadcast before the cut, so it's in our state.
+        assert!(parts[0].queues["A"].iter().any(|&(_, nid)| nid == 20));
+    }
+
+    #[test]
+    fn higher_priority_resource_is_acquired_first() {
+        let mut node = test_node(30, vec![]);
+        assert_eq!(node.acquisition_order(&["B", "A", "B"]), ["A", "B"]);
+        node.priorities.insert("B".to_string(), 5);
+        assert_eq!(node.acquisition_order(&["A", "B"]), ["B", "A"]);
+
+        assert!(node.acquire_all_ordered(&["A", "B"], || {}));
+        let log = log_of(30);
+        let entered: Vec<&str> = log.lines().filter_map(|l| verify::field(l, "Entering Critical Section for resource=")).collect();
+        assert_eq!(entered, ["B", "A"]);
+    }
+
+    #[test]
+    fn rounds_repeat_each_acquisition() {
+        let node = test_node(31, vec![]);
+        run_rounds(&node, 3, &mut Pauses::Fixed { gap: Duration::ZERO, think: Duration::ZERO }, None);
+        let log = log_of(31);
+        let entered_a = log.lines().filter(|l| l.contains("Entering Critical Section for resource=A")).count();
+        assert_eq!(entered_a, 3);
+        assert_eq!(node.metrics.lock().unwrap().rounds.len(), 3);
+    }
+
+    #[test]
+    fn exponential_think_times_are_reproducible_from_the_seed() {
+        let dist: ThinkTime = "exp:300".parse().unwrap();
+        assert_eq!(dist, ThinkTime::Exponential { mean: Duration::from_millis(300) });
+        let draws = |seed, id| {
+            let mut p = Pauses::drawn(dist, seed, id);
+            (0..1000).map(|_| p.think()).collect::<Vec<_>>()
+        };
+        let run = draws(42, 1);
+        assert_eq!(run, draws(42, 1));
+        assert_ne!(run[..10], draws(42, 2)[..10]);
+        assert_ne!(run[..10], draws(43, 1)[..10]);
+
+        // The same stream, drawn straight from rand_distr.
+        let mut rng = StdRng::seed_from_u64(42 ^ 0x9e37_79b9_7f4a_7c15);
+        let exp = rand_distr::Exp::new(1.0 / 0.3).unwrap();
+        for d in &run[..5] {
+            assert_eq!(*d, Duration::from_secs_f64(rand_distr::Distribution::sample(&exp, &mut rng)));
+        }
+        let mean = run.iter().sum::<Duration>().as_secs_f64() / run.len() as f64;
+        assert!((0.25..0.35).contains(&mean), "mean {}", mean);
+
+        assert_eq!("uniform:5..1".parse::<ThinkTime>(), Err(()));
+        assert_eq!("exp:0".parse::<ThinkTime>(), Err(()));
+        assert_eq!("constant:7".parse::<ThinkTime>().unwrap().describe(), "constant:7");
+    }
+
+    #[test]
+    fn reply_for_an_old_request_is_ignored() {
+        let node = test_node(32, vec![(1, 1), (2, 2)]);
+        let old = node.broadcast_request("A", LockMode::Write).unwrap();
+        node.abort_request("A", old);
+        let current = node.broadcast_request("A", LockMode::Write).unwrap();
+        let reply = |from, ts| Inbound::Reply(reply_msg(from, ts));
+
+        node.deliver(reply(1, old));
+        assert!(node.state.lock().unwrap().replies["A"].is_empty());
+        assert!(log_of(32).contains("STRAY_REPLY from 1"));
+        node.deliver(reply(2, current));
+        assert_eq!(node.state.lock().unwrap().replies["A"].len(), 1);
+    }
+
+    #[test]
+    fn no_overlap_under_random_schedules() {
+        let mut sched = scheduler::Scheduler::new(&[40, 41, 42], 2);
+        for seed in 0..200 {
+            sched.reset(seed);
+            sched.run().unwrap();
+        }
+    }
+
+    #[test]
+    fn printed_config_reflects_overrides() {
+        let args = ["--attempt-timeout-ms", "750", "--quorum", "fixed:2", "--id", "1"];
+        let cfg = Config::parse(args.iter().map(|a| a.to_string()));
+        let printed = serde_json::to_value(cfg.effective(&NODES[1..2])).unwrap();
+        assert_eq!(printed["attempt_timeout_ms"], 750);
+        assert_eq!(printed["quorum"], "fixed:2");
+        assert_eq!(printed["nodes"], serde_json::json!([[1, 8001]]));
+        assert_eq!(printed["max_rebroadcasts"], 2);
+    }
+
+    /// A node whose queue for A holds `ahead` (peer 1 = ts 1, peer 2 = ts 2),
+    /// then its own request in `mode`, with every peer's reply in.
+    fn queued_behind(id: usize, ahead: &[LockMode], mode: LockMode) -> Node {
+        let node = test_node(id, vec![(1, 1), (2, 2)]);
+        for (i, &m) in ahead.iter().enumerate() {
+            let from = i + 1;
+            let req = RequestMsg { mode: m, ..request_msg(from, from as u64) };
+            node.deliver(Inbound::Request(req));
+        }
+        let ts = node.broadcast_request("A", mode).unwrap();
+        for from in [1, 2] {
+            node.deliver(Inbound::Reply(reply_msg(from, ts)));
+        }
+        node
+    }
+
+    #[test]
+    fn readers_share_the_resource() {
+        let node = queued_behind(50, &[LockMode::Read, LockMode::Read], LockMode::Read);
+        assert!(node.can_enter_cs("A"));
+        node.mark_in_cs("A", true);
+        assert!(log_of(50).contains("Entering Critical Section for resource=A mode=read"));
+    }
+
+    #[test]
+    fn writer_waits_for_readers_ahead() {
+        let node = queued_behind(51, &[LockMode::Read, LockMode::Read], LockMode::Write);
+        assert!(!node.can_enter_cs("A"));
+        node.receive_release(ReleaseMsg { from: 1, ts: 1, resource: "A".into(), generation: 0 });
+        assert!(!node.can_enter_cs("A"));
+        node.receive_release(ReleaseMsg { from: 2, ts: 2, resource: "A".into(), generation: 0 });
+        assert!(node.can_enter_cs("A"));
+    }
+
+    #[test]
+    fn queued_writer_blocks_later_readers() {
+        let node = queued_behind(52, &[LockMode::Read, LockMode::Write], LockMode::Read);
+        assert!(!node.can_enter_cs("A"));
+        node.receive_release(ReleaseMsg { from: 2, ts: 2, resource: "A".into(), generation: 0 });
+        assert!(node.can_enter_cs("A"));
+    }
+
+    /// CS entry orders recorded from the inline Lamport logic before it
+    /// moved behind `MutexAlgorithm`.
+    #[test]
+    fn default_strategy_matches_inline_lamport() {
+        let golden: [(u64, [(usize, u64); 6]); 3] = [
+            (1, [(60, 1), (62, 1), (60, 3), (61, 4), (61, 6), (62, 7)]),
+            (7, [(60, 1), (60, 2), (61, 3), (62, 3), (61, 6), (62, 8)]),
+            (42, [(60, 1), (61, 1), (62, 4), (60, 6), (61, 8), (62, 8)]),
+        ];
+        let mut sched = scheduler::Scheduler::new(&[60, 61, 62], 2);
+        for (seed, entries) in golden {
+            sched.reset(seed);
+            sched.run().unwrap();
+            assert_eq!(sched.entries, entries, "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn vector_clocks_flag_concurrent_requests() {
+        let with_vclocks = |id| {
+            let mut node = test_node(id, vec![(70, 1), (71, 1)].into_iter().filter(|(p, _)| *p != id).collect());
+            node.vector_clocks = true;
+            node
+        };
+        let (a, b) = (with_vclocks(70), with_vclocks(71));
+        let ts = b.broadcast_request("A", LockMode::Write).unwrap();
+        a.broadcast_request("A", LockMode::Write).unwrap();
+
+        let stamp = |n: &Node| n.state.lock().unwrap().request_vclocks["A"].clone();
+        assert_eq!(stamp(&b).compare(&stamp(&a)), vclock::Causality::Concurrent);
+        assert_eq!(stamp(&a).compare(&stamp(&b)), vclock::Causality::Concurrent);
+
+        let req = RequestMsg { vclock: Some(stamp(&b)), ..request_msg(71, ts) };
+        a.deliver(Inbound::Request(req));
+        assert!(log_of(70).lines().any(|l| l.contains("REQUEST from 71 ts=1") && l.contains("is concurrent with our REQUEST")));
+        // Having seen b's request, a's clock now dominates it.
+        assert_eq!(a.state.lock().unwrap().vclock.compare(&stamp(&b)), vclock::Causality::After);
+    }
+
+    #[test]
+    fn log_rotates_past_max_bytes() {
+        let path = std::env::temp_dir().join(format!("lamport-test-{}-rotate.log", std::process::id()));
+        let log_file = Arc::new(Mutex::new(OpenOptions::new().create(true).append(true).open(&path).unwrap()));
+        let rotation = LogRotation { path: path.clone(), max_bytes: 256, backups: 2 };
+        let node = Node::new(80, 0, vec![], &Config::default(), log_file).offline().with_log_rotation(Some(rotation.clone()));
+        for i in 0..40 {
+            node.log(&format!("filler line {}", i));
+        }
+        let backup = |n| std::path::PathBuf::from(format!("{}.{}", path.display(), n));
+        assert!(backup(1).exists());
+        assert!(backup(2).exists());
+        assert!(!backup(3).exists());
+        assert!(std::fs::metadata(&path).unwrap().len() < rotation.max_bytes);
+        for p in [path.clone(), backup(1), backup(2)] {
+            let _ = std::fs::remove_file(p);
+        }
+    }
+
+    #[test]
+    fn per_node_logs_hold_only_their_node() {
+        let dir = std::env::temp_dir().join(format!("lamport-test-{}-per-node", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let node = |id, peer| Node::new(id, 0, vec![(peer, 1)], &Config::default(), open_log(&log_path_for(&dir, id, true)));
+        let receiver = node(91, 90).offline();
+        let mut sender = node(90, 91);
+        sender.transport = Arc::new(Direct(receiver.clone()));
+        sender.broadcast_request("A", LockMode::Write).unwrap();
+
+        let read = |id| std::fs::read_to_string(dir.join(format!("node_{}.log", id))).unwrap_or_default();
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while !read(91).contains("Received REQUEST from 90") {
+            assert!(Instant::now() < deadline, "REQUEST never arrived");
+            thread::sleep(Duration::from_millis(10));
+        }
+        for (id, other) in [(90, 91), (91, 90)] {
+            let log = read(id);
+            assert!(!log.is_empty());
+            assert!(log.lines().all(|l| l.contains(&format!("[Node {}]", id))), "{}", log);
+            assert!(!log.contains(&format!("[Node {}]", other)));
+        }
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn partition_stalls_entry_until_healed() {
+        let path = std::env::temp_dir().join(format!("lamport-test-{}-100.log", std::process::id()));
+        let cfg = Config { attempt_timeout: Duration::from_millis(100), max_rebroadcasts: 100, ..Config::default() };
+        let (nodes, _) = in_process_cluster(&[100, 101, 102], &cfg, open_log(&path));
+        let set = |from: &Node, peer: usize, partitioned: bool| {
+            let body = serde_json::json!({ "peer": peer, "partitioned": partitioned }).to_string();
+            assert_eq!(from.handle("/chaos", &body).status_code(), 200);
+        };
+        for other in &nodes[..2] {
+            set(&nodes[2], other.id, true);
+            set(other, 102, true);
+        }
+
+        let entered = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let worker = {
+            let (n, entered) = (nodes[0].clone(), entered.clone());
+            thread::spawn(move || n.enter_cs_with("A", || entered.store(true, std::sync::atomic::Ordering::SeqCst)))
+        };
+        thread::sleep(Duration::from_millis(500));
+        assert!(!entered.load(std::sync::atomic::Ordering::SeqCst), "entered while node 102 was cut off");
+        assert!(nodes[0].state.lock().unwrap().replies["A"].contains(&101));
+
+        for other in &nodes[..2] {
+            set(&nodes[2], other.id, false);
+            set(other, 102, false);
+        }
+        assert!(worker.join().unwrap());
+        assert!(entered.load(std::sync::atomic::Ordering::SeqCst));
+        assert!(nodes[0].chaos.lock().unwrap().peers.is_empty());
+    }
+
+    /// Checks `text` against the Prometheus exposition format: metric and
+    /// label names, quoted label values, numeric samples, and a `# TYPE`
+    /// before each family's samples.
+    fn check_exposition(text: &str) -> Result<(), String> {
+        let is_name = |n: &str, colon: bool| {
+            let ok = |c: char, first: bool| c.is_ascii_alphabetic() || c == '_' || (colon && c == ':') || (!first && c.is_ascii_digit());
+            n.chars().next().is_some_and(|c| ok(c, true)) && n.chars().all(|c| ok(c, false))
+        };
+        let mut typed: HashMap<String, String> = HashMap::new();
+        for line in text.lines() {
+            if let Some(rest) = line.strip_prefix("# ") {
+                let mut words = rest.splitn(3, ' ');
+                let (kind, name, arg) = (words.next().unwrap_or(""), words.next().unwrap_or(""), words.next().unwrap_or(""));
+                if !is_name(name, true) {
+                    return Err(format!("bad metric name in {:?}", line));
+                }
+                match kind {
+                    "HELP" => {}
+                    "TYPE" if ["counter", "gauge", "histogram", "summary", "untyped"].contains(&arg) => {
+                        if typed.insert(name.to_string(), arg.to_string()).is_some() {
+                            return Err(format!("second TYPE for {}", name));
+                        }
+                    }
+                    _ => return Err(format!("bad comment {:?}", line)),
+                }
+                continue;
+            }
+            let (series, value) = line.rsplit_once(' ').ok_or_else(|| format!("no value in {:?}", line))?;
+            if value.parse::<f64>().is_err() && !["+Inf", "-Inf", "NaN"].contains(&value) {
+                return Err(format!("bad value in {:?}", line));
+            }
+            let (name, labels) = match series.split_once('{') {
+                Some((name, rest)) => (name, rest.strip_suffix('}').ok_or_else(|| format!("unclosed labels in {:?}", line))?),
+                None => (series, ""),
+            };
+            if !is_name(name, true) {
+                return Err(format!("bad metric name in {:?}", line));
+            }
+            let family = ["_bucket", "_sum", "_count"]
+                .iter()
+                .filter_map(|s| name.strip_suffix(s))
+                .find(|f| typed.get(*f).is_some_and(|t| t == "histogram"))
+                .unwrap_or(name);
+            let kind = typed.get(family).ok_or_else(|| format!("{} sampled before its TYPE", name))?;
+            if kind == "counter" && !name.ends_with("_total") {
+                return Err(format!("counter {} lacks _total", name));
+            }
+            let mut rest = labels;
+            while !rest.is_empty() {
+                let (label, after) = rest.split_once("=\"").ok_or_else(|| format!("bad labels in {:?}", line))?;
+                if !is_name(label, false) {
+                    return Err(format!("bad label name {:?}", label));
+                }
+                let mut escaped = false;
+                let mut end = None;
+                for (i, c) in after.char_indices() {
+                    if c == '"' && !escaped {
+                        end = Some(i);
+                        break;
+                    }
+                    escaped = c == '\\' && !escaped;
+                }
+                let end = end.ok_or_else(|| format!("unterminated label in {:?}", line))?;
+                rest = &after[end + 1..];
+                rest = rest.strip_prefix(',').unwrap_or(rest);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn prometheus_metrics_follow_exposition_format() {
+        let cfg = Config::parse(["--metrics-format", "prometheus"].iter().map(|a| a.to_string()));
+        let path = std::env::temp_dir().join(format!("lamport-test-{}-110.log", std::process::id()));
+        let node = Node::new(110, 0, vec![], &cfg, open_log(&path)).offline();
+        assert!(node.enter_cs_with("A", || {}));
+        assert!(node.enter_cs_with("B", || {}));
+
+        let resp = node.handle("/metrics", "");
+        let content_type = resp.headers().iter().find(|h| h.field.equiv("Content-Type")).unwrap();
+        assert!(content_type.value.as_str().starts_with("text/plain; version=0.0.4"));
+        let text = node.metrics.lock().unwrap().render(node.id, node.metrics_format);
+        check_exposition(&text).unwrap_or_else(|e| panic!("{}\n{}", e, text));
+        for line in [
+            "# TYPE lamport_requests_sent_total counter",
+            "lamport_cs_entries_total{node=\"110\"} 2",
+            "lamport_timeouts_total{node=\"110\"} 0",
+            "# TYPE lamport_cs_wait_seconds histogram",
+            "lamport_cs_wait_seconds_count{node=\"110\",resource=\"A\"} 1",
+            "lamport_cs_wait_seconds_bucket{node=\"110\",resource=\"B\",le=\"+Inf\"} 1",
+        ] {
+            assert!(text.lines().any(|l| l == line), "missing {:?} in\n{}", line, text);
+        }
+        assert!(check_exposition("lamport_cs_entries_total{node=110} 2\n").is_err());
+        assert!(check_exposition("# TYPE 2bad counter\n").is_err());
+    }
+
+    #[test]
+    fn resource_always_maps_to_the_same_coordinator() {
+        let ids = [0, 1, 2, 3];
+        let owner = coordinator::ring_owner("A", &ids).unwrap();
+        for _ in 0..10 {
+            assert_eq!(coordinator::ring_owner("A", &ids), Some(owner));
+        }
+        // Every node computes the same owner from its own view.
+        for id in ids {
+            let peers = ids.iter().filter(|&&p| p != id).map(|&p| (p, p as u16)).collect();
+            assert_eq!(test_node(id, peers).coordinator_for("A"), owner);
+        }
+        // Dropping a node only moves the resources it owned.
+        let names: Vec<String> = (0..50).map(|i| format!("res-{}", i)).collect();
+        let fewer = [0, 1, 3];
+        for r in &names {
+            let before = coordinator::ring_owner(r, &ids).unwrap();
+            if before != 2 {
+                assert_eq!(coordinator::ring_owner(r, &fewer), Some(before), "{}", r);
+            }
+        }
+    }
+
+    #[test]
+    fn coordinator_grants_one_holder_at_a_time() {
+        let path = std::env::temp_dir().join(format!("lamport-test-{}-120.log", std::process::id()));
+        let cfg = Config { algorithm: Algorithm::Coordinator, log_level: LogLevel::Error, ..Config::default() };
+        let (nodes, _) = in_process_cluster(&[120, 121, 122], &cfg, open_log(&path));
+        let inside = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let workers: Vec<_> = nodes
+            .iter()
+            .cloned()
+            .map(|n| {
+                let inside = inside.clone();
+                thread::spawn(move || {
+                    (0..5)
+                        .filter(|_| {
+                            n.enter_cs_with("A", || {
+                                let others = inside.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                                assert_eq!(others, 0, "node {} entered while another node held A", n.id);
+                                thread::sleep(Duration::from_millis(5));
+                                inside.fetch_sub(1, std::sync::atomic::Ordering::SeqCst);
+                            })
+                        })
+                        .count()
+                })
+            })
+            .collect();
+        let entered: usize = workers.into_iter().map(|h| h.join().unwrap()).sum();
+        assert_eq!(entered, 15);
+        let owner = nodes[0].coordinator_for("A");
+        let coord = nodes.iter().find(|n| n.id == owner).unwrap();
+        // The last RELEASE may still be in the coordinator's inbox.
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while coord.state.lock().unwrap().grants["A"].holder.is_some() {
+            assert!(Instant::now() < deadline, "coordinator never freed A");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// A peer on a raw socket that answers every request with `status`.
+    /// Returns its port and a count of the requests it has read.
+    fn raw_http_peer(status: &'static str) -> (u16, Arc<std::sync::atomic::AtomicUsize>) {
+        use std::io::{BufRead, BufReader, Read};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let hits = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = hits.clone();
+        thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut len = 0;
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap_or(0) > 2 {
+                    if let Some(v) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                        len = v.trim().parse().unwrap_or(0);
+                    }
+                    line.clear();
+                }
+                let _ = reader.take(len).read_to_end(&mut vec![]);
+                let _ = write!(stream, "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status);
+            }
+        });
+        (port, hits)
+    }
+
+    #[test]
+    fn server_error_is_a_failed_delivery_and_retried() {
+        let (port, hits) = raw_http_peer("500 Internal Server Error");
+        let mut node = test_node(130, vec![(131, port)]);
+        node.offline = false;
+        node.transport = Arc::new(HttpTransport::new(Config::default().http_client()));
+        node.sender = Arc::new(SendPool::inline());
+        node.broadcast_request("A", LockMode::Write).unwrap();
+        assert_eq!(hits.load(std::sync::atomic::Ordering::SeqCst), 3);
+        assert!(log_of(130).contains("Error sending REQUEST to 131: HTTP 500 Internal Server Error"), "{}", log_of(130));
+    }
+
+    fn fast_path_cluster(ids: &[usize]) -> Vec<Node> {
+        let path = std::env::temp_dir().join(format!("lamport-test-{}-{}.log", std::process::id(), ids[0]));
+        let cfg = Config { fast_path: true, fifo: true, ..Config::default() };
+        in_process_cluster(ids, &cfg, open_log(&path)).0
+    }
+
+    #[test]
+    fn lone_requester_takes_the_fast_path() {
+        let nodes = fast_path_cluster(&[140, 141, 142]);
+        assert!(nodes[2].enter_cs_with("A", || {}));
+        let m = nodes[2].metrics.lock().unwrap();
+        assert_eq!((m.fast_path_entries, m.slow_path_entries), (1, 0));
+        assert_eq!(m.requests_sent, 0);
+    }
+
+    #[test]
+    fn contention_falls_back_to_lamport() {
+        let nodes = fast_path_cluster(&[150, 151, 152]);
+        let inside = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let hold = |n: &Node, ms: u64| {
+            let (n, inside) = (n.clone(), inside.clone());
+            thread::spawn(move || {
+                n.enter_cs_with("A", || {
+                    assert_eq!(inside.fetch_add(1, std::sync::atomic::Ordering::SeqCst), 0, "overlap at node {}", n.id);
+                    thread::sleep(Duration::from_millis(ms));
+                    inside.fetch_sub(1, std::sync::atomic::Ordering::SeqCst);
+                })
+            })
+        };
+        let first = hold(&nodes[2], 300);
+        thread::sleep(Duration::from_millis(100));
+        let second = hold(&nodes[1], 10);
+        assert!(first.join().unwrap());
+        assert!(second.join().unwrap());
+
+        let paths = |n: &Node| {
+            let m = n.metrics.lock().unwrap();
+            (m.fast_path_entries, m.slow_path_entries)
+        };
+        assert_eq!(paths(&nodes[2]), (1, 0));
+        assert_eq!(paths(&nodes[1]), (0, 1));
+        assert!(log_of(150).contains("FAST_PATH refused for resource=A"));
+    }
+
+    #[test]
+    fn malformed_payload_is_a_serialize_error() {
+        assert!(matches!(decode::<RequestMsg>("{\"from\": \"zero\"}"), Err(NodeError::Serialize(_))));
+        assert!(decode::<ReleaseMsg>(r#"{"from": 1, "ts": 2, "resource": "A"}"#).is_ok());
+    }
+
+    #[test]
+    fn unknown_resource_is_its_own_error() {
+        let node = test_node(160, vec![]);
+        let mut ran = false;
+        let err = node.try_enter_cs_as("Z", LockMode::Write, || ran = true).unwrap_err();
+        assert!(matches!(&err, NodeError::UnknownResource(r) if r == "Z"), "{:?}", err);
+        assert!(!ran);
+        assert!(node.try_enter_cs_as("A", LockMode::Write, || {}).is_ok());
+    }
+
+    #[test]
+    fn queue_position_follows_request_priority() {
+        let node = test_node(170, vec![(171, 1), (172, 1), (173, 1)]);
+        for (from, ts) in [(172, 5), (173, 2), (171, 5)] {
+            let msg = serde_json::json!({ "from": from, "ts": ts, "resource": "A" });
+            assert_eq!(node.handle("/receive_request", &msg.to_string()).status_code(), 200);
+        }
+        assert_eq!(node.queue_position("A", 173).unwrap(), Some(0));
+        assert_eq!(node.queue_position("A", 171).unwrap(), Some(1));
+        assert_eq!(node.queue_position("A", 172).unwrap(), Some(2));
+        assert_eq!(node.queue_position("B", 171).unwrap(), None);
+        assert!(matches!(node.queue_position("Z", 171), Err(NodeError::UnknownResource(_))));
+
+        let mut body = String::new();
+        std::io::Read::read_to_string(&mut node.handle("/queue_position?resource=A&node=172", "").into_reader(), &mut body).unwrap();
+        let v: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(v["position"], 2);
+        assert_eq!(node.handle("/queue_position?resource=Z&node=1", "").status_code(), 404);
+        assert_eq!(node.handle("/queue_position?resource=A", "").status_code(), 400);
+    }
+
+    #[test]
+    fn crashed_holder_loses_its_lease() {
+        let clock = Arc::new(MockClock::default());
+        let mut node = test_node(230, vec![(231, 1)]).with_clock(clock.clone());
+        node.lease_grace = Some(Duration::from_millis(100));
+        {
+            // 231 replied to our request, entered first and then died.
+            let mut st = node.state.lock().unwrap();
+            let q = st.request_queues.get_mut("A").unwrap();
+            q.push(Reverse((1, 231)));
+            q.push(Reverse((2, 230)));
+            st.replies.entry("A".to_string()).or_default().insert(231);
+        }
+        assert!(node.expire_leases().is_empty());
+        clock.advance(CS_HOLD);
+        assert!(node.expire_leases().is_empty());
+        assert!(!node.can_enter_cs("A"));
+
+        clock.advance(Duration::from_millis(101));
+        assert_eq!(node.expire_leases(), vec![("A".to_string(), 1, 231)]);
+        assert!(node.can_enter_cs("A"));
+        assert!(log_of(230).contains("LEASE_EXPIRED resource=A"));
+        // Our own entry at the head is never expired.
+        clock.advance(CS_HOLD * 10);
+        assert!(node.expire_leases().is_empty());
+    }
+
+    #[test]
+    fn unreachable_stuck_head_is_evicted() {
+        let dead = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let clock = Arc::new(MockClock::default());
+        let mut node = test_node(250, vec![(251, dead)]).with_clock(clock.clone());
+        node.stuck_head_after = Some(Duration::from_millis(100));
+        {
+            let mut st = node.state.lock().unwrap();
+            st.request_queues.get_mut("A").unwrap().push(Reverse((1, 251)));
+            st.request_queues.get_mut("A").unwrap().push(Reverse((3, 250)));
+            st.request_queues.get_mut("B").unwrap().push(Reverse((2, 251)));
+            st.replies.entry("A".to_string()).or_default().insert(251);
+        }
+        assert!(node.evict_dead_heads().is_empty());
+        clock.advance(Duration::from_millis(100));
+        assert!(node.evict_dead_heads().is_empty());
+        assert!(!node.can_enter_cs("A"));
+
+        clock.advance(Duration::from_millis(1));
+        assert_eq!(node.evict_dead_heads(), vec![251]);
+        assert!(log_of(250).contains("EVICTED_DEAD_NODE node=251"));
+        assert!(node.state.lock().unwrap().request_queues["B"].is_empty());
+        assert!(node.can_enter_cs("A"));
+    }
+
+    #[test]
+    fn happens_before_links_sends_to_receives_only() {
+        let vc = |v: &[u64]| Some(VectorClock(v.to_vec()));
+        let request = |to, recv: &[u64]| trace::TraceEntry {
+            node: to,
+            at_ms: 0,
+            msg: Inbound::Request(RequestMsg { vclock: vc(&[1]), ..request_msg(0, 1) }),
+            recv_vclock: vc(recv),
+        };
+        let entries = vec![
+            request(1, &[1, 1]),
+            request(2, &[1, 0, 1]),
+            trace::TraceEntry {
+                node: 0,
+                at_ms: 0,
+                msg: Inbound::Reply(ReplyMsg { vclock: vc(&[1, 2]), ..reply_msg(1, 2) }),
+                recv_vclock: vc(&[2, 2]),
+            },
+        ];
+        let events = happens_before::events(&entries);
+        let labels: Vec<&str> = events.iter().map(|e| e.label.as_str()).collect();
+        assert_eq!(labels, ["0 send REQUEST", "1 recv REQUEST from 0", "2 recv REQUEST from 0", "1 send REPLY", "0 recv REPLY from 1"]);
+
+        let edges = happens_before::edges(&events);
+        for edge in [(0, 1), (0, 2), (1, 3), (3, 4), (0, 4)] {
+            assert!(edges.contains(&edge), "missing {:?} in {:?}", edge, edges);
+        }
+        // Node 2's receive is concurrent with everything node 1 and the REPLY did.
+        for other in [1, 3, 4] {
+            assert!(!edges.contains(&(2, other)) && !edges.contains(&(other, 2)));
+        }
+        assert!(edges.iter().all(|(a, b)| !edges.contains(&(*b, *a))));
+    }
+
+    #[test]
+    fn late_reply_from_an_earlier_epoch_is_ignored() {
+        let node = test_node(260, vec![(261, 1)]);
+        let reply = |epoch| ReplyMsg { epoch, ..reply_msg(261, 0) };
+        let first = node.broadcast_request("A", LockMode::Write).unwrap();
+        node.finish_cs("A", first);
+        node.broadcast_request("A", LockMode::Write).unwrap();
+        assert_eq!(node.request_epoch("A"), 2);
+
+        // Without a ts to match on, only the epoch tells this one is stale.
+        node.receive_reply(reply(1));
+        assert!(node.state.lock().unwrap().replies["A"].is_empty());
+        assert!(log_of(260).contains("STRAY_REPLY from 261 ts=0 epoch=1 for resource=A"));
+
+        node.receive_reply(reply(2));
+        assert!(node.state.lock().unwrap().replies["A"].contains(&261));
+    }
+
+    #[test]
+    fn silent_peer_is_suspected_then_dead_and_leaves_the_quorum() {
+        let clock = Arc::new(MockClock::default());
+        let mut node = test_node(270, vec![(271, 1), (272, 1)]).with_clock(clock.clone());
+        node.suspect_after = Duration::from_millis(100);
+        node.dead_after = Duration::from_millis(300);
+        let heartbeat = |from| node.handle("/heartbeat", &serde_json::to_string(&HeartbeatMsg { from }).unwrap());
+
+        assert!(node.update_liveness().is_empty());
+        let ts = node.broadcast_request("A", LockMode::Write).unwrap();
+        node.receive_reply(reply_msg(271, ts));
+        assert!(!node.can_enter_cs("A"));
+
+        // 271 keeps beating; 272 has gone quiet.
+        for _ in 0..4 {
+            clock.advance(Duration::from_millis(80));
+            assert_eq!(heartbeat(271).status_code(), 200);
+            node.update_liveness();
+        }
+        let peers = node.peer_statuses();
+        assert_eq!(peers[&271].state, Liveness::Alive);
+        assert_eq!(peers[&272].state, Liveness::Dead);
+        let log = log_of(270);
+        assert!(log.contains("LIVENESS peer=272 Alive -> Suspected"));
+        assert!(log.contains("LIVENESS peer=272 Suspected -> Dead"));
+        assert!(!log.contains("LIVENESS peer=271"));
+        assert!(node.can_enter_cs("A"));
+
+        let mut body = String::new();
+        std::io::Read::read_to_string(&mut node.handle("/peers", "").into_reader(), &mut body).unwrap();
+        let v: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(v["272"]["state"], "dead");
+        assert_eq!(v["271"]["state"], "alive");
+
+        heartbeat(272);
+        assert_eq!(node.peer_statuses()[&272].state, Liveness::Alive);
+        assert!(log_of(270).contains("LIVENESS peer=272 Dead -> Alive"));
+    }
+
+    #[test]
+    fn duplicate_queue_entry_is_detected_and_dropped() {
+        let node = test_node(240, vec![(241, 1)]);
+        {
+            let mut st = node.state.lock().unwrap();
+            let q = st.request_queues.get_mut("A").unwrap();
+            q.push(Reverse((1, 241)));
+            q.push(Reverse((1, 241)));
+            q.push(Reverse((2, 240)));
+        }
+        assert!(!node.can_enter_cs("A"));
+        assert!(log_of(240).contains("DUPLICATE_ENTRY resource=A ts=1 from=241"));
+        assert_eq!(node.state.lock().unwrap().request_queues["A"].len(), 2);
+        assert!(node.state.lock().unwrap().dedup_queues().is_empty());
+    }
+
+    #[test]
+    fn request_round_trips_through_msgpack() {
+        let req = RequestMsg {
+            from: 3,
+            ts: 42,
+            resource: "B".into(),
+            seq: 7,
+            trace_id: Uuid::new_v4(),
+            mode: LockMode::Read,
+            vclock: Some(VectorClock(vec![1, 0, 4])),
+            generation: 2,
+            epoch: 5,
+        };
+        let wire = WireFormat::MessagePack;
+        let bytes = wire.encode(&req).unwrap();
+        assert_eq!(wire.decode::<RequestMsg>(&bytes).unwrap(), req);
+        assert!(bytes.len() < serde_json::to_vec(&req).unwrap().len());
+
+        // What the transports do: JSON text in, MessagePack on the wire, JSON out.
+        let json = serde_json::to_string(&req).unwrap();
+        let back = wire.wire_to_json(&wire.json_to_wire(&json).unwrap()).unwrap();
+        assert_eq!(serde_json::from_str::<RequestMsg>(&back).unwrap(), req);
+        assert!(matches!(wire.wire_to_json(b"\xc1"), Err(NodeError::Wire(_))));
+    }
+
+    #[test]
+    fn message_from_an_older_view_is_dropped() {
+        let node = test_node(180, vec![(181, 1)]);
+        node.adopt_view(2);
+        let request = |generation| serde_json::json!({ "from": 181, "ts": 4, "resource": "A", "generation": generation }).to_string();
+
+        assert_eq!(node.handle("/receive_request", &request(1)).status_code(), 409);
+        assert_eq!(node.queue_position("A", 181).unwrap(), None);
+        assert!(log_of(180).contains("STALE_VIEW dropping REQUEST from 181: generation 1 < 2"), "{}", log_of(180));
+
+        // A newer view is adopted along with the message.
+        assert_eq!(node.handle("/receive_request", &request(3)).status_code(), 200);
+        assert_eq!(node.queue_position("A", 181).unwrap(), Some(0));
+        assert_eq!(node.generation(), 3);
+    }
+
+    #[test]
+    fn reply_batch_from_an_older_view_is_dropped() {
+        let node = test_node(182, vec![(183, 1), (184, 1)]);
+        let ts = node.broadcast_request("A", LockMode::Write).unwrap();
+        node.adopt_view(2);
+        let batch = |generation| {
+            serde_json::json!({ "resource": "A", "from": [183, 184], "ts": ts, "epoch": node.request_epoch("A"), "generation": generation })
+                .to_string()
+        };
+
+        assert_eq!(node.handle("/receive_reply_batch", &batch(1)).status_code(), 409);
+        assert!(node.state.lock().unwrap().replies["A"].is_empty());
+        assert!(log_of(182).contains("STALE_VIEW dropping REPLY batch from [183, 184]: generation 1 < 2"), "{}", log_of(182));
+
+        assert_eq!(node.handle("/receive_reply_batch", &batch(2)).status_code(), 200);
+        assert_eq!(node.state.lock().unwrap().replies["A"], HashSet::from([183, 184]));
+    }
+
+    #[test]
+    fn tcp_sink_ships_json_lines() {
+        use std::io::{BufRead, BufReader};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let sink: LogSink = format!("tcp:{}", listener.local_addr().unwrap()).parse().unwrap();
+        let mut node = test_node(190, vec![]);
+        node.log_sink = Arc::new(sink);
+        node.log("first line");
+        node.log_at(LogLevel::Error, "second line");
+
+        let (stream, _) = listener.accept().unwrap();
+        let lines: Vec<serde_json::Value> =
+            BufReader::new(stream).lines().take(2).map(|l| serde_json::from_str(&l.unwrap()).unwrap()).collect();
+        assert_eq!(lines[0]["msg"], "first line");
+        assert_eq!(lines[0]["node"], 190);
+        assert_eq!(lines[1]["level"], "error");
+        assert!(!log_of(190).contains("first line"));
+        assert!("tcp:".parse::<LogSink>().is_err());
+    }
+
+    #[test]
+    fn lamport_clock_ticks_and_witnesses() {
+        let mut c = MonotonicClock::default();
+        assert_eq!(c.tick(), 1);
+        assert_eq!(c.witness(7), 8);
+        assert_eq!(c.witness(3), 9);
+        c.set(9);
+        assert_eq!(c.get(), 9);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Lamport timestamp went back from 5 to 4")]
+    fn lamport_clock_refuses_to_go_back() {
+        let mut c = MonotonicClock::default();
+        c.set(5);
+        c.set(4);
+    }
+
+    #[test]
+    fn holder_yields_early_to_a_higher_priority_waiter() {
+        let mut node = test_node(200, vec![]);
+        node.priority_inheritance = true;
+        node.node_priorities.insert(201, 4);
+        assert_eq!(node.cs_hold("A"), CS_HOLD);
+
+        let waiter = node.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            let msg = serde_json::json!({ "from": 201, "ts": 100, "resource": "A" });
+            waiter.handle("/receive_request", &msg.to_string());
+        });
+        let started = Instant::now();
+        node.enter_cs("A");
+        let held = started.elapsed();
+        assert!(held < CS_HOLD / 2, "held for {:?}", held);
+        assert_eq!(node.cs_hold("A"), CS_HOLD / 5);
+        assert!(log_of(200).contains("PRIORITY_INHERIT resource=A"));
+
+        // A waiter with no more priority than us doesn't shorten anything.
+        node.node_priorities.insert(201, 0);
+        assert_eq!(node.cs_hold("A"), CS_HOLD);
+    }
+
+    #[test]
+    fn capped_node_lets_a_waiting_peer_go_first() {
+        let mut node = test_node(210, vec![]);
+        node.max_consecutive_entries = Some(2);
+        assert!(node.enter_cs_with("A", || {}));
+        assert!(node.enter_cs_with("A", || {}));
+        assert!(!node.must_yield("A"));
+
+        let msg = serde_json::json!({ "from": 211, "ts": 50, "resource": "A" });
+        node.handle("/receive_request", &msg.to_string());
+        assert!(node.must_yield("A"));
+        let peer = node.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            peer.handle("/receive_release", &serde_json::json!({ "from": 211, "ts": 50, "resource": "A" }).to_string());
+        });
+        assert!(node.enter_cs_with("A", || {}));
+
+        let log = log_of(210);
+        let yielded = log.find("YIELD resource=A").expect("no YIELD logged");
+        let released = log.find("Received RELEASE from 211").unwrap();
+        let third = log.match_indices("Entering Critical Section for resource=A").nth(2).unwrap().0;
+        assert!(yielded < released && released < third, "{}", log);
+        assert_eq!(node.state.lock().unwrap().consecutive["A"], 1);
+    }
+
+    #[test]
+    fn broken_config_files_list_every_problem() {
+        let problems = |json: &str| config_file::validate_config(&serde_json::from_str(json).unwrap());
+        assert_eq!(
+            problems(r#"{"nodes": [{"id": 0, "port": 9000}, {"id": 1}], "resources": []}"#),
+            ["node 1 missing 'port'", "resource list is empty"]
+        );
+        assert_eq!(
+            problems(r#"{"nodes": [{"id": 0, "port": 9000}, {"id": 1, "port": 9001}], "quorum": 3}"#),
+            ["quorum 3 larger than peer count 1"]
+        );
+        assert_eq!(
+            problems(
+                r#"{"nodes": [{"port": 9000}, {"id": 1, "port": 9001}, {"id": 2, "port": 9001}], "resources": ["A", "C"], "timeout": 5}"#
+            ),
+            [
+                "unknown key 'timeout'",
+                "node #1 missing 'id'",
+                "node 2 reuses port 9001",
+                "unknown resource \"C\" (known: A, B)",
+            ]
+        );
+        assert_eq!(problems(r#"{"nodes": [], "attempt_timeout_ms": 0}"#), [
+            "node list is empty",
+            "'attempt_timeout_ms' must be a positive number of milliseconds, got 0",
+        ]);
+        assert_eq!(problems("[1, 2]"), ["config must be a JSON object"]);
+
+        let good = serde_json::json!({ "nodes": [{ "id": 5, "port": 9005 }, { "id": 6, "port": 9006 }], "quorum": "majority" });
+        assert!(config_file::validate_config(&good).is_empty());
+        let mut cfg = Config::default();
+        cfg.apply_file(&good);
+        assert_eq!(cfg.nodes, [(5, 9005), (6, 9006)]);
+        assert_eq!(cfg.quorum, QuorumPolicy::Majority);
+    }
+
+    #[test]
+    fn mock_clock_drives_reply_timeout() {
+        let clock = Arc::new(MockClock::default());
+        let node = test_node(0, vec![(1, 1)]).with_clock(clock.clone());
+        let stop = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let ticker = {
+            let (clock, stop) = (clock.clone(), stop.clone());
+            thread::spawn(move || {
+                while !stop.load(std::sync::atomic::Ordering::Relaxed) {
+                    clock.advance(Duration::from_secs(1));
+                    thread::sleep(Duration::from_millis(20));
+                }
+            })
+        };
+        let started = Instant::now();
+        let mut ran = false;
+        assert!(!node.enter_cs_with("A", || ran = true));
+        stop.store(true, std::sync::atomic::Ordering::Relaxed);
+        ticker.join().unwrap();
+        assert!(!ran);
+        assert!(started.elapsed() < Duration::from_secs(3), "took {:?}", started.elapsed());
+        assert!(!node.state.lock().unwrap().in_flight["A"]);
+    }
+
+    #[test]
+    fn on_exit_log_flush_holds_lines_until_shutdown() {
+        assert_eq!("interval:250".parse(), Ok(LogFlush::Interval(Duration::from_millis(250))));
+        assert_eq!("on-exit".parse(), Ok(LogFlush::OnExit));
+        assert!("interval:soon".parse::<LogFlush>().is_err());
+
+        let cfg = Config { log_buffer: Arc::new(LogBuffer::new(LogFlush::OnExit)), ..Config::default() };
+        let path = std::env::temp_dir().join(format!("lamport-test-{}-280.log", std::process::id()));
+        let log_file = Arc::new(Mutex::new(OpenOptions::new().create(true).append(true).open(path).unwrap()));
+        let node = Node::new(280, 0, vec![], &cfg, log_file).offline();
+        node.log("buffered until exit");
+        assert!(!log_of(280).contains("buffered until exit"));
+        node.flush_log();
+        assert!(log_of(280).contains("[Node 280] buffered until exit"));
+    }
+
+    #[test]
+    fn exit_cleanup_drops_our_entry_and_releases_it_head_or_not() {
+        let queue = |n: &Node| {
+            let mut q: Vec<_> = n.state.lock().unwrap().request_queues["A"].iter().map(|Reverse(e)| *e).collect();
+            q.sort();
+            q
+        };
+        let wait_for = |n: &Node, want: &[(u64, usize)]| {
+            let deadline = Instant::now() + Duration::from_secs(2);
+            while queue(n) != want {
+                assert!(Instant::now() < deadline, "peer queue stuck at {:?}", queue(n));
+                thread::sleep(Duration::from_millis(10));
+            }
+        };
+        let receiver = test_node(282, vec![(281, 1)]);
+        let mut sender = test_node(281, vec![(282, 1)]);
+        sender.offline = false;
+        sender.transport = Arc::new(Direct(receiver.clone()));
+        let seed = |entries: &[(u64, usize)]| {
+            for n in [&sender, &receiver] {
+                let mut st = n.state.lock().unwrap();
+                st.request_queues.insert("A".to_string(), entries.iter().map(|&e| Reverse(e)).collect());
+            }
+        };
+
+        // Head: our entry is first in the queue.
+        seed(&[(1, 281), (5, 282)]);
+        sender.finish_cs("A", 1);
+        assert_eq!(queue(&sender), [(5, 282)]);
+        wait_for(&receiver, &[(5, 282)]);
+
+        // Not the head: a reader behind another reader, with a later request
+        // of ours still queued.
+        seed(&[(2, 282), (3, 281), (7, 281)]);
+        sender.finish_cs("A", 3);
+        assert_eq!(queue(&sender), [(2, 282), (7, 281)]);
+        wait_for(&receiver, &[(2, 282), (7, 281)]);
+    }
+
+    #[test]
+    fn contention_workload_has_no_violations() {
+        let path = std::env::temp_dir().join(format!("lamport-test-{}-contention.log", std::process::id()));
+        let cfg = Config { log_level: LogLevel::Error, ..Config::default() };
+        let r = contention::measure(&[283, 284, 285], &cfg, Duration::from_millis(500), open_log(&path));
+        assert!(r.entries > 0, "nobody entered");
+        assert_eq!(r.violations, 0);
+        assert!(r.entries_per_sec() > 0.0);
+    }
+
+    #[test]
+    fn release_endpoint_preempts_the_holder() {
+        let path = std::env::temp_dir().join(format!("lamport-test-{}-preempt.log", std::process::id()));
+        let (cluster, _) = in_process_cluster(&[286, 287], &Config::default(), open_log(&path));
+        let (holder, peer) = (cluster[0].clone(), cluster[1].clone());
+        assert_eq!(holder.handle("/release?resource=A", "").status_code(), 409);
+
+        let started = Instant::now();
+        let worker = {
+            let holder = holder.clone();
+            thread::spawn(move || holder.enter_cs_with("A", || holder.hold_cs("A")))
+        };
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while !holder.in_cs.lock().unwrap().get("A").copied().unwrap_or(false) {
+            assert!(Instant::now() < deadline, "never entered the CS");
+            thread::sleep(Duration::from_millis(5));
+        }
+        let queued = |n: &Node| n.state.lock().unwrap().request_queues["A"].iter().any(|Reverse((_, from))| *from == 286);
+        assert!(queued(&peer));
+
+        let entered = started.elapsed();
+        assert_eq!(holder.handle("/release?resource=A", "").status_code(), 200);
+        assert!(worker.join().unwrap());
+        assert!(started.elapsed() < entered + CS_HOLD / 2, "held for {:?}", started.elapsed() - entered);
+        assert!(!holder.is_preempted("A"));
+        while queued(&peer) {
+            assert!(Instant::now() < deadline, "peer never saw the RELEASE");
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn jitter_staggers_simultaneous_rebroadcasts() {
+        let clock = Arc::new(MockClock::default());
+        let cfg = Config {
+            attempt_timeout: Duration::from_secs(1),
+            max_rebroadcasts: 1,
+            rebroadcast_jitter: Jitter::ById(Duration::from_secs(1)),
+            ..Config::default()
+        };
+        let node = |id, peer| {
+            let path = std::env::temp_dir().join(format!("lamport-test-{}-{}.log", std::process::id(), id));
+            Node::new(id, 0, vec![(peer, 1)], &cfg, open_log(&path)).offline().with_clock(clock.clone())
+        };
+        let nodes = [node(288, 289), node(289, 288)];
+        assert_eq!(nodes.iter().map(|n| n.rebroadcast_jitter(1)).collect::<Vec<_>>(), [Duration::ZERO, Duration::from_millis(500)]);
+
+        // Both request at mock time 0 and so time out at the same instant.
+        let workers: Vec<_> = nodes.iter().cloned().map(|n| thread::spawn(move || n.enter_cs_with("A", || {}))).collect();
+        thread::sleep(Duration::from_millis(100));
+        let mut rebroadcast_at = [None, None];
+        while rebroadcast_at.contains(&None) {
+            assert!(clock.now() < Duration::from_secs(5), "no rebroadcast by {:?}", clock.now());
+            clock.advance(Duration::from_millis(100));
+            thread::sleep(Duration::from_millis(120));
+            for (at, n) in rebroadcast_at.iter_mut().zip(&nodes) {
+                if at.is_none() && log_of(n.id).contains("Re-broadcasting REQUEST") {
+                    *at = Some(clock.now());
+                }
+            }
+        }
+        let [first, second] = rebroadcast_at.map(Option::unwrap);
+        assert!(second >= first + Duration::from_millis(300), "288 at {:?}, 289 at {:?}", first, second);
+        clock.advance(Duration::from_secs(10));
+        for w in workers {
+            assert!(!w.join().unwrap());
+        }
+    }
+
+    #[test]
+    fn reply_batch_records_every_id() {
+        let node = test_node(290, vec![(291, 1), (292, 1), (293, 1)]);
+        let ts = node.broadcast_request("A", LockMode::Write).unwrap();
+        let batch = serde_json::json!({ "resource": "A", "from": [291, 292, 293], "ts": ts, "epoch": node.request_epoch("A") });
+        assert_eq!(node.handle("/receive_reply_batch", &batch.to_string()).status_code(), 200);
+        assert_eq!(node.state.lock().unwrap().replies["A"], HashSet::from([291, 292, 293]));
+        assert!(node.can_enter_cs("A"));
+    }
+
+    #[test]
+    fn invariant_checker_flags_injected_bad_state() {
+        let node = test_node(294, vec![(295, 1)]);
+        assert!(node.check_invariants().is_empty());
+        {
+            let mut st = node.state.lock().unwrap();
+            st.timestamp.witness(10);
+        }
+        assert!(node.check_invariants().is_empty());
+        {
+            let mut st = node.state.lock().unwrap();
+            st.replies.insert("A".to_string(), HashSet::from([295, 296]));
+            st.request_queues.get_mut("B").unwrap().extend([Reverse((3, 295)), Reverse((3, 295))]);
+            st.timestamp = MonotonicClock::default();
+        }
+        assert_eq!(
+            node.check_invariants(),
+            [
+                "resource=A has 2 replies from 1 peer(s)",
+                "resource=B queues ts=3 from=295 more than once",
+                "Lamport clock went back from 11 to 0",
+            ]
+        );
+        assert!(log_of(294).contains("INVARIANT_VIOLATED resource=B queues ts=3 from=295 more than once"));
+    }
+
+    #[test]
+    fn topology_file_gives_each_node_its_resources() {
+        let sample = r#"
+            [[nodes]]
+            id = 0
+            port = 8000
+            resources = ["A"]
+
+            [[nodes]]
+            id = 1
+            port = 8001
+
+            [[nodes]]
+            id = 2
+            port = 8002
+            resources = ["B"]
+            byzantine = true
+            behavior = "flip_all"
+        "#;
+        let t: topology::Topology = toml::from_str(sample).unwrap();
+        assert!(t.problems().is_empty(), "{:?}", t.problems());
+        let mut cfg = Config::default();
+        cfg.apply_topology(&t);
+        assert_eq!(cfg.nodes, [(0, 8000), (1, 8001), (2, 8002)]);
+        let resources = |id| Node::new(id, 0, vec![], &cfg, open_log(&std::env::temp_dir().join("lamport-test-topology.log"))).resources;
+        assert_eq!(resources(0), ["A"]);
+        assert_eq!(resources(1), ["A", "B"]);
+        assert_eq!(resources(2), ["B"]);
+
+        let bad: topology::Topology = toml::from_str("[[nodes]]\nid = 0\nport = 8000\nresources = [\"C\"]").unwrap();
+        assert_eq!(bad.problems(), ["node 0 lists unknown resource C (known: A, B)"]);
+    }
+
+    /// Records the port of every REQUEST, in the order they are sent.
+    #[derive(Default)]
+    struct SendOrder(Mutex<Vec<u16>>);
+
+    impl Transport for SendOrder {
+        fn send(&self, port: u16, path: &str, _payload: &str) -> Result<(), NodeError> {
+            if path == "/receive_request" {
+                self.0.lock().unwrap().push(port);
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn requests_go_to_peers_in_ascending_rtt() {
+        let cfg = Config { send_workers: 1, ..Config::default() };
+        let path = std::env::temp_dir().join(format!("lamport-test-{}-296.log", std::process::id()));
+        let mut node = Node::new(296, 0, vec![(297, 1), (298, 2), (299, 3)], &cfg, open_log(&path));
+        let sent = Arc::new(SendOrder::default());
+        node.transport = sent.clone();
+        for (peer, delay_ms) in [(297, 60), (298, 5), (299, 30)] {
+            node.chaos.lock().unwrap().peers.insert(peer, chaos::PeerChaos { delay_ms, ..Default::default() });
+        }
+        assert_eq!(node.peers_by_rtt(), node.peers, "unmeasured peers keep their order");
+
+        node.sample_rtts();
+        let rtts = node.state.lock().unwrap().rtts.clone();
+        assert!(rtts[&298] < rtts[&299] && rtts[&299] < rtts[&297], "{:?}", rtts);
+        assert_eq!(node.peers_by_rtt(), [(298, 2), (299, 3), (297, 1)]);
+
+        node.broadcast_request("A", LockMode::Write).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while sent.0.lock().unwrap().len() < 3 {
+            assert!(Instant::now() < deadline, "sent only {:?}", sent.0.lock().unwrap());
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(*sent.0.lock().unwrap(), [2, 3, 1]);
+    }
+
+    #[test]
+    fn cancelled_request_returns_promptly_without_entering() {
+        let peer = test_node(301, vec![(300, 1)]);
+        let mut node = test_node(300, vec![(301, 1)]);
+        node.offline = false;
+        node.transport = Arc::new(Direct(peer.clone()));
+        let cancel = |n: &Node| n.handle("/cancel?resource=A", "").status_code();
+        assert_eq!(cancel(&node), 409, "nothing to cancel yet");
+
+        let waiter = {
+            let node = node.clone();
+            thread::spawn(move || node.try_enter_cs_as("A", LockMode::Write, || panic!("entered a cancelled CS")))
+        };
+        let queued = |n: &Node| n.state.lock().unwrap().request_queues["A"].iter().any(|Reverse((_, from))| *from == 300);
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while !queued(&peer) {
+            assert!(Instant::now() < deadline, "REQUEST never reached the peer");
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let cancelled_at = Instant::now();
+        assert_eq!(cancel(&node), 200);
+        assert!(matches!(waiter.join().unwrap(), Err(NodeError::Cancelled)));
+        assert!(cancelled_at.elapsed() < Duration::from_millis(500), "took {:?}", cancelled_at.elapsed());
+        assert!(!queued(&node));
+        assert!(!node.state.lock().unwrap().in_flight["A"]);
+        while queued(&peer) {
+            assert!(Instant::now() < deadline, "peer never dropped the cancelled REQUEST");
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(log_of(301).contains("Received CANCEL from 300"));
+    }
+
+    #[test]
+    fn gzipped_reply_batch_round_trips_through_the_server() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let path = std::env::temp_dir().join(format!("lamport-test-{}-302.log", std::process::id()));
+        let log_file = Arc::new(Mutex::new(OpenOptions::new().create(true).append(true).open(path).unwrap()));
+        let peers: Vec<(usize, u16)> = (10_000..10_500).map(|id| (id, 1)).collect();
+        let cfg = Config { log_level: LogLevel::Error, ..Config::default() };
+        let node = Node::new(302, port, peers.clone(), &cfg, log_file).offline();
+        node.start_server();
+        let ts = node.broadcast_request("A", LockMode::Write).unwrap();
+
+        let batch = ReplyBatchMsg {
+            resource: "A".into(),
+            from: peers.iter().map(|(id, _)| *id).collect(),
+            ts,
+            epoch: node.request_epoch("A"),
+            generation: 0,
+        };
+        let json = serde_json::to_string(&batch).unwrap();
+        assert!(json.len() >= compress::GZIP_MIN_BYTES);
+        let packed = compress::gzip(json.as_bytes()).unwrap();
+        assert!(packed.len() < json.len() / 2, "{} -> {} bytes", json.len(), packed.len());
+        assert_eq!(compress::gunzip(&packed).unwrap(), json.as_bytes());
+
+        let sender = HttpTransport::new(Config::default().http_client()).with_gzip(true);
+        sender.send(port, "/receive_reply_batch", &json).unwrap();
+        assert_eq!(node.state.lock().unwrap().replies["A"].len(), peers.len());
+        assert!(node.can_enter_cs("A"));
+        assert!(compress::decode_body(Some("br"), packed).is_err());
+    }
+
+    #[test]
+    fn nobody_requests_before_every_node_registers() {
+        let path = std::env::temp_dir().join(format!("lamport-test-{}-303.log", std::process::id()));
+        let cfg = Config { start_barrier: Some(303), log_level: LogLevel::Error, ..Config::default() };
+        let (nodes, _) = in_process_cluster(&[303, 304, 305], &cfg, open_log(&path));
+        let issued = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let start = |n: &Node| {
+            let (n, issued) = (n.clone(), issued.clone());
+            thread::spawn(move || {
+                let started = n.wait_for_start(303, Duration::from_secs(5));
+                issued.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                n.broadcast_request("A", LockMode::Write);
+                started
+            })
+        };
+        let early: Vec<_> = nodes[..2].iter().map(start).collect();
+
+        thread::sleep(Duration::from_millis(300));
+        assert_eq!(issued.load(std::sync::atomic::Ordering::SeqCst), 0, "started before 305 registered");
+        for n in &nodes {
+            assert!(n.state.lock().unwrap().request_queues["A"].is_empty());
+        }
+        assert_eq!(nodes[0].state.lock().unwrap().registered, HashSet::from([303, 304]));
+
+        let last = start(&nodes[2]);
+        for h in early.into_iter().chain([last]) {
+            assert!(h.join().unwrap());
+        }
+        assert_eq!(issued.load(std::sync::atomic::Ordering::SeqCst), 3);
+    }
+
+    /// Checks `xml` has one root, balanced tags and no bare `<` or `&` in
+    /// its attributes; returns the element names in order.
+    fn xml_elements(xml: &str) -> Vec<String> {
+        let body = xml.strip_prefix("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n").expect("no XML declaration");
+        let (mut open, mut seen, mut rest) = (vec![], vec![], body);
+        while let Some(start) = rest.find('<') {
+            assert!(rest[..start].trim().is_empty(), "stray text {:?}", &rest[..start]);
+            let end = rest[start..].find('>').expect("unclosed tag") + start;
+            let tag = &rest[start + 1..end];
+            assert!(!tag.contains('<'), "bare < inside {:?}", tag);
+            for (i, _) in tag.match_indices('&') {
+                let entity = ["&amp;", "&lt;", "&gt;", "&quot;", "&apos;"].iter().any(|e| tag[i..].starts_with(e));
+                assert!(entity, "bare & inside {:?}", tag);
+            }
+            if let Some(name) = tag.strip_prefix('/') {
+                assert_eq!(open.pop().as_deref(), Some(name), "mismatched </{}>", name);
+            } else {
+                let name = tag.split_whitespace().next().unwrap().trim_end_matches('/').to_string();
+                assert!(!open.is_empty() || seen.is_empty(), "second root <{}>", name);
+                if !tag.ends_with('/') {
+                    open.push(name.clone());
+                }
+                seen.push(name);
+            }
+            rest = &rest[end + 1..];
+        }
+        assert!(open.is_empty(), "unclosed {:?}", open);
+        seen
+    }
+
+    #[test]
+    fn junit_report_is_well_formed_with_a_case_per_invariant() {
+        let found = selftest::findings(0).unwrap();
+        let xml = junit::render("self-test", &found.cases());
+        assert_eq!(xml_elements(&xml), ["testsuite", "testcase", "testcase"]);
+        assert!(xml.contains("<testsuite name=\"self-test\" tests=\"2\" failures=\"0\">"), "{}", xml);
+        assert!(xml.contains("name=\"mutual exclusion held\"/>"));
+        assert!(xml.contains("name=\"all nodes progressed\"/>"));
+
+        let cases = [
+            junit::Case::new("mutual exclusion held", Err("node 1 entered <A> while \"node 2\" & 3 held it".to_string())),
+            junit::Case::new("all nodes progressed", Ok(())),
+        ];
+        let xml = junit::render("verify", &cases);
+        assert_eq!(xml_elements(&xml), ["testsuite", "testcase", "failure", "testcase"]);
+        assert!(xml.contains("failures=\"1\""));
+        assert!(xml.contains("message=\"node 1 entered &lt;A&gt; while &quot;node 2&quot; &amp; 3 held it\""), "{}", xml);
+    }
+
+    #[test]
+    fn status_reports_per_resource_health_while_waiting() {
+        let node = test_node(306, vec![(307, 1)]);
+        node.record_cs_entry("B", Duration::from_millis(5));
+        let ts = node.broadcast_request("A", LockMode::Write).unwrap();
+        node.handle("/receive_request", &serde_json::to_string(&request_msg(307, ts + 1)).unwrap());
+
+        let status = |node: &Node| {
+            let mut body = String::new();
+            std::io::Read::read_to_string(&mut node.handle("/status", "").into_reader(), &mut body).unwrap();
+            serde_json::from_str::<serde_json::Value>(&body).unwrap()
+        };
+        let s = status(&node);
+        let a = &s["resources"]["A"];
+        assert_eq!(a["queue"], serde_json::json!([[ts, 306], [ts + 1, 307]]));
+        assert_eq!(a["queue_depth"], 2);
+        assert_eq!(a["replies_received"], 0);
+        assert_eq!(a["entries"], 0);
+        assert_eq!(s["resources"]["B"]["entries"], 1);
+        let waiting: Vec<&String> =
+            s["resources"].as_object().unwrap().iter().filter(|(_, r)| r["waiting"] == true).map(|(res, _)| res).collect();
+        assert_eq!(waiting, ["A"]);
+
+        node.receive_reply(ReplyMsg { epoch: node.request_epoch("A"), ..reply_msg(307, ts) });
+        let a = &status(&node)["resources"]["A"];
+        assert_eq!(a["replies"], serde_json::json!([307]));
+        assert_eq!(a["replies_received"], 1);
+        assert_eq!(a["queue_depth"], 2);
+        assert_eq!(a["waiting"], true);
+    }
+
+    #[test]
+    fn request_past_max_queue_len_gets_503() {
+        let mut node = test_node(310, vec![]);
+        node.max_queue_len = 3;
+        let send = |from| node.handle("/receive_request", &serde_json::to_string(&request_msg(from, 1)).unwrap()).status_code().0;
+        for from in 311..314 {
+            assert_eq!(send(from), 200);
+        }
+        assert_eq!(send(314), 503);
+        assert_eq!(node.state.lock().unwrap().request_queues["A"].len(), 3);
+        // A resent REQUEST that is already queued is not new load.
+        assert_eq!(send(311), 200);
+    }
+
+    #[test]
+    fn cs_wait_records_a_positive_sample_per_entry() {
+        let path = std::env::temp_dir().join(format!("lamport-test-{}-320.log", std::process::id()));
+        let cfg = Config { log_level: LogLevel::Error, ..Config::default() };
+        let (nodes, _) = in_process_cluster(&[320, 321, 322], &cfg, open_log(&path));
+        let workers: Vec<_> = nodes
+            .iter()
+            .cloned()
+            .map(|n| thread::spawn(move || (0..3).all(|_| n.enter_cs_with("A", || thread::sleep(Duration::from_millis(5))))))
+            .collect();
+        for w in workers {
+            assert!(w.join().unwrap());
+        }
+
+        let mut all = vec![];
+        for n in &nodes {
+            let samples = n.metrics.lock().unwrap().cs_wait["A"].clone();
+            assert_eq!(samples.len(), 3, "node {}", n.id);
+            assert!(samples.iter().all(|d| !d.is_zero()), "node {}: {:?}", n.id, samples);
+            all.extend(samples);
+        }
+        let s = LatencySummary::from_samples(&all).unwrap();
+        assert_eq!(s.count, 9);
+        assert!(!s.min.is_zero() && s.min <= s.p50 && s.p50 <= s.p95 && s.p95 <= s.max && s.mean <= s.max);
+    }
+
+    #[test]
+    fn node_delays_repeat_for_a_seed_and_change_with_it() {
+        let delays = |seed: &str| {
+            let cfg = Config::parse(["--seed", seed].iter().map(|a| a.to_string()));
+            node_delays(&cfg, &[1, 2, 3, 4])
+        };
+        assert_eq!(delays("7"), delays("7"));
+        assert_ne!(delays("7"), delays("8"));
+        let cfg = Config::default();
+        for (start, gap) in delays("7").values() {
+            assert!((cfg.start_delay_ms.0..=cfg.start_delay_ms.1).contains(&(start.as_millis() as u64)));
+            assert!((cfg.gap_ms.0..=cfg.gap_ms.1).contains(&(gap.as_millis() as u64)));
+        }
+    }
+
+    #[test]
+    fn token_ring_has_one_token_and_one_holder_at_a_time() {
+        let path = std::env::temp_dir().join(format!("lamport-test-{}-330.log", std::process::id()));
+        let cfg = Config { algorithm: Algorithm::TokenRing, log_level: LogLevel::Error, ..Config::default() };
+        let (nodes, _) = in_process_cluster(&[330, 331, 332], &cfg, open_log(&path));
+        nodes[0].start_token_ring(&["A"]);
+        // Locking every node's state at once freezes the ring for a count.
+        let tokens = |nodes: &[Node]| {
+            let states: Vec<_> = nodes.iter().map(|n| n.state.lock().unwrap()).collect();
+            states.iter().filter(|st| st.tokens.contains("A")).count()
+        };
+
+        let inside = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let workers: Vec<_> = nodes
+            .iter()
+            .cloned()
+            .map(|n| {
+                let inside = inside.clone();
+                thread::spawn(move || {
+                    (0..3)
+                        .filter(|_| {
+                            n.enter_cs_with("A", || {
+                                let others = inside.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                                assert_eq!(others, 0, "node {} entered while another node held A", n.id);
+                                thread::sleep(Duration::from_millis(5));
+                                inside.fetch_sub(1, std::sync::atomic::Ordering::SeqCst);
+                            })
+                        })
+                        .count()
+                })
+            })
+            .collect();
+        while !workers.iter().all(|w| w.is_finished()) {
+            assert!(tokens(&nodes) <= 1, "two nodes hold the token");
+            thread::sleep(Duration::from_millis(2));
+        }
+        let entered: usize = workers.into_iter().map(|h| h.join().unwrap()).sum();
+        assert_eq!(entered, 9);
+
+        // Between hops the token is in flight; it must land somewhere.
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while tokens(&nodes) != 1 {
+            assert!(Instant::now() < deadline, "the token was lost");
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn wait_for_peers_blocks_until_a_late_peer_is_up() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let node = test_node(340, vec![(341, port)]);
+        assert!(!node.wait_for_peers(Duration::from_millis(100)));
+
+        let started = Instant::now();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(400));
+            let path = std::env::temp_dir().join(format!("lamport-test-{}-341.log", std::process::id()));
+            let late = Node::new(341, port, vec![], &Config::default(), open_log(&path)).offline();
+            late.start_server();
+        });
+        assert!(node.wait_for_peers(Duration::from_secs(5)));
+        assert!(started.elapsed() >= Duration::from_millis(400), "returned after {:?}", started.elapsed());
+        assert!(log_of(340).contains("Timed out waiting for peers [341]"), "{}", log_of(340));
+    }
+
+    #[test]
+    fn garbage_request_gets_400_with_a_json_error() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let path = std::env::temp_dir().join(format!("lamport-test-{}-350.log", std::process::id()));
+        let node = Node::new(350, port, vec![], &Config::default(), open_log(&path)).offline();
+        node.start_server();
+
+        let resp = Client::new().post(format!("http://127.0.0.1:{}/receive_request", port)).body("not json {").send().unwrap();
+        assert_eq!(resp.status().as_u16(), 400);
+        assert_eq!(resp.headers()["content-type"], "application/json");
+        let body: serde_json::Value = serde_json::from_str(&resp.text().unwrap()).unwrap();
+        assert!(body["error"].as_str().unwrap().starts_with("malformed REQUEST payload"), "{}", body);
+        assert_eq!(node.metrics.lock().unwrap().malformed_messages, 1);
+        assert!(log_of(350).contains("Bad REQUEST payload: not json {"), "{}", log_of(350));
+    }
+
+    #[test]
+    fn verifier_flags_overlapping_critical_sections() {
+        let log = |lines: &[&str]| lines.join("\n");
+        let clean = log(&[
+            "[100] [Node 1] Broadcasting REQUEST ts=1 for resource=A",
+            "[100] [Node 2] Broadcasting REQUEST ts=2 for resource=A",
+            "[101] [Node 1] Entering Critical Section for resource=A",
+            "[102] [Node 1] Exiting Critical Section for resource=A",
+            "[102] [Node 2] Entering Critical Section for resource=A",
+            "[103] [Node 2] Exiting Critical Section for resource=A",
+        ]);
+        assert_eq!(verify::verify_log(&clean), Ok(2));
+
+        let overlap = log(&[
+            "[100] [Node 1] Broadcasting REQUEST ts=1 for resource=A",
+            "[100] [Node 2] Broadcasting REQUEST ts=2 for resource=A",
+            "[101] [Node 1] Entering Critical Section for resource=A",
+            "[101] [Node 2] Entering Critical Section for resource=A",
+        ]);
+        assert_eq!(
+            verify::verify_log(&overlap),
+            Err("line 4: node 2 entered resource=A at 101 while node 1 (entered line 3, at 101) still held it".to_string())
+        );
+
+        let out_of_order = log(&[
+            "[100] [Node 1] Broadcasting REQUEST ts=1 for resource=A",
+            "[100] [Node 2] Broadcasting REQUEST ts=2 for resource=A",
+            "[101] [Node 2] Entering Critical Section for resource=A",
+            "[102] [Node 2] Exiting Critical Section for resource=A",
+            "[102] [Node 1] Entering Critical Section for resource=A",
+        ]);
+        assert_eq!(
+            verify::verify_log(&out_of_order),
+            Err("line 5: node 1 entered resource=A with ts=1 after node 2 with ts=2".to_string())
+        );
+    }
+
+    #[test]
+    fn send_pool_handles_100_concurrent_sends_at_low_latency() {
+        let (port, hits) = raw_http_peer("200 OK");
+        let cfg = Config { send_workers: 8, log_level: LogLevel::Error, ..Config::default() };
+        let path = std::env::temp_dir().join(format!("lamport-test-{}-360.log", std::process::id()));
+        let node = Node::new(360, 0, vec![(361, port)], &cfg, open_log(&path));
+
+        let (tx, done) = std::sync::mpsc::channel();
+        for _ in 0..100 {
+            let (node, tx) = (node.clone(), tx.clone());
+            node.sender.clone().execute(move || {
+                let started = Instant::now();
+                let sent = node.send_to(port, "/receive_request", "{}");
+                let _ = tx.send((sent.map_err(|e| e.to_string()), started.elapsed()));
+            });
+        }
+        let mut latencies = vec![];
+        for _ in 0..100 {
+            let (sent, took) = done.recv_timeout(Duration::from_secs(10)).unwrap();
+            sent.unwrap();
+            latencies.push(took);
+        }
+        assert_eq!(hits.load(std::sync::atomic::Ordering::SeqCst), 100);
+        let s = LatencySummary::from_samples(&latencies).unwrap();
+        assert!(s.p95 < Duration::from_millis(250), "p95 {:?}, max {:?}", s.p95, s.max);
+    }
+
+    #[test]
+    fn fifo_holds_out_of_order_messages_until_the_gap_fills() {
+        let mut node = test_node(370, vec![(371, 1)]);
+        node.fifo = true;
+        let queued = |node: &Node| {
+            let mut q: Vec<u64> = node.state.lock().unwrap().request_queues["A"].iter().map(|Reverse((ts, _))| *ts).collect();
+            q.sort();
+            q
+        };
+        let send = |seq, ts| {
+            let msg = RequestMsg { seq, ..request_msg(371, ts) };
+            assert_eq!(node.handle("/receive_request", &serde_json::to_string(&msg).unwrap()).status_code(), 200);
+        };
+
+        send(3, 30);
+        assert!(queued(&node).is_empty());
+        send(1, 10);
+        assert_eq!(queued(&node), [10]);
+        send(2, 20);
+        assert_eq!(queued(&node), [10, 20, 30]);
+
+        let log = log_of(370);
+        let received: Vec<&str> = log.lines().filter_map(|l| verify::field(l, "Received REQUEST from 371 ts=")).collect();
+        assert_eq!(received, ["10", "20", "30"]);
+        assert!(log.contains("Buffering out-of-order message seq=3 from 371 (1 held)"), "{}", log);
+    }
+
+    #[test]
+    fn shared_counter_file_loses_no_updates() {
+        let dir = std::env::temp_dir();
+        let counter = dir.join(format!("lamport-test-{}-counter", std::process::id()));
+        let _ = std::fs::remove_file(&counter);
+        let cfg = Config { log_level: LogLevel::Error, ..Config::default() };
+        let path = dir.join(format!("lamport-test-{}-380.log", std::process::id()));
+        let (nodes, _) = in_process_cluster(&[380, 381, 382], &cfg, open_log(&path));
+        let workers: Vec<_> = nodes
+            .into_iter()
+            .map(|n| {
+                let counter = counter.to_str().unwrap().to_string();
+                thread::spawn(move || (0..4).filter(|_| n.enter_cs_with("A", || { increment_counter_file(&counter).unwrap(); })).count())
+            })
+            .collect();
+        let entered: usize = workers.into_iter().map(|h| h.join().unwrap()).sum();
+        assert_eq!(entered, 12);
+        assert_eq!(std::fs::read_to_string(&counter).unwrap().trim(), "12");
+        let _ = std::fs::remove_file(&counter);
+    }
+
+    #[test]
+    fn nested_enter_of_the_same_resource_is_refused_without_deadlock() {
+        let node = test_node(395, vec![]);
+        let started = Instant::now();
+        let mut inner = None;
+        let outer = node.enter_cs_with("A", || inner = Some(node.enter_cs_with("A", || panic!("nested body ran"))));
+        assert!(outer);
+        assert_eq!(inner, Some(false));
+        assert!(started.elapsed() < Duration::from_secs(1), "took {:?}", started.elapsed());
+        assert!(log_of(395).contains("Rejected reentrant enter_cs for resource=A"), "{}", log_of(395));
+        // The outer entry released A as usual.
+        assert!(node.enter_cs_with("A", || {}));
+    }
+
+    #[test]
+    fn two_threads_on_one_node_serialize_their_entries() {
+        let node = test_node(390, vec![]);
+        let workers: Vec<_> = (0..2)
+            .map(|_| {
+                let node = node.clone();
+                thread::spawn(move || node.enter_cs("A"))
+            })
+            .collect();
+        for w in workers {
+            w.join().unwrap();
+        }
+        assert_eq!(node.metrics.lock().unwrap().cs_entries, 2);
+        // Both entries are in one log, so an overlap would show up there.
+        assert_eq!(verify::verify_log(&log_of(390)), Ok(2));
+        assert!(!node.state.lock().unwrap().in_flight["A"]);
+    }
+
+    #[test]
+    fn opposite_resource_orders_do_not_deadlock() {
+        let path = std::env::temp_dir().join(format!("lamport-test-{}-400.log", std::process::id()));
+        let cfg = Config { log_level: LogLevel::Error, ..Config::default() };
+        let (nodes, _) = in_process_cluster(&[400, 401], &cfg, open_log(&path));
+        let wants: [&'static [&'static str]; 2] = [&["B", "A"], &["A", "B"]];
+        let workers: Vec<_> = nodes
+            .iter()
+            .cloned()
+            .zip(wants)
+            .map(|(n, want)| {
+                thread::spawn(move || {
+                    (0..3)
+                        .filter(|_| {
+                            n.acquire_all_ordered(want, || {
+                                let in_cs = n.in_cs.lock().unwrap();
+                                assert!(in_cs["A"] && in_cs["B"], "node {} ran without both resources", n.id);
+                            })
+                        })
+                        .count()
+                })
+            })
+            .collect();
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !workers.iter().all(|w| w.is_finished()) {
+            assert!(Instant::now() < deadline, "deadlocked");
+            thread::sleep(Duration::from_millis(20));
+        }
+        let entered: usize = workers.into_iter().map(|h| h.join().unwrap()).sum();
+        assert_eq!(entered, 6);
+        assert_eq!(nodes[0].acquisition_order(wants[0]), ["A", "B"]);
+    }
+
+    #[test]
+    fn replaying_a_recorded_trace_gives_the_same_delivery_order() {
+        let tmp = |name: &str| std::env::temp_dir().join(format!("lamport-test-{}-410-{}", std::process::id(), name));
+        let (trace_path, run_log, replay_log) = (tmp("trace.jsonl"), tmp("run.log"), tmp("replay.log"));
+        for p in [&trace_path, &run_log, &replay_log] {
+            let _ = std::fs::remove_file(p);
+        }
+        let peers = vec![(411, 0), (412, 0)];
+        let recorded =
+            Node::new(410, 0, peers.clone(), &Config::default(), open_log(&run_log)).offline().with_trace(open_log(&trace_path));
+        for (path, msg) in [
+            ("/receive_request", serde_json::to_string(&request_msg(412, 3)).unwrap()),
+            ("/receive_request", serde_json::to_string(&request_msg(411, 5)).unwrap()),
+            ("/receive_reply", serde_json::to_string(&reply_msg(411, 2)).unwrap()),
+            ("/receive_request", serde_json::to_string(&request_msg(411, 1)).unwrap()),
+            ("/receive_reply", serde_json::to_string(&reply_msg(412, 2)).unwrap()),
+        ] {
+            assert_eq!(recorded.handle(path, &msg).status_code(), 200);
+        }
+
+        let entries = trace::load(trace_path.to_str().unwrap()).unwrap();
+        let replayed = Node::new(410, 0, peers, &Config::default(), open_log(&replay_log)).offline();
+        assert_eq!(trace::replay(&replayed, entries), 5);
+
+        let received = |path: &std::path::Path| -> Vec<String> {
+            std::fs::read_to_string(path)
+                .unwrap()
+                .lines()
+                .filter_map(|l| l.find("Received ").or_else(|| l.find("STRAY_REPLY")).map(|i| &l[i..]))
+                // Drop the arrival time, which differs between the two runs.
+                .map(|l| l.split(" at_ms=").next().unwrap().to_string())
+                .collect()
+        };
+        assert_eq!(received(&run_log).len(), 5);
+        assert_eq!(received(&replay_log), received(&run_log));
+        assert_eq!(replayed.status_json()["resources"], recorded.status_json()["resources"]);
+    }
+
+    #[test]
+    fn messages_below_the_log_level_are_suppressed() {
+        let path = std::env::temp_dir().join(format!("lamport-test-{}-415.log", std::process::id()));
+        let cfg = Config { log_level: LogLevel::Info, ..Config::default() };
+        let node = Node::new(415, 0, vec![], &cfg, open_log(&path)).offline();
+        node.log_at(LogLevel::Error, "an error line");
+        node.log_at(LogLevel::Info, "an info line");
+        node.log_at(LogLevel::Debug, "a debug line");
+        node.log_at(LogLevel::Trace, "a trace line");
+        let log = log_of(415);
+        assert!(log.contains("an error line") && log.contains("an info line"), "{}", log);
+        assert!(!log.contains("a debug line") && !log.contains("a trace line"), "{}", log);
+    }
+
+    #[test]
+    fn each_quorum_policy_enters_at_its_threshold_and_not_before() {
+        let peers: Vec<(usize, u16)> = (421..=425).map(|p| (p, p as u16)).collect();
+        for (id, quorum, weights, needed) in [
+            (420, QuorumPolicy::All, HashMap::new(), 5),
+            (426, QuorumPolicy::Majority, HashMap::new(), 3),
+            (427, QuorumPolicy::Fixed(2), HashMap::new(), 2),
+            // 421 (weight 3) plus ourselves is 4 of 8, not a majority; one more reply is.
+            (428, QuorumPolicy::WeightedMajority, HashMap::from([(421, 3)]), 2),
+        ] {
+            let path = std::env::temp_dir().join(format!("lamport-test-{}-{}.log", std::process::id(), id));
+            let cfg = Config { quorum: quorum.clone(), weights, ..Config::default() };
+            let node = Node::new(id, 0, peers.clone(), &cfg, open_log(&path)).offline();
+            let ts = node.broadcast_request("A", LockMode::Write).unwrap();
+            for (replies, &(from, _)) in peers.iter().enumerate() {
+                assert!(!node.can_enter_cs("A"), "{:?} entered with {} replies", quorum, replies);
+                node.deliver(Inbound::Reply(reply_msg(from, ts)));
+                if replies + 1 == needed {
+                    break;
+                }
+            }
+            assert!(node.can_enter_cs("A"), "{:?} did not enter with {} replies", quorum, needed);
+        }
+    }
+
+    #[test]
+    fn silent_peer_gets_max_rebroadcasts_then_an_abort_that_clears_peer_queues() {
+        let path = std::env::temp_dir().join(format!("lamport-test-{}-430.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let cfg = Config { attempt_timeout: Duration::from_millis(100), max_rebroadcasts: 2, ..Config::default() };
+        let (nodes, _) = in_process_cluster(&[430, 431, 432], &cfg, open_log(&path));
+        // Node 432 never hears from 430, so 430 never gets its reply.
+        let body = serde_json::json!({ "peer": 432, "partitioned": true }).to_string();
+        assert_eq!(nodes[0].handle("/chaos", &body).status_code(), 200);
+
+        assert!(!nodes[0].enter_cs_with("A", || panic!("entered without 432's reply")));
+        let log = std::fs::read_to_string(&path).unwrap();
+        let rebroadcasts: Vec<&str> = log.lines().filter(|l| l.contains("[Node 430] Re-broadcasting REQUEST")).collect();
+        assert_eq!(rebroadcasts.len(), 2, "{}", log);
+        assert!(rebroadcasts[0].ends_with("to [432] (attempt 1)") && rebroadcasts[1].ends_with("to [432] (attempt 2)"), "{}", log);
+        assert!(log.contains("[Node 430] Aborting REQUEST"), "{}", log);
+
+        // The abort's RELEASE reached 431, so its queue isn't left poisoned.
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while nodes[1].state.lock().unwrap().request_queues["A"].iter().any(|Reverse((_, from))| *from == 430) {
+            assert!(Instant::now() < deadline, "430's aborted request still queued at 431");
+            thread::sleep(Duration::from_millis(20));
+        }
+        assert!(nodes[1].enter_cs_with("A", || {}));
+    }
+
+    #[test]
+    fn dashboard_serves_html_that_polls_status_cross_origin() {
+        let node = test_node(435, vec![]);
+        let resp = node.handle("/", "");
+        assert_eq!(resp.status_code(), 200);
+        let content_type = resp.headers().iter().find(|h| h.field.equiv("Content-Type")).unwrap();
+        assert_eq!(content_type.value.as_str(), "text/html; charset=utf-8");
+        let mut body = String::new();
+        std::io::Read::read_to_string(&mut resp.into_reader(), &mut body).unwrap();
+        assert!(body.contains("<title>Lamport mutex dashboard</title>") && body.contains("/status"), "{}", body);
+
+        // The page polls every node's /status from one origin.
+        let status = node.handle("/status", "");
+        let allow = status.headers().iter().find(|h| h.field.equiv("Access-Control-Allow-Origin")).unwrap();
+        assert_eq!(allow.value.as_str(), "*");
+    }
+
+    #[test]
+    fn one_multicast_broadcast_reaches_three_listeners() {
+        let port = std::net::UdpSocket::bind("0.0.0.0:0").unwrap().local_addr().unwrap().port();
+        let args = ["--transport".to_string(), "multicast".to_string(), "--multicast-group".to_string(), format!("239.255.42.7:{}", port)];
+        let cfg = Config::parse(args.into_iter());
+        let inboxes: Vec<_> = (0..3)
+            .map(|_| {
+                let (tx, rx) = std::sync::mpsc::channel();
+                cfg.transport().listen(Box::new(move |text| tx.send(text.to_string()).unwrap())).unwrap();
+                rx
+            })
+            .collect();
+
+        let payload = serde_json::to_string(&request_msg(440, 1)).unwrap();
+        assert!(cfg.transport().broadcast(&payload).unwrap());
+        for rx in &inboxes {
+            assert_eq!(rx.recv_timeout(Duration::from_secs(2)).unwrap(), payload);
+            assert!(rx.recv_timeout(Duration::from_millis(100)).is_err(), "delivered twice");
+        }
+    }
+}
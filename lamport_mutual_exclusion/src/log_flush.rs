@@ -0,0 +1,96 @@
+//! `--log-flush`: when log lines reach the file. `every-line` (the default)
+//! writes each line as it is logged; `interval:MS` queues lines and has a
+//! background thread write them out every MS; `on-exit` holds them until
+//! the process shuts down, which is fastest and loses the most on a crash.
+//! Queued lines keep the order they were logged in across every node of
+//! the process, so the shared log still gives `verify` a total order.
+
+use std::{
+    fs::File,
+    io::{self, Write},
+    str::FromStr,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use dc_common::log::LogRotation;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum LogFlush {
+    #[default]
+    EveryLine,
+    Interval(Duration),
+    OnExit,
+}
+
+impl FromStr for LogFlush {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s.to_ascii_lowercase().as_str() {
+            "every-line" => Ok(LogFlush::EveryLine),
+            "on-exit" => Ok(LogFlush::OnExit),
+            other => other
+                .strip_prefix("interval:")
+                .and_then(|ms| ms.parse().ok())
+                .map(|ms| LogFlush::Interval(Duration::from_millis(ms)))
+                .ok_or(()),
+        }
+    }
+}
+
+impl LogFlush {
+    pub fn describe(self) -> String {
+        match self {
+            LogFlush::EveryLine => "every-line".to_string(),
+            LogFlush::Interval(every) => format!("interval:{}", every.as_millis()),
+            LogFlush::OnExit => "on-exit".to_string(),
+        }
+    }
+}
+
+type Pending = (Arc<Mutex<File>>, Option<LogRotation>, String);
+
+/// Lines waiting for their file, shared by every node in the process.
+#[derive(Default)]
+pub struct LogBuffer {
+    pub policy: LogFlush,
+    pending: Mutex<Vec<Pending>>,
+}
+
+impl LogBuffer {
+    pub fn new(policy: LogFlush) -> Self {
+        LogBuffer { policy, pending: Mutex::new(vec![]) }
+    }
+
+    pub fn push(&self, file: &Arc<Mutex<File>>, rotation: &Option<LogRotation>, line: String) {
+        self.pending.lock().unwrap().push((file.clone(), rotation.clone(), line));
+    }
+
+    /// Writes out everything queued so far, each line under its file's
+    /// mutex so it can't interleave with a direct write.
+    pub fn flush(&self) -> io::Result<()> {
+        let pending = std::mem::take(&mut *self.pending.lock().unwrap());
+        for (file, rotation, line) in pending {
+            let mut f = file.lock().unwrap();
+            f.write_all(line.as_bytes())?;
+            if let Some(r) = &rotation {
+                r.rotate_if_full(&mut f)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// With `interval:MS`, flushes every MS for as long as the process runs.
+    pub fn start_flusher(self: &Arc<Self>) {
+        let LogFlush::Interval(every) = self.policy else { return };
+        let buffer = Arc::clone(self);
+        thread::spawn(move || loop {
+            thread::sleep(every);
+            if let Err(e) = buffer.flush() {
+                eprintln!("cannot flush log: {}", e);
+            }
+        });
+    }
+}
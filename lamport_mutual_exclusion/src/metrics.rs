@@ -0,0 +1,112 @@
+//! `/metrics`: a node's counters and CS wait times, as JSON or in the
+//! Prometheus text exposition format so a lab Prometheus can scrape it.
+
+use std::{fmt::Write, str::FromStr, time::Duration};
+
+use crate::Metrics;
+
+/// Upper bounds, in seconds, of the `cs_wait_seconds` histogram buckets.
+const CS_WAIT_BUCKETS: [f64; 10] = [0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum MetricsFormat {
+    #[default]
+    Json,
+    Prometheus,
+}
+
+impl FromStr for MetricsFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s.to_ascii_lowercase().as_str() {
+            "json" => Ok(MetricsFormat::Json),
+            "prometheus" => Ok(MetricsFormat::Prometheus),
+            _ => Err(()),
+        }
+    }
+}
+
+impl MetricsFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            MetricsFormat::Json => "application/json",
+            MetricsFormat::Prometheus => "text/plain; version=0.0.4; charset=utf-8",
+        }
+    }
+}
+
+/// `(name, help, value)` of every plain counter, without the `lamport_`
+/// prefix or `_total` suffix.
+fn counters(m: &Metrics) -> [(&'static str, &'static str, u64); 7] {
+    [
+        ("requests_sent", "REQUEST messages sent to peers, counting each peer of a broadcast.", m.requests_sent),
+        ("replies_received", "REPLY messages accepted for an outstanding request.", m.replies_received),
+        ("cs_entries", "Critical section entries.", m.cs_entries),
+        ("fast_path_entries", "Critical section entries granted by the fast-path leader.", m.fast_path_entries),
+        ("slow_path_entries", "Critical section entries through the full Lamport broadcast.", m.slow_path_entries),
+        ("timeouts", "Requests abandoned after waiting too long for replies or the token.", m.timeouts),
+        ("malformed_messages", "Inbound payloads rejected as malformed.", m.malformed_messages),
+    ]
+}
+
+/// Sorted by resource so repeated scrapes list series in the same order.
+fn cs_wait_by_resource(m: &Metrics) -> Vec<(&String, &Vec<Duration>)> {
+    let mut waits: Vec<_> = m.cs_wait.iter().collect();
+    waits.sort_by_key(|(res, _)| *res);
+    waits
+}
+
+/// Escapes a label value per the exposition format.
+fn escape_label(v: &str) -> String {
+    v.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+impl Metrics {
+    pub fn render(&self, node: usize, format: MetricsFormat) -> String {
+        match format {
+            MetricsFormat::Json => self.to_json(node).to_string(),
+            MetricsFormat::Prometheus => self.to_prometheus(node),
+        }
+    }
+
+    pub fn to_json(&self, node: usize) -> serde_json::Value {
+        let mut out = serde_json::Map::new();
+        out.insert("node".into(), node.into());
+        for (name, _, v) in counters(self) {
+            out.insert(name.into(), v.into());
+        }
+        let waits: serde_json::Map<_, _> = cs_wait_by_resource(self)
+            .into_iter()
+            .map(|(res, samples)| {
+                let sum: Duration = samples.iter().sum();
+                (res.clone(), serde_json::json!({ "count": samples.len(), "sum_seconds": sum.as_secs_f64() }))
+            })
+            .collect();
+        out.insert("cs_wait_seconds".into(), waits.into());
+        out.into()
+    }
+
+    pub fn to_prometheus(&self, node: usize) -> String {
+        let mut out = String::new();
+        for (name, help, v) in counters(self) {
+            let _ = writeln!(out, "# HELP lamport_{}_total {}", name, help);
+            let _ = writeln!(out, "# TYPE lamport_{}_total counter", name);
+            let _ = writeln!(out, "lamport_{}_total{{node=\"{}\"}} {}", name, node, v);
+        }
+        let _ = writeln!(out, "# HELP lamport_cs_wait_seconds Time from broadcasting a REQUEST to entering the critical section.");
+        let _ = writeln!(out, "# TYPE lamport_cs_wait_seconds histogram");
+        for (res, samples) in cs_wait_by_resource(self) {
+            let labels = format!("node=\"{}\",resource=\"{}\"", node, escape_label(res));
+            for le in CS_WAIT_BUCKETS {
+                let n = samples.iter().filter(|d| d.as_secs_f64() <= le).count();
+                let _ = writeln!(out, "lamport_cs_wait_seconds_bucket{{{},le=\"{}\"}} {}", labels, le, n);
+            }
+            let sum: Duration = samples.iter().sum();
+            let _ = writeln!(out, "lamport_cs_wait_seconds_bucket{{{},le=\"+Inf\"}} {}", labels, samples.len());
+            let _ = writeln!(out, "lamport_cs_wait_seconds_sum{{{}}} {}", labels, sum.as_secs_f64());
+            let _ = writeln!(out, "lamport_cs_wait_seconds_count{{{}}} {}", labels, samples.len());
+        }
+        out
+    }
+}
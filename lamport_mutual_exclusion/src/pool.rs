@@ -0,0 +1,45 @@
+use std::{
+    sync::{mpsc, Arc, Mutex},
+    thread,
+};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Fixed set of worker threads for outbound sends, so a burst of messages
+/// can't spawn an unbounded number of threads.
+pub struct SendPool {
+    /// None runs every job on the caller's thread.
+    tx: Option<mpsc::Sender<Job>>,
+}
+
+impl SendPool {
+    pub fn new(workers: usize) -> Self {
+        let (tx, rx) = mpsc::channel::<Job>();
+        let rx = Arc::new(Mutex::new(rx));
+        for _ in 0..workers.max(1) {
+            let rx = Arc::clone(&rx);
+            thread::spawn(move || loop {
+                let job = match rx.lock().unwrap().recv() {
+                    Ok(job) => job,
+                    Err(_) => break,
+                };
+                job();
+            });
+        }
+        SendPool { tx: Some(tx) }
+    }
+
+    /// A pool that sends synchronously, so send order is the call order.
+    pub fn inline() -> Self {
+        SendPool { tx: None }
+    }
+
+    pub fn execute<F: FnOnce() + Send + 'static>(&self, job: F) {
+        match &self.tx {
+            Some(tx) => {
+                let _ = tx.send(Box::new(job));
+            }
+            None => job(),
+        }
+    }
+}
@@ -0,0 +1,24 @@
+//! `/release?resource=R`: preempts a node holding R, for chaos and recovery
+//! testing. The default CS body notices within one of its 10ms checks and
+//! returns early, after which the usual exit drops our entry and broadcasts
+//! RELEASE. A custom body can't be interrupted; it finishes first and the
+//! preemption lapses when it does.
+
+use crate::{LogLevel, Node};
+
+impl Node {
+    /// Asks the CS we hold on `resource` to end now. False if we don't
+    /// hold it.
+    pub fn preempt(&self, resource: &str) -> bool {
+        if !self.in_cs.lock().unwrap().get(resource).copied().unwrap_or(false) {
+            return false;
+        }
+        self.state.lock().unwrap().preempted.insert(resource.to_string());
+        self.log_at(LogLevel::Info, &format!("PREEMPTED resource={}: leaving the CS early on /release", resource));
+        true
+    }
+
+    pub fn is_preempted(&self, resource: &str) -> bool {
+        self.state.lock().unwrap().preempted.contains(resource)
+    }
+}
@@ -0,0 +1,56 @@
+use std::collections::{HashMap, HashSet};
+
+/// How many REPLYs a node needs before it may enter the CS. Anything short
+/// of `All` trades Lamport's safety guarantee for liveness when peers are
+/// slow or down; the others exist for experiments.
+#[derive(Clone, Debug, PartialEq)]
+pub enum QuorumPolicy {
+    /// A reply from every peer (classic Lamport).
+    All,
+    /// Replies from enough peers that, counting ourselves, a strict majority
+    /// of the cluster agrees.
+    Majority,
+    /// At least this many replies.
+    Fixed(usize),
+    /// Like `Majority`, but each node counts with its configured weight
+    /// (default 1).
+    WeightedMajority,
+}
+
+impl std::str::FromStr for QuorumPolicy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "all" => Ok(QuorumPolicy::All),
+            "majority" => Ok(QuorumPolicy::Majority),
+            "weighted" => Ok(QuorumPolicy::WeightedMajority),
+            _ => s.strip_prefix("fixed:").and_then(|n| n.parse().ok()).map(QuorumPolicy::Fixed).ok_or(()),
+        }
+    }
+}
+
+impl QuorumPolicy {
+    /// Replies needed out of `peers` peers (unweighted policies only).
+    pub fn threshold(&self, peers: usize) -> usize {
+        match self {
+            QuorumPolicy::All => peers,
+            QuorumPolicy::Majority => peers.div_ceil(2),
+            QuorumPolicy::Fixed(n) => (*n).min(peers),
+            QuorumPolicy::WeightedMajority => peers,
+        }
+    }
+
+    pub fn satisfied(&self, me: usize, peers: &[usize], replied: &HashSet<usize>, weights: &HashMap<usize, u32>) -> bool {
+        let replied_peers = peers.iter().filter(|p| replied.contains(p)).count();
+        match self {
+            QuorumPolicy::WeightedMajority => {
+                let w = |id: &usize| weights.get(id).copied().unwrap_or(1) as u64;
+                let total: u64 = peers.iter().map(w).sum::<u64>() + w(&me);
+                let have: u64 = peers.iter().filter(|p| replied.contains(p)).map(w).sum::<u64>() + w(&me);
+                have * 2 > total
+            }
+            _ => replied_peers >= self.threshold(peers.len()),
+        }
+    }
+}
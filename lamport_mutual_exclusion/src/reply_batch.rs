@@ -0,0 +1,56 @@
+//! Batched REPLYs for large clusters: instead of N-1 separate POSTs, a relay
+//! that has collected several peers' replies to one REQUEST forwards them
+//! as a single `/receive_reply_batch`. Each id is then counted exactly as if
+//! its own REPLY had arrived, stray and epoch checks included; a batch
+//! from an older membership view is dropped whole. A batch carries no
+//! per-sender `seq`, so it bypasses `--fifo` reordering.
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{LogLevel, Node, ReplyMsg};
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct ReplyBatchMsg {
+    pub resource: String,
+    /// Every peer whose REPLY the batch stands for.
+    pub from: Vec<usize>,
+    /// Timestamp of the REQUEST being answered; 0 if the relay didn't say.
+    #[serde(default)]
+    pub ts: u64,
+    #[serde(default)]
+    pub epoch: u64,
+    #[serde(default)]
+    pub generation: u64,
+}
+
+impl ReplyBatchMsg {
+    /// The individual REPLYs the batch replaces.
+    pub fn split(&self) -> Vec<ReplyMsg> {
+        self.from
+            .iter()
+            .map(|&from| ReplyMsg {
+                from,
+                resource: self.resource.clone(),
+                seq: 0,
+                ts: self.ts,
+                trace_id: Uuid::nil(),
+                vclock: None,
+                generation: self.generation,
+                epoch: self.epoch,
+            })
+            .collect()
+    }
+}
+
+impl Node {
+    pub fn receive_reply_batch(&self, msg: ReplyBatchMsg) {
+        self.log_at(
+            LogLevel::Debug,
+            &format!("Received REPLY batch from {:?} ts={} for resource={}", msg.from, msg.ts, msg.resource),
+        );
+        for reply in msg.split() {
+            self.receive_reply(reply);
+        }
+    }
+}
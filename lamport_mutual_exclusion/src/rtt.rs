@@ -0,0 +1,55 @@
+//! `--rtt-probe-ms`: times a `/ping` to every peer that often and keeps an
+//! EWMA of the round trip, so `broadcast_request` can send to the fastest
+//! peers first. Under a majority quorum the replies that decide entry are
+//! the earliest ones, so sending to near peers first shortens the wait.
+//! Peers never measured go last, in their configured order.
+
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{LogLevel, Node};
+
+/// Weight of the newest sample; the rest is the running average.
+const RTT_ALPHA: f64 = 0.3;
+
+impl Node {
+    /// Folds one round-trip sample to `peer` into its EWMA.
+    pub fn record_rtt(&self, peer: usize, sample: Duration) {
+        let mut st = self.state.lock().unwrap();
+        let ewma = match st.rtts.get(&peer) {
+            Some(old) => old.mul_f64(1.0 - RTT_ALPHA) + sample.mul_f64(RTT_ALPHA),
+            None => sample,
+        };
+        st.rtts.insert(peer, ewma);
+    }
+
+    /// Pings every peer once, recording the round trip of each that answers.
+    pub fn sample_rtts(&self) {
+        for (nid, port) in self.peers.clone() {
+            let started = Instant::now();
+            match self.send_to(port, "/ping", "") {
+                Ok(()) => self.record_rtt(nid, started.elapsed()),
+                Err(e) => self.log_at(LogLevel::Trace, &format!("RTT probe to {} failed: {}", nid, e)),
+            }
+        }
+    }
+
+    /// Peers from lowest to highest smoothed RTT, unmeasured ones last.
+    pub fn peers_by_rtt(&self) -> Vec<(usize, u16)> {
+        let st = self.state.lock().unwrap();
+        let mut peers = self.peers.clone();
+        peers.sort_by_key(|(nid, _)| st.rtts.get(nid).copied().unwrap_or(Duration::MAX));
+        peers
+    }
+
+    pub fn start_rtt_probe(&self) {
+        let Some(every) = self.rtt_probe_every else { return };
+        let node = self.clone();
+        thread::spawn(move || loop {
+            node.sample_rtts();
+            thread::sleep(every);
+        });
+    }
+}
@@ -0,0 +1,189 @@
+//! Deterministic interleavings for tests and `--self-test`: every message in
+//! flight sits in one queue and a seeded RNG picks which is delivered next,
+//! so a seed names one schedule and a failure can be replayed exactly.
+
+#[cfg(test)]
+use std::fs::OpenOptions;
+use std::{
+    collections::HashMap,
+    fs::File,
+    sync::{Arc, Mutex},
+};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{error::NodeError, pool::SendPool, transport::Transport, Config, LockMode, LogLevel, Node, State};
+
+const RESOURCE: &str = "A";
+
+type Queue = Arc<Mutex<Vec<(u16, String, String)>>>;
+
+/// Parks every send in the scheduler's queue instead of delivering it.
+struct Held(Queue);
+
+impl Transport for Held {
+    fn send(&self, port: u16, path: &str, payload: &str) -> Result<(), NodeError> {
+        self.0.lock().unwrap().push((port, path.to_string(), payload.to_string()));
+        Ok(())
+    }
+}
+
+pub struct Scheduler {
+    pub nodes: Vec<Node>,
+    queue: Queue,
+    rng: StdRng,
+    seed: u64,
+    /// Outstanding request timestamp per waiting node.
+    waiting: HashMap<usize, u64>,
+    holder: Option<(usize, u64)>,
+    per_node: usize,
+    /// Acquisitions each node has yet to start.
+    remaining: HashMap<usize, usize>,
+    /// `(node, ts)` of every CS entry so far, in order.
+    pub entries: Vec<(usize, u64)>,
+}
+
+impl Scheduler {
+    /// Nodes `ids` each want resource A `per_node` times. Node `id` is
+    /// addressed as port `id`. Call `reset` to pick a schedule.
+    #[cfg(test)]
+    pub fn new(ids: &[usize], per_node: usize) -> Self {
+        let path = std::env::temp_dir().join(format!("lamport-test-{}-{}.log", std::process::id(), ids[0]));
+        let log_file = Arc::new(Mutex::new(OpenOptions::new().create(true).append(true).open(path).unwrap()));
+        Self::with_log(ids, per_node, log_file)
+    }
+
+    pub fn with_log(ids: &[usize], per_node: usize, log_file: Arc<Mutex<File>>) -> Self {
+        // Lamport assumes FIFO channels; without them a reply can overtake
+        // an earlier REQUEST and the oracle finds overlaps within a few seeds.
+        let cfg = Config { fifo: true, log_level: LogLevel::Error, send_workers: 1, ..Config::default() };
+        let queue: Queue = Arc::default();
+        let nodes = ids
+            .iter()
+            .map(|&id| {
+                let peers = ids.iter().filter(|&&p| p != id).map(|&p| (p, p as u16)).collect();
+                let mut node = Node::new(id, id as u16, peers, &cfg, log_file.clone());
+                node.transport = Arc::new(Held(queue.clone()));
+                node.sender = Arc::new(SendPool::inline());
+                node
+            })
+            .collect();
+        Scheduler {
+            nodes,
+            queue,
+            rng: StdRng::seed_from_u64(0),
+            seed: 0,
+            waiting: HashMap::new(),
+            holder: None,
+            per_node,
+            remaining: HashMap::new(),
+            entries: vec![],
+        }
+    }
+
+    /// Starts over from fresh node state under the schedule named by `seed`.
+    /// Reuses the nodes themselves, which are slow to build.
+    pub fn reset(&mut self, seed: u64) {
+        for node in &mut self.nodes {
+            node.state = Arc::new(Mutex::new(State::new()));
+            node.in_cs = Arc::default();
+            node.cs_threads = Arc::default();
+        }
+        self.queue.lock().unwrap().clear();
+        self.rng = StdRng::seed_from_u64(seed);
+        self.seed = seed;
+        self.waiting.clear();
+        self.holder = None;
+        self.entries.clear();
+        self.remaining = self.nodes.iter().map(|n| (n.id, self.per_node)).collect();
+    }
+
+    fn node(&self, id: usize) -> &Node {
+        self.nodes.iter().find(|n| n.id == id).unwrap()
+    }
+
+    /// Delivers one message chosen by the seed. Returns false if none are in
+    /// flight.
+    pub fn deliver_next(&mut self) -> bool {
+        let next = {
+            let mut q = self.queue.lock().unwrap();
+            if q.is_empty() {
+                return false;
+            }
+            let i = self.rng.gen_range(0..q.len());
+            q.swap_remove(i)
+        };
+        let (port, path, payload) = next;
+        self.node(port as usize).handle(&path, &payload);
+        true
+    }
+
+    /// Takes one step — a delivery, a new request or a CS exit — then lets in
+    /// every node the protocol now admits. Returns Ok(false) once nothing is
+    /// left to do, or Err if two nodes would hold the CS at once.
+    pub fn step(&mut self) -> Result<bool, String> {
+        let in_flight = self.queue.lock().unwrap().len();
+        let mut idle: Vec<usize> = self
+            .remaining
+            .iter()
+            .filter(|(id, left)| **left > 0 && !self.waiting.contains_key(id) && self.holder.map(|(h, _)| h) != Some(**id))
+            .map(|(id, _)| *id)
+            .collect();
+        idle.sort();
+        let exits = usize::from(self.holder.is_some());
+        let choices = in_flight + idle.len() + exits;
+        if choices == 0 {
+            return Ok(false);
+        }
+        let pick = self.rng.gen_range(0..choices);
+        if pick < in_flight {
+            self.deliver_next();
+        } else if pick < in_flight + idle.len() {
+            let id = idle[pick - in_flight];
+            let ts = self.node(id).broadcast_request(RESOURCE, LockMode::Write).unwrap();
+            *self.remaining.get_mut(&id).unwrap() -= 1;
+            self.waiting.insert(id, ts);
+        } else if let Some((id, ts)) = self.holder.take() {
+            let node = self.node(id).clone();
+            node.mark_in_cs(RESOURCE, false);
+            node.finish_cs(RESOURCE, ts);
+        }
+        self.admit()?;
+        Ok(true)
+    }
+
+    /// The CS oracle: nobody may be admitted while someone else holds it.
+    fn admit(&mut self) -> Result<(), String> {
+        let mut ready: Vec<usize> = self.waiting.keys().copied().filter(|&id| self.node(id).can_enter_cs(RESOURCE)).collect();
+        ready.sort();
+        for id in ready {
+            if let Some((holder, _)) = self.holder {
+                return Err(format!("seed {}: node {} admitted to resource={} while node {} holds it", self.seed, id, RESOURCE, holder));
+            }
+            let ts = self.waiting.remove(&id).unwrap();
+            self.node(id).mark_in_cs(RESOURCE, true);
+            self.holder = Some((id, ts));
+            self.entries.push((id, ts));
+        }
+        Ok(())
+    }
+
+    /// Steps until nothing more can happen. Returns Err on overlap.
+    pub fn run_until_quiet(&mut self) -> Result<(), String> {
+        while self.step()? {}
+        Ok(())
+    }
+
+    /// Why the schedule ended with requests still waiting, if it did.
+    pub fn stall(&self) -> Option<String> {
+        (!self.waiting.is_empty()).then(|| format!("seed {}: stalled with {:?} still waiting", self.seed, self.waiting))
+    }
+
+    /// Steps until every acquisition has finished. Returns Err on overlap or
+    /// if the schedule stalls with requests still waiting.
+    #[cfg(test)]
+    pub fn run(&mut self) -> Result<(), String> {
+        self.run_until_quiet()?;
+        self.stall().map_or(Ok(()), Err)
+    }
+}
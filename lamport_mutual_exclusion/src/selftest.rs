@@ -0,0 +1,102 @@
+//! `--self-test`: a fixed, seeded run of the whole protocol in-process under
+//! the scheduler's CS oracle, so CI gets a pass/fail answer without ports,
+//! and with `--junit` a testcase per invariant.
+
+use std::{
+    fs::OpenOptions,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    junit::{self, Case},
+    scheduler::Scheduler,
+    Config,
+};
+
+const SELF_TEST_NODES: [usize; 4] = [0, 1, 2, 3];
+const SELF_TEST_PER_NODE: usize = 3;
+const SELF_TEST_SCHEDULES: u64 = 50;
+const SELF_TEST_LOG: &str = "selftest.log";
+
+/// What the self-test found over every schedule: how many entries were
+/// made, and the first overlap and first unfinished node, if any.
+#[derive(Debug, Default)]
+pub struct Findings {
+    pub entries: usize,
+    pub overlap: Option<String>,
+    pub stall: Option<String>,
+}
+
+impl Findings {
+    /// The first failure, or the number of entries checked.
+    pub fn verdict(&self) -> Result<usize, String> {
+        match self.overlap.clone().or_else(|| self.stall.clone()) {
+            Some(e) => Err(e),
+            None => Ok(self.entries),
+        }
+    }
+
+    pub fn cases(&self) -> Vec<Case> {
+        vec![
+            Case::new("mutual exclusion held", self.overlap.clone().map_or(Ok(()), Err)),
+            Case::new("all nodes progressed", self.stall.clone().map_or(Ok(()), Err)),
+        ]
+    }
+}
+
+/// Runs `SELF_TEST_SCHEDULES` schedules from `--seed` (default 0), checking
+/// each for overlap and for a node that didn't finish all its entries.
+pub fn findings(first_seed: u64) -> Result<Findings, String> {
+    let path = std::env::temp_dir().join(SELF_TEST_LOG);
+    let file = OpenOptions::new().create(true).append(true).open(&path);
+    let log_file = Arc::new(Mutex::new(file.map_err(|e| format!("cannot open {}: {}", path.display(), e))?));
+    let mut sched = Scheduler::with_log(&SELF_TEST_NODES, SELF_TEST_PER_NODE, log_file);
+    let mut found = Findings::default();
+    for seed in first_seed..first_seed + SELF_TEST_SCHEDULES {
+        sched.reset(seed);
+        if let Err(e) = sched.run_until_quiet() {
+            found.overlap.get_or_insert(e);
+            continue;
+        }
+        let short = SELF_TEST_NODES.iter().find_map(|&id| {
+            let got = sched.entries.iter().filter(|(n, _)| *n == id).count();
+            (got != SELF_TEST_PER_NODE)
+                .then(|| format!("seed {}: node {} entered {} of {} times", seed, id, got, SELF_TEST_PER_NODE))
+        });
+        if let Some(e) = sched.stall().or(short) {
+            found.stall.get_or_insert(e);
+        }
+        found.entries += sched.entries.len();
+    }
+    Ok(found)
+}
+
+/// Entry point for `--self-test`; returns the process exit code.
+pub fn run(cfg: &Config) -> i32 {
+    let first_seed = cfg.seed.unwrap_or(0);
+    let found = match findings(first_seed) {
+        Ok(f) => f,
+        Err(e) => {
+            println!("SELF-TEST FAILED: {}", e);
+            return 1;
+        }
+    };
+    if let Some(path) = &cfg.junit {
+        if let Err(e) = junit::write(path, "self-test", &found.cases()) {
+            eprintln!("cannot write {}: {}", path, e);
+        }
+    }
+    match found.verdict() {
+        Ok(entries) => {
+            println!(
+                "SELF-TEST OK: {} schedules from seed {}, {} critical section entries, no overlap",
+                SELF_TEST_SCHEDULES, first_seed, entries
+            );
+            0
+        }
+        Err(e) => {
+            println!("SELF-TEST FAILED: {}", e);
+            1
+        }
+    }
+}
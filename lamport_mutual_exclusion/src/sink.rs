@@ -0,0 +1,89 @@
+//! `--log-sink`: where `log_at` sends each line. `file` is the shared or
+//! per-node log, echoed to stdout, and is the only sink `verify` can read;
+//! `tcp:HOST:PORT` ships newline-delimited JSON to a collector such as
+//! Logstash, for clusters spread over several hosts.
+
+use std::{
+    io::{self, Write},
+    net::{TcpStream, ToSocketAddrs},
+    str::FromStr,
+    sync::Mutex,
+    time::Duration,
+};
+
+const TCP_CONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Default)]
+pub enum LogSink {
+    #[default]
+    File,
+    Stdout,
+    Tcp(TcpSink),
+}
+
+impl FromStr for LogSink {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s.to_ascii_lowercase().as_str() {
+            "file" => Ok(LogSink::File),
+            "stdout" => Ok(LogSink::Stdout),
+            _ => match s.split_once(':') {
+                Some((kind, addr)) if kind.eq_ignore_ascii_case("tcp") && !addr.is_empty() => {
+                    Ok(LogSink::Tcp(TcpSink::new(addr)))
+                }
+                _ => Err(()),
+            },
+        }
+    }
+}
+
+impl LogSink {
+    pub fn describe(&self) -> String {
+        match self {
+            LogSink::File => "file".to_string(),
+            LogSink::Stdout => "stdout".to_string(),
+            LogSink::Tcp(t) => format!("tcp:{}", t.addr),
+        }
+    }
+}
+
+/// One connection to the collector, opened on the first line and again
+/// after any write fails, so a collector restart only loses the lines sent
+/// while it was down.
+#[derive(Debug)]
+pub struct TcpSink {
+    addr: String,
+    stream: Mutex<Option<TcpStream>>,
+}
+
+impl TcpSink {
+    pub fn new(addr: &str) -> Self {
+        TcpSink { addr: addr.to_string(), stream: Mutex::new(None) }
+    }
+
+    fn connect(&self) -> io::Result<TcpStream> {
+        let mut last = io::Error::new(io::ErrorKind::NotFound, format!("{} resolves to no address", self.addr));
+        for a in self.addr.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&a, TCP_CONNECT_TIMEOUT) {
+                Ok(s) => return Ok(s),
+                Err(e) => last = e,
+            }
+        }
+        Err(last)
+    }
+
+    pub fn send(&self, ts: u64, node: usize, level: &str, msg: &str) -> io::Result<()> {
+        let mut line = serde_json::json!({ "ts": ts, "node": node, "level": level, "msg": msg }).to_string();
+        line.push('\n');
+        let mut stream = self.stream.lock().unwrap();
+        if stream.is_none() {
+            *stream = Some(self.connect()?);
+        }
+        let result = stream.as_mut().unwrap().write_all(line.as_bytes());
+        if result.is_err() {
+            *stream = None;
+        }
+        result
+    }
+}
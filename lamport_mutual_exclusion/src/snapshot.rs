@@ -0,0 +1,247 @@
+//! Chandy-Lamport global snapshots over the REQUEST/REPLY channels.
+//!
+//! Markers share the per-peer FIFO sequence with REQUEST/REPLY, so with
+//! `--fifo` every message sent before a marker is delivered before it and
+//! the recorded cut is consistent. Without `--fifo` it is best effort.
+
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap, HashSet},
+    thread,
+    time::{Duration, Instant},
+};
+
+use reqwest::blocking::Client;
+use serde::{Deserialize, Serialize};
+
+use crate::{fifo::Inbound, LogLevel, Node, State, NODES};
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct MarkerMsg {
+    pub from: usize,
+    pub snapshot_id: u64,
+    #[serde(default)]
+    pub seq: u64,
+}
+
+/// One node's part of a global snapshot.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct NodeSnapshot {
+    pub id: usize,
+    pub snapshot_id: u64,
+    /// True once a marker has arrived on every incoming channel.
+    pub complete: bool,
+    pub timestamp: u64,
+    pub queues: BTreeMap<String, Vec<(u64, usize)>>,
+    pub replies: BTreeMap<String, Vec<usize>>,
+    /// REQUEST/REPLY messages sent to / delivered from each peer so far.
+    pub sent: BTreeMap<usize, u64>,
+    pub received: BTreeMap<usize, u64>,
+    /// Messages in flight on each incoming channel at the cut.
+    pub channels: BTreeMap<usize, Vec<Inbound>>,
+}
+
+#[derive(Debug, Default)]
+pub struct Recorder {
+    sent: HashMap<usize, u64>,
+    received: HashMap<usize, u64>,
+    current: Option<NodeSnapshot>,
+    /// Incoming channels still being recorded for `current`.
+    open: HashSet<usize>,
+}
+
+impl Recorder {
+    pub fn note_sent(&mut self, to: usize) {
+        *self.sent.entry(to).or_default() += 1;
+    }
+
+    /// Counts a delivered REQUEST/REPLY and, while its channel is open,
+    /// records it as in flight at the cut.
+    pub fn note_received(&mut self, from: usize, msg: &Inbound) {
+        *self.received.entry(from).or_default() += 1;
+        if self.open.contains(&from) {
+            if let Some(snap) = &mut self.current {
+                snap.channels.entry(from).or_default().push(msg.clone());
+            }
+        }
+    }
+}
+
+/// Checks that no channel shows more messages received or in flight than
+/// its sender had sent at the cut.
+pub fn check_cut(nodes: &[NodeSnapshot]) -> Result<(), String> {
+    for to in nodes {
+        for from in nodes.iter().filter(|n| n.id != to.id) {
+            let sent = from.sent.get(&to.id).copied().unwrap_or(0);
+            let received = to.received.get(&from.id).copied().unwrap_or(0);
+            let in_flight = to.channels.get(&from.id).map(Vec::len).unwrap_or(0) as u64;
+            if received + in_flight > sent {
+                return Err(format!(
+                    "channel {}->{}: {} received + {} in flight but only {} sent",
+                    from.id, to.id, received, in_flight, sent
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+impl Node {
+    /// Records local state under the state lock and opens recording on
+    /// every incoming channel except `closed`. Returns the outgoing marker
+    /// sequence numbers, taken under the same lock so nothing sent after the
+    /// cut can be sequenced ahead of a marker.
+    fn record_local(&self, st: &mut State, snapshot_id: u64, closed: Option<usize>) -> Vec<(usize, u16, u64)> {
+        let queues = st
+            .request_queues
+            .iter()
+            .map(|(r, q)| {
+                let mut v: Vec<(u64, usize)> = q.iter().map(|Reverse(e)| *e).collect();
+                v.sort();
+                (r.clone(), v)
+            })
+            .collect();
+        let replies = st
+            .replies
+            .iter()
+            .map(|(r, s)| {
+                let mut v: Vec<usize> = s.iter().cloned().collect();
+                v.sort();
+                (r.clone(), v)
+            })
+            .collect();
+        let rec = &mut st.snapshots;
+        rec.open = self.peers.iter().map(|(nid, _)| *nid).filter(|nid| Some(*nid) != closed).collect();
+        rec.current = Some(NodeSnapshot {
+            id: self.id,
+            snapshot_id,
+            complete: rec.open.is_empty(),
+            timestamp: st.timestamp.get(),
+            queues,
+            replies,
+            sent: rec.sent.iter().map(|(k, v)| (*k, *v)).collect(),
+            received: rec.received.iter().map(|(k, v)| (*k, *v)).collect(),
+            channels: BTreeMap::new(),
+        });
+        // Markers ride the FIFO sequence but aren't counted as sent.
+        self.peers
+            .iter()
+            .map(|(nid, port)| (*nid, *port, if self.fifo { st.fifo.next_seq(*nid) } else { 0 }))
+            .collect()
+    }
+
+    fn send_markers(&self, snapshot_id: u64, markers: Vec<(usize, u16, u64)>) {
+        if self.offline {
+            return;
+        }
+        for (nid, port, seq) in markers {
+            let msg = MarkerMsg { from: self.id, snapshot_id, seq };
+            let payload = serde_json::to_string(&msg).unwrap();
+            let node = self.clone();
+            self.sender.execute(move || {
+                if let Err(e) = node.send_to(port, "/marker", &payload) {
+                    node.log_at(LogLevel::Error, &format!("Error sending MARKER to {}: {}", nid, e));
+                }
+            });
+        }
+    }
+
+    /// Starts a new global snapshot with this node as initiator.
+    pub fn start_snapshot(&self) -> u64 {
+        let snapshot_id = self.clock.now().as_millis() as u64;
+        let markers = self.record_local(&mut self.state.lock().unwrap(), snapshot_id, None);
+        self.log(&format!("Starting snapshot {}", snapshot_id));
+        self.send_markers(snapshot_id, markers);
+        snapshot_id
+    }
+
+    pub fn receive_marker(&self, msg: MarkerMsg) {
+        let mut st = self.state.lock().unwrap();
+        let current = st.snapshots.current.as_ref().map(|s| (s.snapshot_id, s.complete));
+        match current {
+            Some((id, _)) if id == msg.snapshot_id => {
+                st.snapshots.open.remove(&msg.from);
+                let done = st.snapshots.open.is_empty();
+                if let Some(snap) = &mut st.snapshots.current {
+                    snap.complete = done;
+                }
+                drop(st);
+                if done {
+                    self.log(&format!("Snapshot {} complete", msg.snapshot_id));
+                }
+            }
+            Some((id, false)) => {
+                drop(st);
+                self.log_at(
+                    LogLevel::Error,
+                    &format!("Ignoring MARKER for snapshot {} while snapshot {} is still open", msg.snapshot_id, id),
+                );
+            }
+            _ => {
+                let markers = self.record_local(&mut st, msg.snapshot_id, Some(msg.from));
+                drop(st);
+                self.log(&format!("Recorded state for snapshot {} on MARKER from {}", msg.snapshot_id, msg.from));
+                self.send_markers(msg.snapshot_id, markers);
+            }
+        }
+    }
+
+    pub fn snapshot(&self) -> Option<NodeSnapshot> {
+        self.state.lock().unwrap().snapshots.current.clone()
+    }
+}
+
+/// Entry point for `snapshot`: asks node 0 of a running cluster to start a
+/// snapshot, then gathers every node's part and prints the cut as JSON.
+pub fn run() -> i32 {
+    let client = Client::new();
+    let (_, first) = NODES[0];
+    let started = client
+        .post(format!("http://127.0.0.1:{}/snapshot/start", first))
+        .send()
+        .and_then(|r| r.json::<serde_json::Value>());
+    let snapshot_id = match started.map(|v| v["snapshot_id"].as_u64()) {
+        Ok(Some(id)) => id,
+        other => {
+            eprintln!("cannot start snapshot on port {}: {:?}", first, other);
+            return 2;
+        }
+    };
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let mut nodes: Vec<NodeSnapshot> = vec![];
+    for (id, port) in NODES {
+        loop {
+            let part = client
+                .get(format!("http://127.0.0.1:{}/snapshot", port))
+                .send()
+                .and_then(|r| r.json::<Option<NodeSnapshot>>());
+            if let Ok(Some(s)) = part {
+                if s.snapshot_id == snapshot_id && s.complete {
+                    nodes.push(s);
+                    break;
+                }
+            }
+            if Instant::now() >= deadline {
+                eprintln!("node {} never completed snapshot {}", id, snapshot_id);
+                return 2;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    let consistent = check_cut(&nodes);
+    let out = serde_json::json!({
+        "snapshot_id": snapshot_id,
+        "consistent": consistent.is_ok(),
+        "nodes": nodes,
+    });
+    println!("{}", serde_json::to_string_pretty(&out).unwrap());
+    match consistent {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("INCONSISTENT: {}", e);
+            1
+        }
+    }
+}
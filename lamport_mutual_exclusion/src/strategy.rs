@@ -0,0 +1,104 @@
+//! The rules for who may enter the CS, apart from the transport, logging and
+//! timeouts around them. `Node` calls into a strategy with its state locked,
+//! so an implementation only has to keep `State` consistent.
+
+use std::{cmp::Reverse, collections::HashSet};
+
+use crate::{LockMode, Node, ReplyMsg, RequestMsg, State};
+
+/// What became of a peer's REQUEST.
+#[derive(Debug, PartialEq)]
+pub enum Admission {
+    /// Queued, or already known; reply as usual.
+    Queued,
+    /// No room for it; don't reply, so the sender retries.
+    QueueFull,
+    /// Not a resource we know; reply anyway so the sender isn't stuck.
+    UnknownResource,
+}
+
+pub trait MutexAlgorithm: Send + Sync {
+    fn on_request_received(&self, node: &Node, st: &mut State, msg: &RequestMsg) -> Admission;
+
+    /// Returns false if the REPLY answers none of our outstanding requests.
+    fn on_reply_received(&self, node: &Node, st: &mut State, msg: &ReplyMsg) -> bool;
+
+    fn can_enter(&self, node: &Node, st: &State, resource: &str) -> bool;
+
+    /// Forgets our request `ts` once we've left the CS; the caller then
+    /// broadcasts RELEASE.
+    fn on_exit(&self, node: &Node, st: &mut State, resource: &str, ts: u64);
+}
+
+/// Lamport's algorithm: everyone queues every request by `(ts, id)` and a
+/// node enters once it heads its own queue and has every reply.
+pub struct Lamport;
+
+impl MutexAlgorithm for Lamport {
+    fn on_request_received(&self, node: &Node, st: &mut State, msg: &RequestMsg) -> Admission {
+        st.timestamp.witness(msg.ts);
+        let released = st.released.contains(&(msg.resource.clone(), msg.ts, msg.from));
+        let Some(q) = st.request_queues.get_mut(&msg.resource) else { return Admission::UnknownResource };
+        let queued = q.iter().any(|Reverse(e)| *e == (msg.ts, msg.from));
+        if q.len() >= node.max_queue_len && !queued && !released {
+            return Admission::QueueFull;
+        }
+        if !queued && !released {
+            q.push(Reverse((msg.ts, msg.from)));
+            if msg.mode == LockMode::Read {
+                st.readers.insert((msg.resource.clone(), msg.ts, msg.from));
+            }
+        }
+        Admission::Queued
+    }
+
+    fn on_reply_received(&self, node: &Node, st: &mut State, msg: &ReplyMsg) -> bool {
+        if msg.epoch != 0 && st.epochs.get(&msg.resource).copied().unwrap_or(0) != msg.epoch {
+            return false;
+        }
+        if msg.ts != 0 {
+            let waiting = st.in_flight.get(&msg.resource).copied().unwrap_or(false)
+                && st
+                    .request_queues
+                    .get(&msg.resource)
+                    .is_some_and(|q| q.iter().any(|Reverse(e)| *e == (msg.ts, node.id)));
+            if !waiting {
+                return false;
+            }
+        }
+        st.replies.entry(msg.resource.clone()).or_default().insert(msg.from);
+        true
+    }
+
+    /// A writer must be at the head of the queue; a reader only needs every
+    /// request ahead of it to be a reader too. Readers therefore can't jump a
+    /// queued writer, which is what keeps writers from starving.
+    fn can_enter(&self, node: &Node, st: &State, resource: &str) -> bool {
+        let Some(q) = st.request_queues.get(resource) else { return false };
+        let mut entries: Vec<(u64, usize)> = q.iter().map(|Reverse(e)| *e).collect();
+        entries.sort();
+        let Some(pos) = entries.iter().position(|&(_, nid)| nid == node.id) else { return false };
+        let compatible = match st.mode_of(resource, entries[pos]) {
+            LockMode::Write => pos == 0,
+            LockMode::Read => entries[..pos].iter().all(|&e| st.mode_of(resource, e) == LockMode::Read),
+        };
+        let empty = HashSet::new();
+        let replied = st.replies.get(resource).unwrap_or(&empty);
+        let peers: Vec<usize> = node.peers.iter().map(|(p, _)| *p).filter(|p| st.counts_on(*p)).collect();
+        compatible && node.quorum.satisfied(node.id, &peers, replied, &node.weights)
+    }
+
+    fn on_exit(&self, node: &Node, st: &mut State, resource: &str, ts: u64) {
+        st.readers.remove(&(resource.to_string(), ts, node.id));
+        // Our entry need not be the head: a reader shares the CS with the
+        // readers queued ahead of it. Either way only the request we just
+        // served goes; a later one of ours stays queued.
+        if let Some(q) = st.request_queues.get_mut(resource) {
+            q.retain(|Reverse(e)| *e != (ts, node.id));
+        }
+        if let Some(rset) = st.replies.get_mut(resource) {
+            rset.clear();
+        }
+        st.in_flight.insert(resource.to_string(), false);
+    }
+}
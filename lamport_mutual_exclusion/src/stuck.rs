@@ -0,0 +1,74 @@
+//! `--stuck-head-ms`: a queue whose head hasn't moved for that long may be
+//! waiting on a node that died before releasing. Unlike a lease this asks
+//! the owner first: if it still answers `/ping` it is only slow and keeps
+//! its place; otherwise every entry it has queued here is evicted.
+
+use std::{cmp::Reverse, thread, time::Duration};
+
+use crate::{LogLevel, Node};
+
+const MIN_CHECK_INTERVAL: Duration = Duration::from_millis(50);
+
+impl Node {
+    /// Pings the owner of every queue head stuck past `stuck_head_after`
+    /// and evicts those that don't answer. Returns the evicted node ids.
+    pub fn evict_dead_heads(&self) -> Vec<usize> {
+        let Some(after) = self.stuck_head_after else { return vec![] };
+        let mut stuck: Vec<(usize, String, Duration)> = vec![];
+        for (resource, (_, from), age) in self.queue_heads() {
+            if from != self.id && age > after && !stuck.iter().any(|(id, _, _)| *id == from) {
+                stuck.push((from, resource, age));
+            }
+        }
+        let mut evicted = vec![];
+        for (from, resource, age) in stuck {
+            let Some(&(_, port)) = self.peers.iter().find(|(nid, _)| *nid == from) else { continue };
+            if self.ping(port) {
+                self.log_at(LogLevel::Debug, &format!("Head of resource={} from {} stuck for {:?} but it answers /ping", resource, from, age));
+                continue;
+            }
+            let dropped = {
+                let mut st = self.state.lock().unwrap();
+                let mut dropped = vec![];
+                for (r, q) in st.request_queues.iter_mut() {
+                    q.retain(|Reverse((ts, nid))| {
+                        let keep = *nid != from;
+                        if !keep {
+                            dropped.push((r.clone(), *ts, from));
+                        }
+                        keep
+                    });
+                }
+                for entry in &dropped {
+                    st.readers.remove(entry);
+                    st.released.insert(entry.clone());
+                }
+                dropped
+            };
+            self.log_at(
+                LogLevel::Info,
+                &format!(
+                    "EVICTED_DEAD_NODE node={}: head of resource={} for {:?} and /ping failed; dropped {} entr{}",
+                    from,
+                    resource,
+                    age,
+                    dropped.len(),
+                    if dropped.len() == 1 { "y" } else { "ies" }
+                ),
+            );
+            evicted.push(from);
+        }
+        evicted
+    }
+
+    /// Checks for stuck heads in the background for as long as the process
+    /// runs.
+    pub fn start_stuck_head_watch(&self) {
+        let Some(after) = self.stuck_head_after else { return };
+        let node = self.clone();
+        thread::spawn(move || loop {
+            thread::sleep((after / 2).max(MIN_CHECK_INTERVAL));
+            node.evict_dead_heads();
+        });
+    }
+}
@@ -0,0 +1,99 @@
+//! `--think-dist`: draws the pauses between a node's requests from a
+//! distribution instead of the fixed per-node gaps, so nodes drift in and
+//! out of contention the way real clients do. Each node gets its own RNG
+//! from `--seed` and its id, so a seed names the whole workload.
+
+use std::{str::FromStr, time::Duration};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rand_distr::{Distribution, Exp};
+
+/// Parsed from `constant:MS`, `uniform:MIN..MAX` or `exp:MEAN`, all in
+/// milliseconds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ThinkTime {
+    Constant(Duration),
+    /// Inclusive on both ends.
+    Uniform(Duration, Duration),
+    /// Memoryless pauses, as between independent clients' requests.
+    Exponential { mean: Duration },
+}
+
+impl FromStr for ThinkTime {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        let (kind, arg) = s.split_once(':').ok_or(())?;
+        let ms = |v: &str| v.parse().map(Duration::from_millis).map_err(|_| ());
+        match kind.to_ascii_lowercase().as_str() {
+            "constant" => Ok(ThinkTime::Constant(ms(arg)?)),
+            "uniform" => {
+                let (lo, hi) = arg.split_once("..").ok_or(())?;
+                let (lo, hi) = (ms(lo)?, ms(hi)?);
+                if lo > hi {
+                    return Err(());
+                }
+                Ok(ThinkTime::Uniform(lo, hi))
+            }
+            "exp" | "exponential" => {
+                let mean = ms(arg)?;
+                if mean.is_zero() {
+                    return Err(());
+                }
+                Ok(ThinkTime::Exponential { mean })
+            }
+            _ => Err(()),
+        }
+    }
+}
+
+impl ThinkTime {
+    pub fn sample(&self, rng: &mut impl Rng) -> Duration {
+        match *self {
+            ThinkTime::Constant(d) => d,
+            ThinkTime::Uniform(lo, hi) => rng.gen_range(lo..=hi),
+            ThinkTime::Exponential { mean } => {
+                let exp = Exp::new(1.0 / mean.as_secs_f64()).expect("mean is positive");
+                Duration::from_secs_f64(exp.sample(rng))
+            }
+        }
+    }
+
+    /// The `--print-config` spelling, which parses back to the same value.
+    pub fn describe(&self) -> String {
+        match self {
+            ThinkTime::Constant(d) => format!("constant:{}", d.as_millis()),
+            ThinkTime::Uniform(lo, hi) => format!("uniform:{}..{}", lo.as_millis(), hi.as_millis()),
+            ThinkTime::Exponential { mean } => format!("exp:{}", mean.as_millis()),
+        }
+    }
+}
+
+/// Where one node's pauses come from: the gap between its A and B requests
+/// and the think time between rounds.
+pub enum Pauses {
+    Fixed { gap: Duration, think: Duration },
+    /// Boxed so a `Fixed` node doesn't carry a whole RNG's worth of space.
+    Drawn { dist: ThinkTime, rng: Box<StdRng> },
+}
+
+impl Pauses {
+    /// Node `id`'s draws under `seed`; every node gets a different stream.
+    pub fn drawn(dist: ThinkTime, seed: u64, id: usize) -> Self {
+        Pauses::Drawn { dist, rng: Box::new(StdRng::seed_from_u64(seed ^ (id as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15))) }
+    }
+
+    pub fn gap(&mut self) -> Duration {
+        match self {
+            Pauses::Fixed { gap, .. } => *gap,
+            Pauses::Drawn { dist, rng } => dist.sample(rng),
+        }
+    }
+
+    pub fn think(&mut self) -> Duration {
+        match self {
+            Pauses::Fixed { think, .. } => *think,
+            Pauses::Drawn { dist, rng } => dist.sample(rng),
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use std::{
+    thread,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{error::NodeError, LogLevel, Node};
+
+/// Delay before passing on a token nobody here wants, so an idle ring
+/// doesn't spin.
+const IDLE_PASS_DELAY: Duration = Duration::from_millis(50);
+const TOKEN_WAIT_TIMEOUT: Duration = Duration::from_secs(6);
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct TokenMsg {
+    pub from: usize,
+    pub resource: String,
+}
+
+impl Node {
+    /// Next node in the ring, by ascending id.
+    fn successor(&self) -> (usize, u16) {
+        let mut ring = self.peers.clone();
+        ring.sort();
+        ring.iter()
+            .find(|(nid, _)| *nid > self.id)
+            .or_else(|| ring.first())
+            .cloned()
+            .expect("token ring needs at least one peer")
+    }
+
+    /// Called on the node that holds every token at startup.
+    pub fn start_token_ring(&self, resources: &[&str]) {
+        {
+            let mut st = self.state.lock().unwrap();
+            for r in resources {
+                st.tokens.insert(r.to_string());
+            }
+        }
+        for r in resources {
+            self.log(&format!("Holding initial TOKEN for resource={}", r));
+            self.pass_token_if_idle(r.to_string());
+        }
+    }
+
+    fn pass_token(&self, resource: &str) {
+        {
+            let mut st = self.state.lock().unwrap();
+            if !st.tokens.remove(resource) {
+                return;
+            }
+        }
+        let (nid, port) = self.successor();
+        let payload = serde_json::to_string(&TokenMsg { from: self.id, resource: resource.to_string() }).unwrap();
+        self.log_at(LogLevel::Debug, &format!("Passing TOKEN for resource={} to {}", resource, nid));
+        if let Err(e) = self.send_to(port, "/receive_token", &payload) {
+            self.log_at(LogLevel::Error, &format!("Error sending TOKEN to {}: {}", nid, e));
+            self.state.lock().unwrap().tokens.insert(resource.to_string());
+        }
+    }
+
+    fn pass_token_if_idle(&self, resource: String) {
+        let node = self.clone();
+        thread::spawn(move || {
+            thread::sleep(IDLE_PASS_DELAY);
+            let wanted = node.state.lock().unwrap().wanted.contains(&resource);
+            if !wanted {
+                node.pass_token(&resource);
+            }
+        });
+    }
+
+    pub fn receive_token(&self, msg: TokenMsg) {
+        let wanted = {
+            let mut st = self.state.lock().unwrap();
+            st.tokens.insert(msg.resource.clone());
+            st.wanted.contains(&msg.resource)
+        };
+        self.log_at(LogLevel::Debug, &format!("Received TOKEN from {} for resource={}", msg.from, msg.resource));
+        if !wanted {
+            self.pass_token_if_idle(msg.resource);
+        }
+    }
+
+    pub fn enter_cs_token_ring<F: FnOnce()>(&self, resource: &str, body: F) -> Result<(), NodeError> {
+        let requested_at = self.clock.now();
+        self.state.lock().unwrap().wanted.insert(resource.to_string());
+        self.log_at(LogLevel::Debug, &format!("Waiting for TOKEN for resource={}", resource));
+        loop {
+            let holding = self.state.lock().unwrap().tokens.contains(resource);
+            if holding {
+                let waited = self.clock.elapsed_since(requested_at);
+                self.record_cs_entry(resource, waited);
+                self.run_cs_body(resource, body);
+                self.state.lock().unwrap().wanted.remove(resource);
+                self.pass_token(resource);
+                return Ok(());
+            }
+            if self.clock.elapsed_since(requested_at) > TOKEN_WAIT_TIMEOUT {
+                self.log("Timeout waiting for token");
+                self.metrics.lock().unwrap().timeouts += 1;
+                self.state.lock().unwrap().wanted.remove(resource);
+                return Err(NodeError::Timeout);
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+}
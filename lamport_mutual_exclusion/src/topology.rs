@@ -0,0 +1,80 @@
+//! `--topology FILE`: the cluster from a `topology.toml` shared with the
+//! Byzantine binary, one `[[nodes]]` table per node, e.g.
+//!
+//! ```toml
+//! [[nodes]]
+//! id = 0
+//! port = 8000
+//! resources = ["A"]
+//!
+//! [[nodes]]
+//! id = 2
+//! port = 8002
+//! byzantine = true
+//! behavior = "flip_all"
+//! ```
+//!
+//! Each binary reads the keys it understands and ignores the rest, so the
+//! traitor settings are simply skipped here. `resources` lists what a node
+//! requests in its rounds (all of them if left out); it still queues and
+//! answers everyone's requests for the others. Every node runs on this
+//! machine at 127.0.0.1, so a node has a port but no host.
+
+use std::collections::HashMap;
+
+use dc_common::topology::validate_topology;
+use serde::Deserialize;
+
+use crate::{Config, RESOURCES};
+
+#[derive(Debug, Deserialize)]
+pub struct Topology {
+    pub nodes: Vec<TopologyNode>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TopologyNode {
+    pub id: usize,
+    pub port: u16,
+    pub resources: Option<Vec<String>>,
+}
+
+impl Topology {
+    /// Every problem with the topology; empty if it can be applied.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+        if self.nodes.is_empty() {
+            problems.push("node list is empty".to_string());
+        }
+        for n in &self.nodes {
+            for r in n.resources.iter().flatten().filter(|r| !RESOURCES.contains(&r.as_str())) {
+                problems.push(format!("node {} lists unknown resource {} (known: {})", n.id, r, RESOURCES.join(", ")));
+            }
+        }
+        let nodes: Vec<(usize, u16)> = self.nodes.iter().map(|n| (n.id, n.port)).collect();
+        if let Err(e) = validate_topology(&nodes) {
+            problems.push(e);
+        }
+        problems
+    }
+}
+
+/// Reads and checks `path`, returning the problems if there are any.
+pub fn load(path: &str) -> Result<Topology, Vec<String>> {
+    let text = std::fs::read_to_string(path).map_err(|e| vec![format!("cannot read: {}", e)])?;
+    let topology: Topology = toml::from_str(&text).map_err(|e| vec![format!("not valid topology TOML: {}", e)])?;
+    let problems = topology.problems();
+    if problems.is_empty() {
+        Ok(topology)
+    } else {
+        Err(problems)
+    }
+}
+
+impl Config {
+    /// Applies a topology that passed `Topology::problems`.
+    pub fn apply_topology(&mut self, t: &Topology) {
+        self.nodes = t.nodes.iter().map(|n| (n.id, n.port)).collect();
+        self.node_resources = t.nodes.iter().filter_map(|n| Some((n.id, n.resources.clone()?))).collect::<HashMap<_, _>>();
+    }
+}
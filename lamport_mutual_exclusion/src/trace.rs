@@ -0,0 +1,53 @@
+use std::{
+    fs::{self, File},
+    io::Write,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{fifo::Inbound, vclock::VectorClock, Node};
+
+/// One inbound message as captured by `--record`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TraceEntry {
+    /// Receiving node.
+    pub node: usize,
+    /// Arrival time, milliseconds since the Unix epoch.
+    pub at_ms: u64,
+    #[serde(flatten)]
+    pub msg: Inbound,
+    /// The receiver's vector clock for the receive event, as of arrival.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recv_vclock: Option<VectorClock>,
+}
+
+pub fn record(file: &Arc<Mutex<File>>, node: usize, msg: &Inbound, recv_vclock: Option<VectorClock>) {
+    let at_ms = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+    let entry = TraceEntry { node, at_ms, msg: msg.clone(), recv_vclock };
+    if let Ok(mut f) = file.lock() {
+        let _ = writeln!(f, "{}", serde_json::to_string(&entry).unwrap());
+    }
+}
+
+pub fn load(path: &str) -> Result<Vec<TraceEntry>, String> {
+    let contents = fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, l)| !l.trim().is_empty())
+        .map(|(i, l)| serde_json::from_str(l).map_err(|e| format!("{}:{}: {}", path, i + 1, e)))
+        .collect()
+}
+
+/// Feeds every message `node` received, in arrival order, into its
+/// handlers. The node must be offline so nothing is sent to real peers.
+pub fn replay(node: &Node, entries: Vec<TraceEntry>) -> usize {
+    let mut fed = 0;
+    for e in entries.into_iter().filter(|e| e.node == node.id) {
+        node.deliver(e.msg);
+        fed += 1;
+    }
+    fed
+}
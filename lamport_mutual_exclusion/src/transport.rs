@@ -0,0 +1,199 @@
+use std::{
+    collections::HashMap,
+    io,
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use reqwest::blocking::Client;
+use socket2::{Domain, Protocol, Socket, Type};
+
+use crate::{
+    compress::{gzip, GZIP, GZIP_MIN_BYTES},
+    error::NodeError,
+    wire::WireFormat,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TransportKind {
+    Http,
+    Multicast,
+}
+
+impl FromStr for TransportKind {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s.to_ascii_lowercase().as_str() {
+            "http" => Ok(TransportKind::Http),
+            "multicast" => Ok(TransportKind::Multicast),
+            _ => Err(()),
+        }
+    }
+}
+
+/// How messages leave a node. Point-to-point sends always exist; a
+/// transport may also offer a one-shot broadcast to every peer.
+pub trait Transport: Send + Sync {
+    /// POSTs `payload` to `path` on the node listening on `port`.
+    fn send(&self, port: u16, path: &str, payload: &str) -> Result<(), NodeError>;
+
+    /// Sends one REQUEST to every peer at once. Returns false if this
+    /// transport can't, in which case the caller sends to each peer.
+    fn broadcast(&self, _payload: &str) -> Result<bool, NodeError> {
+        Ok(false)
+    }
+
+    /// Starts feeding broadcasts from peers to `deliver`, if this transport
+    /// receives anything outside the HTTP server.
+    fn listen(&self, _deliver: Box<dyn Fn(&str) + Send>) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Extra attempts after a peer answers with a 5xx status.
+const SERVER_ERROR_RETRIES: u32 = 2;
+const SERVER_ERROR_BACKOFF: Duration = Duration::from_millis(100);
+
+pub struct HttpTransport {
+    client: Client,
+    format: WireFormat,
+    gzip: bool,
+}
+
+impl HttpTransport {
+    pub fn new(client: Client) -> Self {
+        HttpTransport { client, format: WireFormat::Json, gzip: false }
+    }
+
+    pub fn with_format(mut self, format: WireFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn with_gzip(mut self, gzip: bool) -> Self {
+        self.gzip = gzip;
+        self
+    }
+}
+
+impl Transport for HttpTransport {
+    /// A non-2xx answer is a failed delivery. 5xx is retried with a linear
+    /// backoff, since the peer may recover; 4xx means the peer rejected the
+    /// message itself and resending won't help.
+    fn send(&self, port: u16, path: &str, payload: &str) -> Result<(), NodeError> {
+        let url = format!("http://127.0.0.1:{}{}", port, path);
+        let mut body = self.format.json_to_wire(payload)?;
+        let gzipped = self.gzip && body.len() >= GZIP_MIN_BYTES;
+        if gzipped {
+            body = gzip(&body)?;
+        }
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let mut req = self.client.post(&url).header(reqwest::header::CONTENT_TYPE, self.format.content_type());
+            if gzipped {
+                req = req.header(reqwest::header::CONTENT_ENCODING, GZIP);
+            }
+            let resp = req.body(body.clone()).send()?;
+            let status = resp.status();
+            if status.is_success() {
+                return Ok(());
+            }
+            if !status.is_server_error() || attempt > SERVER_ERROR_RETRIES {
+                return Err(NodeError::Delivery(format!("HTTP {} from port {} after {} attempt(s)", status, port, attempt)));
+            }
+            thread::sleep(SERVER_ERROR_BACKOFF * attempt);
+        }
+    }
+}
+
+/// REQUESTs go out as a single UDP datagram to a multicast group every
+/// node joins; everything else stays unicast HTTP. Datagrams can be lost,
+/// which the reply-timeout rebroadcasts cover.
+/// Datagrams have no Content-Type, so every node in the group must share a
+/// `--wire-format`.
+pub struct MulticastTransport {
+    http: HttpTransport,
+    group: SocketAddrV4,
+    socket: UdpSocket,
+    format: WireFormat,
+}
+
+impl MulticastTransport {
+    pub fn new(client: Client, group: SocketAddrV4, format: WireFormat, gzip: bool) -> io::Result<Self> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+        socket.set_multicast_loop_v4(true)?;
+        socket.set_multicast_ttl_v4(1)?;
+        Ok(MulticastTransport { http: HttpTransport::new(client).with_format(format).with_gzip(gzip), group, socket, format })
+    }
+}
+
+impl Transport for MulticastTransport {
+    fn send(&self, port: u16, path: &str, payload: &str) -> Result<(), NodeError> {
+        self.http.send(port, path, payload)
+    }
+
+    fn broadcast(&self, payload: &str) -> Result<bool, NodeError> {
+        let datagram = self.format.json_to_wire(payload)?;
+        self.socket.send_to(&datagram, self.group).map(|_| true).map_err(|e| NodeError::Delivery(e.to_string()))
+    }
+
+    fn listen(&self, deliver: Box<dyn Fn(&str) + Send>) -> io::Result<()> {
+        // Several nodes in one process (or on one host) share the port.
+        let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+        socket.set_reuse_address(true)?;
+        #[cfg(unix)]
+        socket.set_reuse_port(true)?;
+        socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, self.group.port())).into())?;
+        socket.join_multicast_v4(self.group.ip(), &Ipv4Addr::UNSPECIFIED)?;
+        let socket: UdpSocket = socket.into();
+        let format = self.format;
+        thread::spawn(move || {
+            let mut buf = [0u8; 64 * 1024];
+            while let Ok(n) = socket.recv(&mut buf) {
+                if let Ok(text) = format.wire_to_json(&buf[..n]) {
+                    deliver(&text);
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
+/// Routes sends between nodes in one process without touching the network.
+/// Each registered port gets an inbox of `(path, payload)` pairs, which its
+/// owner drains on one thread, as the HTTP server would.
+#[derive(Default)]
+pub struct InProcessTransport {
+    inboxes: Mutex<HashMap<u16, mpsc::Sender<(String, String)>>>,
+    sent: AtomicU64,
+}
+
+impl InProcessTransport {
+    pub fn register(&self, port: u16) -> mpsc::Receiver<(String, String)> {
+        let (tx, rx) = mpsc::channel();
+        self.inboxes.lock().unwrap().insert(port, tx);
+        rx
+    }
+
+    /// Messages sent so far, across every node.
+    pub fn sent(&self) -> u64 {
+        self.sent.load(Ordering::Relaxed)
+    }
+}
+
+impl Transport for InProcessTransport {
+    fn send(&self, port: u16, path: &str, payload: &str) -> Result<(), NodeError> {
+        self.sent.fetch_add(1, Ordering::Relaxed);
+        let inboxes = self.inboxes.lock().unwrap();
+        let inbox = inboxes.get(&port).ok_or_else(|| NodeError::Delivery(format!("no node on port {}", port)))?;
+        inbox.send((path.to_string(), payload.to_string())).map_err(|e| NodeError::Delivery(e.to_string()))
+    }
+}
@@ -0,0 +1,118 @@
+//! Optional vector clocks (`--vector-clocks`), kept next to the scalar
+//! Lamport clock. They change nothing about who enters the CS; they only let
+//! the log say whether two REQUESTs were causally ordered or concurrent.
+
+use std::cmp::Ordering;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{LogLevel, Node, RequestMsg};
+
+/// One counter per node, indexed by node id; missing entries are 0.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VectorClock(pub Vec<u64>);
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Causality {
+    Before,
+    After,
+    Equal,
+    Concurrent,
+}
+
+impl VectorClock {
+    fn get(&self, id: usize) -> u64 {
+        self.0.get(id).copied().unwrap_or(0)
+    }
+
+    pub fn tick(&mut self, id: usize) {
+        if self.0.len() <= id {
+            self.0.resize(id + 1, 0);
+        }
+        self.0[id] += 1;
+    }
+
+    pub fn merge(&mut self, other: &VectorClock) {
+        if self.0.len() < other.0.len() {
+            self.0.resize(other.0.len(), 0);
+        }
+        for (mine, theirs) in self.0.iter_mut().zip(&other.0) {
+            *mine = (*mine).max(*theirs);
+        }
+    }
+
+    /// How the event stamped `self` relates to the one stamped `other`.
+    pub fn compare(&self, other: &VectorClock) -> Causality {
+        let (mut less, mut greater) = (false, false);
+        for i in 0..self.0.len().max(other.0.len()) {
+            match self.get(i).cmp(&other.get(i)) {
+                Ordering::Less => less = true,
+                Ordering::Greater => greater = true,
+                Ordering::Equal => {}
+            }
+        }
+        match (less, greater) {
+            (true, true) => Causality::Concurrent,
+            (true, false) => Causality::Before,
+            (false, true) => Causality::After,
+            (false, false) => Causality::Equal,
+        }
+    }
+}
+
+impl Node {
+    /// Ticks our clock for a send and returns the stamp, if enabled.
+    pub fn vclock_send(&self) -> Option<VectorClock> {
+        if !self.vector_clocks {
+            return None;
+        }
+        let mut st = self.state.lock().unwrap();
+        st.vclock.tick(self.id);
+        Some(st.vclock.clone())
+    }
+
+    /// The stamp receiving `stamp` would give us right now, without
+    /// touching our clock.
+    pub fn vclock_on_receipt(&self, stamp: Option<&VectorClock>) -> Option<VectorClock> {
+        let stamp = stamp.filter(|_| self.vector_clocks)?;
+        let mut clock = self.state.lock().unwrap().vclock.clone();
+        clock.merge(stamp);
+        clock.tick(self.id);
+        Some(clock)
+    }
+
+    /// Merges a received stamp into our clock and ticks it.
+    pub fn vclock_receive(&self, stamp: Option<&VectorClock>) {
+        if !self.vector_clocks {
+            return;
+        }
+        let mut st = self.state.lock().unwrap();
+        if let Some(stamp) = stamp {
+            st.vclock.merge(stamp);
+        }
+        st.vclock.tick(self.id);
+    }
+
+    /// Logs how a peer's REQUEST relates to our own outstanding one for the
+    /// same resource, then takes it into our clock.
+    pub fn vclock_observe_request(&self, msg: &RequestMsg) {
+        let Some(theirs) = msg.vclock.as_ref().filter(|_| self.vector_clocks) else { return };
+        let ours = self.state.lock().unwrap().request_vclocks.get(&msg.resource).cloned();
+        if let Some(ours) = ours {
+            let relation = match theirs.compare(&ours) {
+                Causality::Concurrent => "concurrent with",
+                Causality::Before => "causally before",
+                Causality::After => "causally after",
+                Causality::Equal => "identical to",
+            };
+            self.log_at(
+                LogLevel::Debug,
+                &format!(
+                    "REQUEST from {} ts={} vclock={:?} is {} our REQUEST for resource={} vclock={:?}",
+                    msg.from, msg.ts, theirs.0, relation, msg.resource, ours.0
+                ),
+            );
+        }
+        self.vclock_receive(Some(theirs));
+    }
+}
@@ -0,0 +1,141 @@
+use std::{collections::HashMap, fs};
+
+use crate::junit::{self, Case};
+
+/// One parsed `[secs] [Node N] message` log line.
+pub(crate) struct LogLine<'a> {
+    pub line_no: usize,
+    pub secs: u64,
+    pub node: usize,
+    pub msg: &'a str,
+}
+
+pub(crate) fn parse_line(line_no: usize, line: &str) -> Option<LogLine<'_>> {
+    let rest = line.strip_prefix('[')?;
+    let (secs, rest) = rest.split_once("] [Node ")?;
+    let (node, msg) = rest.split_once("] ")?;
+    Some(LogLine { line_no, secs: secs.parse().ok()?, node: node.parse().ok()?, msg })
+}
+
+pub(crate) fn field<'a>(msg: &'a str, key: &str) -> Option<&'a str> {
+    let start = msg.find(key)? + key.len();
+    Some(msg[start..].split_whitespace().next().unwrap_or(""))
+}
+
+struct Holder {
+    node: usize,
+    ts: Option<u64>,
+    read: bool,
+    line_no: usize,
+    secs: u64,
+}
+
+#[derive(Default)]
+struct Run {
+    /// Timestamp of each node's most recent REQUEST per resource.
+    pending: HashMap<(usize, String), u64>,
+    /// Who is inside the CS right now, per resource. More than one only
+    /// when all of them are readers.
+    holders: HashMap<String, Vec<Holder>>,
+    /// `(ts, node, read)` of the last entry per resource.
+    last_entry: HashMap<String, (u64, usize, bool)>,
+    events: usize,
+}
+
+/// Replays a log and returns the first violation, or the number of CS
+/// entries checked. Overlap is judged by line order, which is a total order
+/// because every node appends under the same file lock.
+pub fn verify_log(contents: &str) -> Result<usize, String> {
+    let mut run = Run::default();
+    let mut entries = 0;
+    for (i, raw) in contents.lines().enumerate() {
+        let Some(l) = parse_line(i + 1, raw) else { continue };
+        if l.msg.starts_with("Server started") {
+            if run.events > 0 {
+                run = Run::default();
+            }
+            continue;
+        }
+        run.events += 1;
+        if l.msg.starts_with("Broadcasting REQUEST") {
+            if let (Some(ts), Some(res)) = (field(l.msg, "ts="), field(l.msg, "resource=")) {
+                if let Ok(ts) = ts.parse() {
+                    run.pending.insert((l.node, res.to_string()), ts);
+                }
+            }
+        } else if l.msg.starts_with("Entering Critical Section") {
+            let Some(res) = field(l.msg, "resource=") else { continue };
+            let read = field(l.msg, "mode=") == Some("read");
+            let ts = run.pending.remove(&(l.node, res.to_string()));
+            let held = run.holders.entry(res.to_string()).or_default();
+            if let Some(h) = held.iter().find(|h| !(read && h.read)) {
+                return Err(format!(
+                    "line {}: node {} entered resource={} at {} while node {} (entered line {}, at {}) still held it",
+                    l.line_no, l.node, res, l.secs, h.node, h.line_no, h.secs
+                ));
+            }
+            if let Some(ts) = ts {
+                // Readers admitted together may log their entries in any order.
+                if let Some(&(prev_ts, prev_node, prev_read)) = run.last_entry.get(res) {
+                    if (ts, l.node) < (prev_ts, prev_node) && !(read && prev_read) {
+                        return Err(format!(
+                            "line {}: node {} entered resource={} with ts={} after node {} with ts={}",
+                            l.line_no, l.node, res, ts, prev_node, prev_ts
+                        ));
+                    }
+                }
+                run.last_entry.insert(res.to_string(), (ts, l.node, read));
+            }
+            held.push(Holder { node: l.node, ts, read, line_no: l.line_no, secs: l.secs });
+            entries += 1;
+        } else if l.msg.starts_with("Exiting Critical Section") {
+            let Some(res) = field(l.msg, "resource=") else { continue };
+            let held = run.holders.entry(res.to_string()).or_default();
+            match held.iter().position(|h| h.node == l.node) {
+                Some(i) => {
+                    held.remove(i);
+                }
+                None if !held.is_empty() => {
+                    let h = &held[0];
+                    return Err(format!(
+                        "line {}: node {} exited resource={} held by node {} (ts={:?})",
+                        l.line_no, l.node, res, h.node, h.ts
+                    ));
+                }
+                None => {
+                    return Err(format!("line {}: node {} exited resource={} it never entered", l.line_no, l.node, res));
+                }
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// Entry point for `verify <logfile> [--junit PATH]`; returns the process
+/// exit code.
+pub fn run(path: &str, junit: Option<&str>) -> i32 {
+    let contents = match fs::read_to_string(path) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("cannot read {}: {}", path, e);
+            return 2;
+        }
+    };
+    let verdict = verify_log(&contents);
+    if let Some(out) = junit {
+        let case = Case::new("mutual exclusion held", verdict.clone().map(|_| ()));
+        if let Err(e) = junit::write(out, "verify", &[case]) {
+            eprintln!("cannot write {}: {}", out, e);
+        }
+    }
+    match verdict {
+        Ok(entries) => {
+            println!("OK: {} critical section entries, no violations", entries);
+            0
+        }
+        Err(v) => {
+            println!("VIOLATION: {}", v);
+            1
+        }
+    }
+}
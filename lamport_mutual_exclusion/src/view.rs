@@ -0,0 +1,61 @@
+//! Membership views. Every change to the cluster's membership bumps a
+//! generation that rides on REQUEST, REPLY (single or batched), RELEASE and
+//! CANCEL; a message stamped with an older generation than ours was sent
+//! from a stale view, say by a node rejoining with outdated peers, and is
+//! dropped before it touches the queues.
+//!
+//! The other peer messages carry no generation, on purpose:
+//! - `/register` and `/start` run the start barrier, before any view change
+//!   and before anything is queued.
+//! - `/heartbeat` is how a rejoining node gets seen alive again; dropping
+//!   it would have the node declared dead for being behind.
+//! - `/receive_token` and `/coord_grant` hand over the only record of who
+//!   may enter, so dropping one as stale would lose the resource for every
+//!   node. Those algorithms keep no Lamport queue for a stale view to spoil,
+//!   so `/coord_request` and `/coord_release` skip the check with them.
+//! - `/fast_*` grants are serialized by the leader alone, and a refused or
+//!   unanswered fast request falls back to the checked Lamport messages.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{LogLevel, Node};
+
+/// Body of a `/view` POST, and its answer.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct ViewMsg {
+    pub generation: u64,
+}
+
+impl Node {
+    pub fn generation(&self) -> u64 {
+        self.state.lock().unwrap().generation
+    }
+
+    /// Moves to `generation` if it is newer; a view never goes back.
+    pub fn adopt_view(&self, generation: u64) {
+        let old = {
+            let mut st = self.state.lock().unwrap();
+            if generation <= st.generation {
+                return;
+            }
+            std::mem::replace(&mut st.generation, generation)
+        };
+        self.log(&format!("VIEW generation {} -> {}", old, generation));
+    }
+
+    /// Whether a `kind` message from `from` stamped with `generation` comes
+    /// from an older view and must be dropped. A newer stamp means we missed
+    /// the change, so we adopt it.
+    pub fn is_stale(&self, kind: &str, from: impl std::fmt::Debug, generation: u64) -> bool {
+        let current = self.generation();
+        if generation < current {
+            self.log_at(
+                LogLevel::Info,
+                &format!("STALE_VIEW dropping {} from {:?}: generation {} < {}", kind, from, generation, current),
+            );
+            return true;
+        }
+        self.adopt_view(generation);
+        false
+    }
+}
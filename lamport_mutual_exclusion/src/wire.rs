@@ -0,0 +1,88 @@
+//! `--wire-format`: how messages travel between nodes. Handlers, traces and
+//! logs all work on JSON text; with `msgpack` the HTTP and multicast
+//! transports re-encode each payload as MessagePack on the way out and back
+//! to JSON on the way in. HTTP bodies carry their Content-Type, so a server
+//! accepts either format whatever its own setting.
+
+use std::str::FromStr;
+
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+
+use crate::error::NodeError;
+
+const MSGPACK_CONTENT_TYPE: &str = "application/msgpack";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum WireFormat {
+    #[default]
+    Json,
+    MessagePack,
+}
+
+impl FromStr for WireFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s.to_ascii_lowercase().as_str() {
+            "json" => Ok(WireFormat::Json),
+            "msgpack" | "messagepack" => Ok(WireFormat::MessagePack),
+            _ => Err(()),
+        }
+    }
+}
+
+impl WireFormat {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            WireFormat::Json => "json",
+            WireFormat::MessagePack => "msgpack",
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            WireFormat::Json => "application/json",
+            WireFormat::MessagePack => MSGPACK_CONTENT_TYPE,
+        }
+    }
+
+    /// The format of a body sent with `content_type`; JSON if unsure.
+    pub fn of_content_type(content_type: Option<&str>) -> Self {
+        match content_type {
+            Some(ct) if ct.eq_ignore_ascii_case(MSGPACK_CONTENT_TYPE) => WireFormat::MessagePack,
+            _ => WireFormat::Json,
+        }
+    }
+
+    pub fn encode<T: Serialize>(self, msg: &T) -> Result<Vec<u8>, NodeError> {
+        match self {
+            WireFormat::Json => Ok(serde_json::to_vec(msg)?),
+            // Named fields, so `#[serde(default)]` fields may be left out.
+            WireFormat::MessagePack => rmp_serde::to_vec_named(msg).map_err(|e| NodeError::Wire(e.to_string())),
+        }
+    }
+
+    pub fn decode<T: DeserializeOwned>(self, bytes: &[u8]) -> Result<T, NodeError> {
+        match self {
+            WireFormat::Json => Ok(serde_json::from_slice(bytes)?),
+            WireFormat::MessagePack => rmp_serde::from_slice(bytes).map_err(|e| NodeError::Wire(e.to_string())),
+        }
+    }
+
+    /// A JSON payload as it goes on the wire.
+    pub fn json_to_wire(self, json: &str) -> Result<Vec<u8>, NodeError> {
+        match self {
+            WireFormat::Json => Ok(json.as_bytes().to_vec()),
+            WireFormat::MessagePack => self.encode(&serde_json::from_str::<Value>(json)?),
+        }
+    }
+
+    /// A body off the wire, as the JSON text the handlers take.
+    pub fn wire_to_json(self, bytes: &[u8]) -> Result<String, NodeError> {
+        match self {
+            WireFormat::Json => String::from_utf8(bytes.to_vec()).map_err(|e| NodeError::Wire(e.to_string())),
+            WireFormat::MessagePack => Ok(serde_json::to_string(&self.decode::<Value>(bytes)?)?),
+        }
+    }
+}
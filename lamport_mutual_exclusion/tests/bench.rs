@@ -0,0 +1,36 @@
+use std::{fs, process::Command};
+
+/// Runs `bench args` in its own directory, named after the calling test.
+fn bench(test: &str, args: &[&str]) -> std::process::Output {
+    let dir = std::env::temp_dir().join(format!("lamport-bench-{}-{}", std::process::id(), test));
+    fs::create_dir_all(&dir).unwrap();
+    let out = Command::new(env!("CARGO_BIN_EXE_lamport_mutex")).arg("bench").args(args).current_dir(&dir).output().unwrap();
+    let _ = fs::remove_dir_all(&dir);
+    out
+}
+
+#[test]
+fn bench_prints_one_row_per_size() {
+    let out = bench("rows", &["--sizes", "3,5", "--per-node", "2"]);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert_eq!(out.status.code(), Some(0), "{}{}", stdout, String::from_utf8_lossy(&out.stderr));
+    let rows: Vec<Vec<&str>> = stdout.lines().map(|l| l.split_whitespace().collect()).collect();
+    assert_eq!(rows[0], ["nodes", "cs", "secs", "cs/sec", "msgs/cs"]);
+    assert_eq!(rows.len(), 3, "{}", stdout);
+    // Every entry costs a REQUEST, a REPLY and a RELEASE per peer.
+    for (row, (nodes, cs, msgs_per_cs)) in rows[1..].iter().zip([("3", "6", "6.0"), ("5", "10", "12.0")]) {
+        assert_eq!((row[0], row[1], row[4]), (nodes, cs, msgs_per_cs), "{}", stdout);
+        assert!(row[3].parse::<f64>().unwrap() > 0.0, "{}", stdout);
+    }
+}
+
+#[test]
+fn bench_rejects_bad_arguments() {
+    let out = bench("bad-args", &["--per-node", "1", "--bogus"]);
+    assert_eq!(out.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("unknown argument: --bogus"));
+
+    let out = bench("bad-args", &["--sizes", "3,x"]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("--sizes expects N,N,..., got \"3,x\""));
+}
@@ -0,0 +1,9 @@
+use std::process::Command;
+
+#[test]
+fn self_test_passes() {
+    let out = Command::new(env!("CARGO_BIN_EXE_lamport_mutex")).arg("--self-test").output().unwrap();
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert_eq!(out.status.code(), Some(0), "{}{}", stdout, String::from_utf8_lossy(&out.stderr));
+    assert!(stdout.contains("SELF-TEST OK"), "{}", stdout);
+}
@@ -0,0 +1,48 @@
+#![cfg(unix)]
+
+use std::{
+    fs,
+    net::{TcpListener, TcpStream},
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+#[test]
+fn sigterm_flushes_the_log_frees_the_port_and_exits_0() {
+    let dir = std::env::temp_dir().join(format!("lamport-shutdown-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    // Held together so the two ports differ; node 1 is never started, so
+    // node 0 stays busy waiting for it.
+    let listeners: Vec<TcpListener> = (0..2).map(|_| TcpListener::bind("127.0.0.1:0").unwrap()).collect();
+    let ports: Vec<u16> = listeners.iter().map(|l| l.local_addr().unwrap().port()).collect();
+    drop(listeners);
+    let config = serde_json::json!({ "nodes": [{ "id": 0, "port": ports[0] }, { "id": 1, "port": ports[1] }] });
+    fs::write(dir.join("config.json"), config.to_string()).unwrap();
+
+    let child = Command::new(env!("CARGO_BIN_EXE_lamport_mutex"))
+        .args(["--config", "config.json", "--id", "0", "--log-flush", "on-exit", "--deadline-secs", "60"])
+        .current_dir(&dir)
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while TcpStream::connect(("127.0.0.1", ports[0])).is_err() {
+        assert!(Instant::now() < deadline, "node 0 never came up");
+        thread::sleep(Duration::from_millis(50));
+    }
+    // The handler is installed just after the servers start.
+    thread::sleep(Duration::from_millis(500));
+    let killed = Command::new("kill").args(["-TERM", &child.id().to_string()]).status().unwrap();
+    assert!(killed.success());
+
+    let out = child.wait_with_output().unwrap();
+    let log = fs::read_to_string(dir.join("lamport.log")).unwrap_or_default();
+    let _ = fs::remove_dir_all(&dir);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert_eq!(out.status.code(), Some(0), "{}", stdout);
+    assert!(stdout.contains("Shutdown complete: 1 node(s) stopped"), "{}", stdout);
+    // Buffered until exit, so only there if shutdown flushed it.
+    assert!(log.contains("[Node 0] Shutting down on signal"), "{}", log);
+    assert!(TcpListener::bind(("0.0.0.0", ports[0])).is_ok(), "port {} still held", ports[0]);
+}
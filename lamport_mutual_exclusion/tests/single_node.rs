@@ -0,0 +1,58 @@
+use std::{
+    fs,
+    net::TcpListener,
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+#[test]
+fn id_runs_one_node_on_its_configured_port_against_its_peers() {
+    let root = std::env::temp_dir().join(format!("lamport-single-node-{}", std::process::id()));
+    // Held together so the two ports differ.
+    let listeners: Vec<TcpListener> = (0..2).map(|_| TcpListener::bind("127.0.0.1:0").unwrap()).collect();
+    let ports: Vec<u16> = listeners.iter().map(|l| l.local_addr().unwrap().port()).collect();
+    drop(listeners);
+    let config = serde_json::json!({ "nodes": [{ "id": 0, "port": ports[0] }, { "id": 1, "port": ports[1] }] });
+
+    // One working directory per process, so each writes its own lamport.log.
+    let dirs: Vec<_> = (0..2).map(|id| root.join(id.to_string())).collect();
+    let mut children: Vec<_> = dirs
+        .iter()
+        .enumerate()
+        .map(|(id, dir)| {
+            fs::create_dir_all(dir).unwrap();
+            fs::write(dir.join("config.json"), config.to_string()).unwrap();
+            Command::new(env!("CARGO_BIN_EXE_lamport_mutex"))
+                .args(["--config", "config.json", "--id", &id.to_string(), "--rounds", "1", "--deadline-secs", "60"])
+                .current_dir(dir)
+                .stdout(Stdio::null())
+                .spawn()
+                .unwrap()
+        })
+        .collect();
+    let deadline = Instant::now() + Duration::from_secs(60);
+    let codes: Vec<_> = children
+        .iter_mut()
+        .map(|c| loop {
+            if let Some(status) = c.try_wait().unwrap() {
+                break status.code();
+            }
+            if Instant::now() > deadline {
+                let _ = c.kill();
+            }
+            thread::sleep(Duration::from_millis(100));
+        })
+        .collect();
+    let logs: Vec<String> = dirs.iter().map(|d| fs::read_to_string(d.join("lamport.log")).unwrap_or_default()).collect();
+    let _ = fs::remove_dir_all(&root);
+
+    for (id, log) in logs.iter().enumerate() {
+        let other = 1 - id;
+        assert_eq!(codes[id], Some(0), "node {}:\n{}", id, log);
+        assert!(log.contains(&format!("Server started on 0.0.0.0:{}", ports[id])), "{}", log);
+        assert!(log.contains(&format!("Received REQUEST from {}", other)), "{}", log);
+        assert!(log.contains("Entering Critical Section for resource=A"), "{}", log);
+        assert!(!log.contains(&format!("[Node {}]", other)), "{}", log);
+    }
+}
@@ -0,0 +1,24 @@
+use std::{fs, net::TcpListener, process::Command};
+
+#[test]
+fn watchdog_exits_a_run_stuck_on_a_silent_peer() {
+    let dir = std::env::temp_dir().join(format!("lamport-watchdog-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    // Held together so the two ports differ; node 1 is never started.
+    let listeners: Vec<TcpListener> = (0..2).map(|_| TcpListener::bind("127.0.0.1:0").unwrap()).collect();
+    let ports: Vec<u16> = listeners.iter().map(|l| l.local_addr().unwrap().port()).collect();
+    drop(listeners);
+    let config = serde_json::json!({ "nodes": [{ "id": 0, "port": ports[0] }, { "id": 1, "port": ports[1] }] });
+    let path = dir.join("config.json");
+    fs::write(&path, config.to_string()).unwrap();
+
+    let out = Command::new(env!("CARGO_BIN_EXE_lamport_mutex"))
+        .args(["--config", path.to_str().unwrap(), "--id", "0", "--deadline-secs", "1"])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    let log = fs::read_to_string(dir.join("lamport.log")).unwrap_or_default();
+    let _ = fs::remove_dir_all(&dir);
+    assert_eq!(out.status.code(), Some(3), "{}", String::from_utf8_lossy(&out.stderr));
+    assert!(log.contains("WATCHDOG deadline"), "{}", log);
+}